@@ -0,0 +1,309 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use crate::{config::Config, state::ListeningSocket};
+
+pub const USAGE: &str = "\
+Usage: aurora [OPTIONS] [-- COMMAND [ARGS...]]
+
+Options:
+  --backend <winit|headless|udev>  Backend to run Aurora with (default: winit)
+  --socket <name>                  Name of the wayland socket (default: first free wayland-N)
+  --config <path>                  Path to the configuration file
+                                   (default: $XDG_CONFIG_HOME/aurora/config.toml)
+  --log-file <path>                Write logs to this file instead of stderr
+  -h, --help                       Print this help
+
+Everything after `--` is launched as a client once the wayland socket is ready.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Nested inside a host desktop, only for debuging and development
+    Winit,
+    /// Virtual outputs rendered offscreen, for CI and automated tests
+    Headless,
+    /// Directly on the hardware through DRM/KMS and libinput
+    Udev,
+}
+
+impl BackendKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Winit => "winit",
+            BackendKind::Headless => "headless",
+            BackendKind::Udev => "udev",
+        }
+    }
+
+    /// Whether the cargo feature of this backend was enabled at build time.
+    pub fn is_compiled_in(&self) -> bool {
+        match self {
+            BackendKind::Winit => cfg!(feature = "winit"),
+            BackendKind::Headless => cfg!(feature = "headless"),
+            BackendKind::Udev => cfg!(feature = "udev"),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "winit" => Ok(BackendKind::Winit),
+            "headless" => Ok(BackendKind::Headless),
+            "udev" => Ok(BackendKind::Udev),
+            other => Err(CliError::UnknownBackend(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("unknown backend `{0}`, expected one of: winit, headless, udev")]
+    UnknownBackend(String),
+    #[error("Aurora was built without the `{0}` backend, rebuild it with `--features {0}`")]
+    BackendNotCompiled(BackendKind),
+    #[error("missing value for `{0}`")]
+    MissingValue(String),
+    #[error("`{0}` does not take a value")]
+    UnexpectedValue(String),
+    #[error("unknown argument `{0}`")]
+    UnknownArgument(String),
+    #[error("help requested")]
+    Help,
+}
+
+/*
+Command line arguments of the aurora binary.
+*/
+#[derive(Debug, Clone)]
+pub struct CliArgs {
+    pub backend: BackendKind,
+    pub socket_name: Option<String>,
+    pub config_path: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    /// Command (and its arguments) to launch once the wayland socket is ready.
+    pub command: Vec<String>,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        CliArgs {
+            backend: BackendKind::Winit,
+            socket_name: None,
+            config_path: None,
+            log_file: None,
+            command: Vec::new(),
+        }
+    }
+}
+
+impl CliArgs {
+    /*
+    Parses the arguments (without the program name).
+    Both `--option value` and `--option=value` forms are accepted.
+    */
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut cli = CliArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                cli.command = args.collect();
+                break;
+            }
+
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| CliError::MissingValue(flag.clone()))
+            };
+            // Flags without a value must not silently drop one given with `=`.
+            let no_value = || match inline_value {
+                Some(_) => Err(CliError::UnexpectedValue(flag.clone())),
+                None => Ok(()),
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => {
+                    no_value()?;
+                    return Err(CliError::Help);
+                }
+                "--backend" => cli.backend = value()?.parse()?,
+                "--socket" => cli.socket_name = Some(value()?),
+                "--config" => cli.config_path = Some(value()?.into()),
+                "--log-file" => cli.log_file = Some(value()?.into()),
+                // The headless backend was first started with `--headless`, scripts may still use it.
+                "--headless" => {
+                    no_value()?;
+                    cli.backend = BackendKind::Headless;
+                }
+                _ => return Err(CliError::UnknownArgument(flag.clone())),
+            }
+        }
+
+        if !cli.backend.is_compiled_in() {
+            return Err(CliError::BackendNotCompiled(cli.backend));
+        }
+
+        Ok(cli)
+    }
+
+    /*
+    The socket given on the command line, falling back to the one from the config file.
+    */
+    pub fn listening_socket(&self, config: &Config) -> ListeningSocket {
+        match self.socket_name.as_ref().or(config.socket.as_ref()) {
+            Some(name) => ListeningSocket::Named(name.clone()),
+            None => ListeningSocket::Auto,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, CliError> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    #[cfg(feature = "headless")]
+    fn every_flag_is_parsed() {
+        let cli = parse(&[
+            "--backend",
+            "headless",
+            "--socket",
+            "wayland-7",
+            "--config",
+            "/tmp/aurora.toml",
+            "--log-file",
+            "aurora.log",
+        ])
+        .unwrap();
+        assert_eq!(cli.backend, BackendKind::Headless);
+        assert_eq!(cli.socket_name.as_deref(), Some("wayland-7"));
+        assert_eq!(cli.config_path, Some(PathBuf::from("/tmp/aurora.toml")));
+        assert_eq!(cli.log_file, Some(PathBuf::from("aurora.log")));
+        assert!(cli.command.is_empty());
+
+        assert_eq!(parse(&["--headless"]).unwrap().backend, BackendKind::Headless);
+    }
+
+    #[test]
+    #[cfg(feature = "headless")]
+    fn values_can_follow_an_equal_sign() {
+        let cli = parse(&[
+            "--backend=headless",
+            "--socket=wayland-7",
+            "--config=/tmp/a=b.toml",
+            "--log-file=aurora.log",
+        ])
+        .unwrap();
+        assert_eq!(cli.backend, BackendKind::Headless);
+        assert_eq!(cli.socket_name.as_deref(), Some("wayland-7"));
+        // Only the first `=` separates the value.
+        assert_eq!(cli.config_path, Some(PathBuf::from("/tmp/a=b.toml")));
+        assert_eq!(cli.log_file, Some(PathBuf::from("aurora.log")));
+    }
+
+    #[test]
+    #[cfg(feature = "headless")]
+    fn everything_after_the_separator_is_the_command() {
+        let cli = parse(&["--backend", "headless", "--", "foot", "--config", "--", "-e", "htop"]).unwrap();
+        assert_eq!(cli.backend, BackendKind::Headless);
+        assert_eq!(cli.config_path, None);
+        assert_eq!(cli.command, ["foot", "--config", "--", "-e", "htop"]);
+    }
+
+    #[test]
+    fn help_is_requested() {
+        assert!(matches!(parse(&["--help"]), Err(CliError::Help)));
+        assert!(matches!(parse(&["--socket", "wayland-7", "-h"]), Err(CliError::Help)));
+    }
+
+    #[test]
+    fn missing_values_are_reported() {
+        for flag in ["--backend", "--socket", "--config", "--log-file"] {
+            match parse(&[flag]) {
+                Err(CliError::MissingValue(missing)) => assert_eq!(missing, flag),
+                other => panic!("{} without a value gave {:?}", flag, other),
+            }
+        }
+    }
+
+    #[test]
+    fn flags_without_a_value_reject_one() {
+        for arg in ["--headless=foo", "--help=x"] {
+            match parse(&[arg]) {
+                Err(CliError::UnexpectedValue(flag)) => assert_eq!(Some(flag.as_str()), arg.split('=').next()),
+                other => panic!("{} gave {:?}", arg, other),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_arguments_and_backends_are_rejected() {
+        assert!(matches!(
+            parse(&["--backend", "wayland"]),
+            Err(CliError::UnknownBackend(backend)) if backend == "wayland"
+        ));
+        assert!(matches!(
+            parse(&["--backend=tty-udev"]),
+            Err(CliError::UnknownBackend(backend)) if backend == "tty-udev"
+        ));
+        assert!(matches!(
+            parse(&["--winit"]),
+            Err(CliError::UnknownArgument(arg)) if arg == "--winit"
+        ));
+        assert!(matches!(
+            parse(&["weston-terminal"]),
+            Err(CliError::UnknownArgument(arg)) if arg == "weston-terminal"
+        ));
+    }
+
+    #[test]
+    fn backends_have_to_be_compiled_in() {
+        for backend in [BackendKind::Winit, BackendKind::Headless, BackendKind::Udev] {
+            let result = parse(&["--backend", backend.name()]);
+            if backend.is_compiled_in() {
+                assert_eq!(result.unwrap().backend, backend);
+            } else {
+                assert!(matches!(result, Err(CliError::BackendNotCompiled(missing)) if missing == backend));
+            }
+        }
+    }
+
+    #[test]
+    fn the_command_line_socket_wins_over_the_config() {
+        let config = Config {
+            socket: Some("wayland-config".into()),
+            ..Config::default()
+        };
+        let cli = CliArgs {
+            socket_name: Some("wayland-cli".into()),
+            ..CliArgs::default()
+        };
+        assert!(matches!(cli.listening_socket(&config), ListeningSocket::Named(name) if name == "wayland-cli"));
+        assert!(matches!(
+            CliArgs::default().listening_socket(&config),
+            ListeningSocket::Named(name) if name == "wayland-config"
+        ));
+        assert!(matches!(
+            CliArgs::default().listening_socket(&Config::default()),
+            ListeningSocket::Auto
+        ));
+    }
+}
@@ -0,0 +1,936 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+use smithay::{
+    input::keyboard::{Keysym, XkbConfig},
+    output::{Output, Scale},
+    reexports::calloop::timer::{TimeoutAction, Timer},
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as DecorationMode,
+    utils::{Logical, Point, Transform},
+};
+use xkbcommon::xkb;
+
+use crate::{
+    input_handler::{KeyAction, KeyBinding, KeyBindings, Modifiers},
+    state::{AuroraState, Backend},
+    window_manager::{MasterStack, WindowManager, Workspaces, MAX_WORKSPACES},
+};
+
+/// How often the config file is checked for modifications
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("invalid keyboard layout (rules: `{rules}`, model: `{model}`, layout: `{layout}`, variant: `{variant}`, options: `{options}`)")]
+    InvalidKeymap {
+        rules: String,
+        model: String,
+        layout: String,
+        variant: String,
+        options: String,
+    },
+    #[error("keyboard repeat rate must not be negative (0 turns repeat off) and delay must be positive, got rate {rate} and delay {delay}")]
+    InvalidRepeat { rate: i32, delay: i32 },
+    #[error("invalid scale {scale} for output `{output}`, expected a value between 0.25 and 8")]
+    InvalidScale { output: String, scale: f64 },
+    #[error("invalid refresh rate {refresh} for output `{output}`, expected a positive value in Hz")]
+    InvalidRefresh { output: String, refresh: f64 },
+    #[error("invalid gesture {field} {value}, expected a positive value")]
+    InvalidGesture { field: &'static str, value: f64 },
+    #[error("invalid key binding `{keys}`: {reason}")]
+    InvalidBinding { keys: String, reason: String },
+    #[error("invalid window rule #{index}: {reason}")]
+    InvalidWindowRule { index: usize, reason: String },
+}
+
+/*
+Aurora's configuration, read from a TOML file.
+
+```toml
+[keyboard]
+layout = "us,de"
+options = "grp:alt_shift_toggle"
+repeat_delay = 300
+repeat_rate = 30
+
+[outputs.eDP-1]
+scale = 1.5
+transform = "90"
+position = [0, 0]
+
+[[bindings]]
+keys = "Logo+Return"
+action = { run = "foot" }
+
+[window]
+decoration_mode = "server"
+layout = "tiling"
+
+[[window.rules]]
+app_id = "org.gnome.Calculator"
+layout = "floating"
+size = [360, 520]
+```
+
+Every section is optional, missing values fall back to Aurora's defaults.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Name of the wayland socket, the `--socket` argument takes precedence.
+    pub socket: Option<String>,
+    pub keyboard: KeyboardConfig,
+    pub input: InputConfig,
+    pub gestures: GestureConfig,
+    /// Per-output settings, keyed by output name (eg. `eDP-1`, `winit` or `headless`).
+    pub outputs: HashMap<String, OutputConfig>,
+    /// Extra key bindings, overriding the default ones with the same keys.
+    /// Binding a key combination to `"none"` removes the default binding.
+    pub bindings: Vec<BindingConfig>,
+    pub window: WindowConfig,
+    /// File this config is loaded from, watched for live reloading. It may not exist yet.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyboardConfig {
+    pub rules: String,
+    pub model: String,
+    pub layout: String,
+    pub variant: String,
+    pub options: Option<String>,
+    /// Delay before a held key starts repeating, in milliseconds
+    pub repeat_delay: i32,
+    /// Repeated key presses per second, 0 turns key repeat off
+    pub repeat_rate: i32,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        KeyboardConfig {
+            rules: String::new(),
+            model: String::new(),
+            layout: String::new(),
+            variant: String::new(),
+            options: None,
+            repeat_delay: 200,
+            repeat_rate: 25,
+        }
+    }
+}
+
+impl KeyboardConfig {
+    pub fn xkb_config(&self) -> XkbConfig<'_> {
+        XkbConfig {
+            rules: &self.rules,
+            model: &self.model,
+            layout: &self.layout,
+            variant: &self.variant,
+            options: self.options.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Output touchscreens are mapped to, defaults to the internal panel (`eDP-*`) or the first output
+    pub touch_output: Option<String>,
+    /// Turn mouse drags into touch events in the winit backend, Ctrl adds a second finger
+    pub touch_emulation: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GestureConfig {
+    /// Whether the compositor recognises navigation gestures at all
+    pub enabled: bool,
+    /// Width of the edge zones swipes have to start in, in logical pixels
+    pub edge_size: f64,
+    /// Distance a swipe has to travel to be recognised, in logical pixels
+    pub distance: f64,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            enabled: true,
+            edge_size: 24.0,
+            distance: 80.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub scale: Option<f64>,
+    pub transform: Option<TransformConfig>,
+    /// Position of the top-left corner in the global compositor space
+    pub position: Option<(i32, i32)>,
+    /// Refresh rate in Hz, only used by virtual outputs (winit and headless)
+    pub refresh: Option<f64>,
+}
+
+impl OutputConfig {
+    pub fn position(&self) -> Option<Point<i32, Logical>> {
+        self.position.map(Point::from)
+    }
+
+    /// Refresh rate in mHz, as used by `smithay::output::Mode`
+    pub fn refresh_mhz(&self) -> Option<i32> {
+        self.refresh.map(|refresh| (refresh * 1000.0).round() as i32)
+    }
+
+    /*
+    Applies scale, transform and position to the output.
+    Settings that are not configured are left untouched.
+    */
+    pub fn apply(&self, output: &Output) {
+        output.change_current_state(
+            None,
+            self.transform.map(Transform::from),
+            self.scale.map(Scale::Fractional),
+            self.position(),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TransformConfig {
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "90")]
+    _90,
+    #[serde(rename = "180")]
+    _180,
+    #[serde(rename = "270")]
+    _270,
+    #[serde(rename = "flipped")]
+    Flipped,
+    #[serde(rename = "flipped-90")]
+    Flipped90,
+    #[serde(rename = "flipped-180")]
+    Flipped180,
+    #[serde(rename = "flipped-270")]
+    Flipped270,
+}
+
+impl From<TransformConfig> for Transform {
+    fn from(transform: TransformConfig) -> Self {
+        match transform {
+            TransformConfig::Normal => Transform::Normal,
+            TransformConfig::_90 => Transform::_90,
+            TransformConfig::_180 => Transform::_180,
+            TransformConfig::_270 => Transform::_270,
+            TransformConfig::Flipped => Transform::Flipped,
+            TransformConfig::Flipped90 => Transform::Flipped90,
+            TransformConfig::Flipped180 => Transform::Flipped180,
+            TransformConfig::Flipped270 => Transform::Flipped270,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BindingConfig {
+    /// Modifiers and a keysym name joined by `+`, eg. `Logo+Shift+m` or `Ctrl+Alt+BackSpace`
+    pub keys: String,
+    pub action: KeyAction,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    /// Decoration mode windows get unless they ask for a specific one
+    pub decoration_mode: DecorationModeConfig,
+    /// App ids that are never suspended in the background (eg. music or navigation apps)
+    pub run_in_background: Vec<String>,
+    /// Window management policy
+    pub layout: LayoutConfig,
+    /// Rules for specific apps, see `WindowRule`
+    pub rules: Vec<WindowRule>,
+}
+
+impl WindowConfig {
+    pub fn runs_in_background(&self, app_id: &str) -> bool {
+        self.run_in_background.iter().any(|id| id == app_id)
+    }
+
+    /*
+    Merges every rule matching a window into one, later rules override what earlier ones set.
+    Windows no rule matches get the default, empty rule.
+    */
+    pub fn rule_for(&self, app_id: Option<&str>, title: Option<&str>) -> WindowRule {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(app_id, title))
+            .fold(WindowRule::default(), |merged, rule| WindowRule {
+                app_id: None,
+                title: None,
+                layout: rule.layout.or(merged.layout),
+                output: rule.output.clone().or(merged.output),
+                workspace: rule.workspace.or(merged.workspace),
+                size: rule.size.or(merged.size),
+                opacity: rule.opacity.or(merged.opacity),
+                decoration_mode: rule.decoration_mode.or(merged.decoration_mode),
+                no_focus: rule.no_focus.or(merged.no_focus),
+            })
+    }
+}
+
+/*
+How windows of a given app are handled.
+
+A rule matches on the xdg app id and title of a window, `*` standing for any text.
+A rule without `app_id` or `title` matches every window. Unset fields leave the
+window as the window manager and the client want it.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowRule {
+    /// App id to match, eg. `org.gnome.Calculator` or `org.gnome.*`
+    pub app_id: Option<String>,
+    /// Title to match, eg. `*Picture-in-Picture*`
+    pub title: Option<String>,
+    pub layout: Option<WindowLayoutRule>,
+    /// Name of the output the window opens on
+    pub output: Option<String>,
+    /// Workspace the window opens on, counted from 0 like the `screen` action
+    pub workspace: Option<usize>,
+    /// Size the window opens with, in logical pixels
+    pub size: Option<(i32, i32)>,
+    /// Opacity the window is drawn with, from 0 to 1
+    pub opacity: Option<f32>,
+    /// Decoration mode the window gets, whatever its client asks for
+    pub decoration_mode: Option<DecorationModeConfig>,
+    /// The window never takes the focus by itself, when it opens or asks to be activated
+    pub no_focus: Option<bool>,
+}
+
+impl WindowRule {
+    pub fn matches(&self, app_id: Option<&str>, title: Option<&str>) -> bool {
+        let matches = |pattern: &Option<String>, value: Option<&str>| match pattern {
+            Some(pattern) => value.map(|value| glob_match(pattern, value)).unwrap_or(false),
+            None => true,
+        };
+        matches(&self.app_id, app_id) && matches(&self.title, title)
+    }
+
+    pub fn floating(&self) -> bool {
+        self.layout == Some(WindowLayoutRule::Floating)
+    }
+
+    pub fn no_focus(&self) -> bool {
+        self.no_focus.unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowLayoutRule {
+    Fullscreen,
+    Maximized,
+    /// Placed freely, even in tablet mode or with the tiling layout
+    Floating,
+}
+
+/*
+Matches a text against a pattern where `*` stands for any text, including none.
+*/
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all, the text has to be the same.
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutConfig {
+    /// Workspaces of fullscreen stacks, see `Workspaces`
+    #[default]
+    Stack,
+    /// Master and stack tiling, see `MasterStack`
+    Tiling,
+}
+
+impl LayoutConfig {
+    pub fn window_manager(&self) -> Box<dyn WindowManager> {
+        match self {
+            LayoutConfig::Stack => Box::new(Workspaces::new()),
+            LayoutConfig::Tiling => Box::new(MasterStack::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecorationModeConfig {
+    #[default]
+    Client,
+    Server,
+}
+
+impl From<DecorationModeConfig> for DecorationMode {
+    fn from(mode: DecorationModeConfig) -> Self {
+        match mode {
+            DecorationModeConfig::Client => DecorationMode::ClientSide,
+            DecorationModeConfig::Server => DecorationMode::ServerSide,
+        }
+    }
+}
+
+/*
+Parses a key combination like `Logo+Shift+m` into its modifiers and keysym.
+*/
+pub fn parse_key_combo(keys: &str) -> Result<(Modifiers, Keysym), String> {
+    let mut parts = keys.split('+').map(str::trim).collect::<Vec<_>>();
+    let keysym_name = parts.pop().filter(|name| !name.is_empty()).ok_or("missing key")?;
+
+    let mut modifiers = Modifiers::empty();
+    for part in parts {
+        modifiers |= match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CTRL,
+            "alt" | "mod1" => Modifiers::ALT,
+            "shift" => Modifiers::SHIFT,
+            "logo" | "super" | "mod4" => Modifiers::LOGO,
+            _ => return Err(format!("unknown modifier `{}`", part)),
+        };
+    }
+
+    let mut keysym = xkb::keysym_from_name(keysym_name, xkb::KEYSYM_NO_FLAGS);
+    if keysym == Keysym::NoSymbol {
+        keysym = xkb::keysym_from_name(keysym_name, xkb::KEYSYM_CASE_INSENSITIVE);
+    }
+    if keysym == Keysym::NoSymbol {
+        return Err(format!("unknown key `{}`", keysym_name));
+    }
+
+    Ok((modifiers, keysym))
+}
+
+impl Config {
+    /*
+    The config file used when none is given on the command line:
+    `$XDG_CONFIG_HOME/aurora/config.toml`, falling back to `~/.config/aurora/config.toml`.
+    */
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("aurora").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut config = Config::parse(&content)?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    pub fn parse(content: &str) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let keyboard = &self.keyboard;
+        // A rate of 0 is how wl_keyboard says keys do not repeat.
+        if keyboard.repeat_rate < 0 || keyboard.repeat_delay <= 0 {
+            return Err(ConfigError::InvalidRepeat {
+                rate: keyboard.repeat_rate,
+                delay: keyboard.repeat_delay,
+            });
+        }
+
+        // Compile the keymap once, so typos in layouts are reported instead of silently ignored.
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            &keyboard.rules,
+            &keyboard.model,
+            &keyboard.layout,
+            &keyboard.variant,
+            keyboard.options.clone(),
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        );
+        if keymap.is_none() {
+            return Err(ConfigError::InvalidKeymap {
+                rules: keyboard.rules.clone(),
+                model: keyboard.model.clone(),
+                layout: keyboard.layout.clone(),
+                variant: keyboard.variant.clone(),
+                options: keyboard.options.clone().unwrap_or_default(),
+            });
+        }
+
+        for (field, value) in [
+            ("edge_size", self.gestures.edge_size),
+            ("distance", self.gestures.distance),
+        ] {
+            if value <= 0.0 || !value.is_finite() {
+                return Err(ConfigError::InvalidGesture { field, value });
+            }
+        }
+
+        for (name, output) in self.outputs.iter() {
+            if let Some(scale) = output.scale {
+                if !(0.25..=8.0).contains(&scale) {
+                    return Err(ConfigError::InvalidScale {
+                        output: name.clone(),
+                        scale,
+                    });
+                }
+            }
+            if let Some(refresh) = output.refresh {
+                if refresh <= 0.0 || !refresh.is_finite() {
+                    return Err(ConfigError::InvalidRefresh {
+                        output: name.clone(),
+                        refresh,
+                    });
+                }
+            }
+        }
+
+        for binding in self.bindings.iter() {
+            parse_key_combo(&binding.keys).map_err(|reason| ConfigError::InvalidBinding {
+                keys: binding.keys.clone(),
+                reason,
+            })?;
+        }
+
+        for (index, rule) in self.window.rules.iter().enumerate() {
+            let invalid = |reason: &str| ConfigError::InvalidWindowRule {
+                index: index + 1,
+                reason: reason.to_string(),
+            };
+            if let Some(opacity) = rule.opacity {
+                if !(0.0..=1.0).contains(&opacity) {
+                    return Err(invalid("opacity must be between 0 and 1"));
+                }
+            }
+            if let Some((width, height)) = rule.size {
+                if width <= 0 || height <= 0 {
+                    return Err(invalid("size must be positive"));
+                }
+            }
+            if rule.workspace.is_some_and(|workspace| workspace >= MAX_WORKSPACES) {
+                return Err(invalid(&format!("workspace must be below {}", MAX_WORKSPACES)));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn output(&self, name: &str) -> Option<&OutputConfig> {
+        self.outputs.get(name)
+    }
+
+    /*
+    The default key bindings with the configured ones applied on top.
+    */
+    pub fn key_bindings(&self) -> KeyBindings {
+        let mut bindings = KeyBindings::default();
+
+        for binding in self.bindings.iter() {
+            let Ok((modifiers, keysym)) = parse_key_combo(&binding.keys) else {
+                continue;
+            };
+            bindings
+                .0
+                .retain(|b| !(b.modifiers == modifiers && b.keysym == keysym));
+            if binding.action != KeyAction::None {
+                bindings
+                    .0
+                    .push(KeyBinding::new(modifiers, keysym, binding.action.clone()));
+            }
+        }
+
+        bindings
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl<BackendData: Backend + 'static> AuroraState<BackendData> {
+    /*
+    Polls the config file from the event loop and reloads it whenever it is modified.
+    Polling the modification time keeps working when editors replace the file instead of writing into it,
+    and picks up a file that did not exist yet once it is created.
+    */
+    pub fn watch_config(&self) {
+        let Some(path) = self.config.path.clone() else {
+            return;
+        };
+
+        tracing::info!(path = %path.display(), "Watching config file");
+        let mut last_modified = modified_time(&path);
+        self.handle
+            .insert_source(Timer::from_duration(CONFIG_POLL_INTERVAL), move |_, _, state| {
+                let modified = modified_time(&path);
+                if modified != last_modified {
+                    last_modified = modified;
+                    state.reload_config();
+                }
+                TimeoutAction::ToDuration(CONFIG_POLL_INTERVAL)
+            })
+            .expect("Failed to init config watcher");
+    }
+
+    /*
+    Reads the config file again and applies it in place.
+    An invalid file is reported and the current config stays active.
+    */
+    pub fn reload_config(&mut self) {
+        let Some(path) = self.config.path.clone() else {
+            return;
+        };
+
+        match Config::load(&path) {
+            Ok(config) => {
+                tracing::info!(path = %path.display(), "Reloading configuration");
+                self.apply_config(config);
+            }
+            Err(err) => tracing::error!("Failed to reload configuration, keeping the previous one: {}", err),
+        }
+    }
+
+    /*
+    Switches to a new config, updating the keyboard, outputs and bindings that changed.
+    Output settings removed from the config keep their current value.
+    */
+    pub fn apply_config(&mut self, config: Config) {
+        if config == self.config {
+            return;
+        }
+
+        if config.socket != self.config.socket {
+            tracing::warn!("The wayland socket can not be changed at runtime, restart Aurora to apply it");
+        }
+
+        if config.keyboard != self.config.keyboard {
+            let keyboard = self.seat.get_keyboard().unwrap();
+            match keyboard.set_xkb_config(self, config.keyboard.xkb_config()) {
+                Ok(()) => tracing::info!("Updated keymap"),
+                Err(err) => tracing::error!("Failed to update keymap: {:?}", err),
+            }
+            keyboard.change_repeat_info(config.keyboard.repeat_rate, config.keyboard.repeat_delay);
+        }
+
+        let mut changed_outputs = Vec::new();
+        for output in self.space.outputs().cloned().collect::<Vec<_>>() {
+            let name = output.name();
+            let Some(output_config) = config.output(&name) else {
+                continue;
+            };
+            if self.config.output(&name) == Some(output_config) {
+                continue;
+            }
+
+            tracing::info!(output = name, ?output_config, "Updating output");
+            output_config.apply(&output);
+            self.backend_data.reset_buffers(&output);
+            changed_outputs.push(output);
+        }
+
+        // The running apps are handed over to the new policy.
+        if config.window.layout != self.config.window.layout {
+            tracing::info!(layout = ?config.window.layout, "Switching window management policy");
+            self.set_window_manager(config.window.layout.window_manager());
+        }
+
+        let rules_changed = config.window.rules != self.config.window.rules;
+
+        // Swapped in one go, so a key press never sees a mix of old and new bindings.
+        self.key_bindings = config.key_bindings();
+        self.config = config;
+
+        if rules_changed {
+            let windows = self.window_manager.windows().cloned().collect::<Vec<_>>();
+            for window in windows {
+                self.apply_window_rules(&window);
+            }
+        }
+
+        if !changed_outputs.is_empty() {
+            let pointer_location = self.pointer.current_location();
+            crate::shell::fixup_positions(&mut self.space, &self.config, pointer_location);
+            for output in &changed_outputs {
+                self.window_manager.output_resized(output, &mut self.space);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_empty_file_gives_the_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn every_section_is_parsed() {
+        let config = Config::parse(
+            r#"
+socket = "wayland-7"
+
+[keyboard]
+layout = "us,de"
+options = "grp:alt_shift_toggle"
+repeat_delay = 300
+repeat_rate = 30
+
+[outputs.eDP-1]
+scale = 1.5
+transform = "flipped-90"
+position = [1920, 0]
+refresh = 59.95
+
+[[bindings]]
+keys = "Logo+Return"
+action = { run = "foot" }
+
+[[bindings]]
+keys = "Logo+q"
+action = "none"
+
+[window]
+decoration_mode = "server"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.socket.as_deref(), Some("wayland-7"));
+        assert_eq!(config.keyboard.layout, "us,de");
+        assert_eq!(config.keyboard.options.as_deref(), Some("grp:alt_shift_toggle"));
+        assert_eq!((config.keyboard.repeat_delay, config.keyboard.repeat_rate), (300, 30));
+        // Unset values keep their default.
+        assert_eq!(config.keyboard.rules, "");
+
+        let output = config.output("eDP-1").unwrap();
+        assert_eq!(output.scale, Some(1.5));
+        assert_eq!(output.transform.map(Transform::from), Some(Transform::Flipped90));
+        assert_eq!(output.position(), Some(Point::from((1920, 0))));
+        assert_eq!(output.refresh_mhz(), Some(59_950));
+        assert!(config.output("HDMI-A-1").is_none());
+
+        assert_eq!(config.bindings.len(), 2);
+        assert_eq!(config.bindings[0].action, KeyAction::Run("foot".into()));
+        assert_eq!(config.bindings[1].action, KeyAction::None);
+        assert_eq!(config.window.decoration_mode, DecorationModeConfig::Server);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for content in [
+            "[keybaord]\nlayout = \"us\"\n",
+            "[keyboard]\nrepeat_speed = 30\n",
+            "[outputs.eDP-1]\nscal = 2.0\n",
+            "[[bindings]]\nkeys = \"Logo+q\"\naction = \"quit\"\nmode = \"locked\"\n",
+            "[window]\ndecorations = \"server\"\n",
+        ] {
+            assert!(matches!(Config::parse(content), Err(ConfigError::Parse(_))), "{}", content);
+        }
+        // Values of the wrong type or out of an enum are parse errors as well.
+        for content in [
+            "[keyboard]\nrepeat_rate = \"fast\"\n",
+            "[outputs.eDP-1]\ntransform = \"45\"\n",
+            "[window]\ndecoration_mode = \"none\"\n",
+        ] {
+            assert!(matches!(Config::parse(content), Err(ConfigError::Parse(_))), "{}", content);
+        }
+    }
+
+    #[test]
+    fn key_repeat_can_be_turned_off() {
+        let config = Config::parse("[keyboard]\nrepeat_rate = 0\n").unwrap();
+        assert_eq!(config.keyboard.repeat_rate, 0);
+
+        for content in ["[keyboard]\nrepeat_rate = -1\n", "[keyboard]\nrepeat_delay = 0\n"] {
+            assert!(matches!(Config::parse(content), Err(ConfigError::InvalidRepeat { .. })), "{}", content);
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        assert!(matches!(
+            Config::parse("[keyboard]\nlayout = \"not-a-layout\"\n"),
+            Err(ConfigError::InvalidKeymap { layout, .. }) if layout == "not-a-layout"
+        ));
+        assert!(matches!(
+            Config::parse("[outputs.eDP-1]\nscale = 10.0\n"),
+            Err(ConfigError::InvalidScale { output, .. }) if output == "eDP-1"
+        ));
+        assert!(matches!(
+            Config::parse("[outputs.headless]\nrefresh = 0.0\n"),
+            Err(ConfigError::InvalidRefresh { output, .. }) if output == "headless"
+        ));
+        assert!(matches!(
+            Config::parse("[gestures]\ndistance = 0.0\n"),
+            Err(ConfigError::InvalidGesture { field: "distance", .. })
+        ));
+        assert!(matches!(
+            Config::parse("[[bindings]]\nkeys = \"Hyper+q\"\naction = \"quit\"\n"),
+            Err(ConfigError::InvalidBinding { keys, .. }) if keys == "Hyper+q"
+        ));
+    }
+
+    #[test]
+    fn key_combos_are_parsed() {
+        assert_eq!(
+            parse_key_combo("Logo+Shift+m"),
+            Ok((Modifiers::LOGO | Modifiers::SHIFT, Keysym::m))
+        );
+        // Modifiers and key names are case insensitive, spaces around `+` are allowed.
+        assert_eq!(
+            parse_key_combo("ctrl + ALT + backspace"),
+            Ok((Modifiers::CTRL | Modifiers::ALT, Keysym::BackSpace))
+        );
+        assert_eq!(parse_key_combo("Return"), Ok((Modifiers::empty(), Keysym::Return)));
+        assert!(parse_key_combo("Logo+").is_err());
+        assert!(parse_key_combo("Logo+nokey").is_err());
+        assert!(parse_key_combo("Hyper+q").is_err());
+    }
+
+    #[test]
+    fn glob_without_star_matches_the_whole_text() {
+        assert!(glob_match("firefox", "firefox"));
+        assert!(!glob_match("firefox", "firefox-esr"));
+        assert!(!glob_match("firefox", "org.firefox"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "firefox"));
+    }
+
+    #[test]
+    fn glob_star_matches_any_text() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        // Prefix and suffix
+        assert!(glob_match("org.gnome.*", "org.gnome.Calculator"));
+        assert!(glob_match("org.gnome.*", "org.gnome."));
+        assert!(!glob_match("org.gnome.*", "org.kde.Calculator"));
+        assert!(glob_match("*- Private Browsing", "Tabs - Private Browsing"));
+        assert!(!glob_match("*- Private Browsing", "Private Browsing - Tabs"));
+        // In the middle, and several of them
+        assert!(glob_match("org.*.Calculator", "org.gnome.Calculator"));
+        assert!(!glob_match("org.*.Calculator", "org.gnome.Calendar"));
+        assert!(glob_match("*Picture*Picture*", "Picture-in-Picture"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(!glob_match("a*b*c", "acb"));
+        // The end of the pattern may not reuse text matched before it.
+        assert!(!glob_match("ab*b", "ab"));
+        assert!(glob_match("ab*b", "abb"));
+    }
+
+    #[test]
+    fn rules_match_on_app_id_and_title() {
+        let rule = WindowRule {
+            app_id: Some("org.mozilla.*".into()),
+            title: Some("*Private*".into()),
+            ..WindowRule::default()
+        };
+        assert!(rule.matches(Some("org.mozilla.firefox"), Some("Private Browsing")));
+        assert!(!rule.matches(Some("org.mozilla.firefox"), Some("Mozilla Firefox")));
+        assert!(!rule.matches(None, Some("Private Browsing")));
+        // Without matchers a rule applies to every window.
+        assert!(WindowRule::default().matches(None, None));
+    }
+
+    #[test]
+    fn later_rules_override_earlier_ones() {
+        let window = WindowConfig {
+            rules: vec![
+                WindowRule {
+                    layout: Some(WindowLayoutRule::Floating),
+                    opacity: Some(0.5),
+                    no_focus: Some(true),
+                    ..WindowRule::default()
+                },
+                WindowRule {
+                    app_id: Some("org.gnome.Calculator".into()),
+                    opacity: Some(0.9),
+                    size: Some((360, 520)),
+                    ..WindowRule::default()
+                },
+                WindowRule {
+                    app_id: Some("org.gnome.Calculator".into()),
+                    no_focus: Some(false),
+                    ..WindowRule::default()
+                },
+                WindowRule {
+                    app_id: Some("org.gnome.Terminal".into()),
+                    layout: Some(WindowLayoutRule::Maximized),
+                    ..WindowRule::default()
+                },
+            ],
+            ..WindowConfig::default()
+        };
+
+        let rule = window.rule_for(Some("org.gnome.Calculator"), Some("Calculator"));
+        assert_eq!(
+            rule,
+            WindowRule {
+                layout: Some(WindowLayoutRule::Floating),
+                size: Some((360, 520)),
+                opacity: Some(0.9),
+                no_focus: Some(false),
+                ..WindowRule::default()
+            }
+        );
+        // The merged rule does not keep any matcher.
+        assert_eq!(rule.app_id, None);
+
+        let rule = window.rule_for(Some("org.gnome.Terminal"), None);
+        assert_eq!(rule.layout, Some(WindowLayoutRule::Maximized));
+        assert_eq!(rule.opacity, Some(0.5));
+        assert!(rule.no_focus());
+
+        assert_eq!(WindowConfig::default().rule_for(Some("org.gnome.Calculator"), None), WindowRule::default());
+    }
+
+    #[test]
+    fn rules_with_an_unknown_workspace_are_rejected() {
+        let config = Config::parse("[[window.rules]]\napp_id = \"foot\"\nworkspace = 9\n");
+        assert!(matches!(config, Err(ConfigError::InvalidWindowRule { index: 1, .. })));
+
+        let config = Config::parse("[[window.rules]]\napp_id = \"foot\"\nworkspace = 8\n").unwrap();
+        assert_eq!(config.window.rules[0].workspace, Some(8));
+    }
+}
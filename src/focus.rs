@@ -30,6 +30,13 @@ impl From<WindowElement> for KeyboardFocusTarget {
     }
 }
 
+impl From<PopupKind> for KeyboardFocusTarget {
+    #[inline]
+    fn from(p: PopupKind) -> Self {
+        KeyboardFocusTarget::Popup(p)
+    }
+}
+
 impl<BackendData: Backend> KeyboardTarget<AuroraState<BackendData>> for KeyboardFocusTarget {
     fn enter(
         &self,
@@ -125,6 +132,17 @@ pub enum PointerFocusTarget {
     WlSurface(WlSurface)
 }
 
+impl From<KeyboardFocusTarget> for PointerFocusTarget {
+    #[inline]
+    fn from(target: KeyboardFocusTarget) -> Self {
+        match target {
+            KeyboardFocusTarget::Window(w) => PointerFocusTarget::WlSurface(w.toplevel().unwrap().wl_surface().clone()),
+            KeyboardFocusTarget::LayerSurface(l) => PointerFocusTarget::WlSurface(l.wl_surface().clone()),
+            KeyboardFocusTarget::Popup(p) => PointerFocusTarget::WlSurface(p.wl_surface().clone()),
+        }
+    }
+}
+
 impl IsAlive for PointerFocusTarget {
     #[inline]
     fn alive(&self) -> bool {
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+
+use smithay::{
+    backend::input::TouchSlot,
+    utils::{Logical, Point, Rectangle},
+};
+
+use crate::{config::GestureConfig, window_manager::SwitchDirection};
+
+/// Number of fingers of the app switching swipe
+const SWIPE_FINGERS: usize = 3;
+/// Number of fingers of the workspace switching swipe
+const WORKSPACE_SWIPE_FINGERS: usize = 4;
+
+/*
+System navigation gestures recognised by the compositor.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    /// Swipe up from the bottom edge
+    Home,
+    /// Swipe in from the left or right edge
+    Back,
+    /// Horizontal three finger swipe
+    SwitchApp(SwitchDirection),
+    /// Horizontal four finger swipe, `Next` goes to the workspace on the right
+    SwitchWorkspace(SwitchDirection),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    Left,
+    Right,
+    Bottom,
+}
+
+impl Edge {
+    /*
+    How far a point moved away from this edge, towards the center of the output.
+    */
+    fn inward_distance(&self, from: Point<f64, Logical>, to: Point<f64, Logical>) -> f64 {
+        match self {
+            Edge::Left => to.x - from.x,
+            Edge::Right => from.x - to.x,
+            Edge::Bottom => from.y - to.y,
+        }
+    }
+
+    fn gesture(&self) -> Gesture {
+        match self {
+            Edge::Left | Edge::Right => Gesture::Back,
+            Edge::Bottom => Gesture::Home,
+        }
+    }
+}
+
+/*
+What the input handler should do with a touch event after the recognizer saw it.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchFilter {
+    /// Not part of a gesture, forward it to clients
+    Forward,
+    /// Part of a recognised gesture, clients must not see it
+    Intercept,
+    /// A gesture was just recognised, the client's touch sequence has to be cancelled
+    Recognized(Gesture),
+}
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Idle,
+    /// A single finger started in an edge zone. It is forwarded to clients, so taps near
+    /// the edges still work, until it travels far enough to become an edge swipe.
+    Edge {
+        slot: TouchSlot,
+        edge: Edge,
+        start: Point<f64, Logical>,
+        current: Point<f64, Logical>,
+    },
+    /// Touch points forwarded to clients, watched for a multi finger swipe (start and current location)
+    Forwarding {
+        points: HashMap<TouchSlot, (Point<f64, Logical>, Point<f64, Logical>)>,
+    },
+    /// The sequence was claimed by a gesture, everything is intercepted until all fingers are up
+    Claimed { slots: Vec<TouchSlot> },
+}
+
+/*
+Touch gesture state machine sitting in front of the touch forwarding.
+
+Every touch sequence (from the first finger down to the last finger up) is forwarded
+to clients while it is watched for an edge swipe, when it begins in an edge zone, or
+for a multi finger swipe. A recognised gesture claims the rest of the sequence, and
+clients get a cancel for what they already saw.
+*/
+#[derive(Debug, Default)]
+pub struct GestureRecognizer {
+    state: State,
+}
+
+impl GestureRecognizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn down(
+        &mut self,
+        config: &GestureConfig,
+        slot: TouchSlot,
+        location: Point<f64, Logical>,
+        output_geometry: Option<Rectangle<i32, Logical>>,
+    ) -> TouchFilter {
+        match &mut self.state {
+            State::Idle => {
+                let edge = output_geometry
+                    .filter(|_| config.enabled)
+                    .and_then(|geo| edge_at(config, geo, location));
+                match edge {
+                    Some(edge) => {
+                        self.state = State::Edge {
+                            slot,
+                            edge,
+                            start: location,
+                            current: location,
+                        };
+                        TouchFilter::Forward
+                    }
+                    None => {
+                        self.state = State::Forwarding {
+                            points: HashMap::from([(slot, (location, location))]),
+                        };
+                        TouchFilter::Forward
+                    }
+                }
+            }
+            State::Edge {
+                slot: edge_slot,
+                start,
+                current,
+                ..
+            } => {
+                // More fingers are no edge swipe anymore, but may still become a multi finger swipe.
+                self.state = State::Forwarding {
+                    points: HashMap::from([(*edge_slot, (*start, *current)), (slot, (location, location))]),
+                };
+                TouchFilter::Forward
+            }
+            State::Forwarding { points } => {
+                points.insert(slot, (location, location));
+                TouchFilter::Forward
+            }
+            State::Claimed { slots } => {
+                slots.push(slot);
+                TouchFilter::Intercept
+            }
+        }
+    }
+
+    pub fn motion(&mut self, config: &GestureConfig, slot: TouchSlot, location: Point<f64, Logical>) -> TouchFilter {
+        match &mut self.state {
+            State::Idle => TouchFilter::Forward,
+            State::Edge {
+                slot: edge_slot,
+                edge,
+                start,
+                current,
+            } => {
+                if *edge_slot != slot {
+                    return TouchFilter::Forward;
+                }
+                *current = location;
+                if edge.inward_distance(*start, location) < config.distance {
+                    return TouchFilter::Forward;
+                }
+
+                let gesture = edge.gesture();
+                self.state = State::Claimed { slots: vec![slot] };
+                TouchFilter::Recognized(gesture)
+            }
+            State::Forwarding { points } => {
+                if let Some(point) = points.get_mut(&slot) {
+                    point.1 = location;
+                }
+                let fingers = points.len();
+                if !config.enabled || (fingers != SWIPE_FINGERS && fingers != WORKSPACE_SWIPE_FINGERS) {
+                    return TouchFilter::Forward;
+                }
+
+                // All fingers have to move the same way, so the average movement is used.
+                let (dx, dy) = points.values().fold((0.0, 0.0), |(dx, dy), (start, current)| {
+                    (dx + current.x - start.x, dy + current.y - start.y)
+                });
+                let (dx, dy) = (dx / fingers as f64, dy / fingers as f64);
+                if dx.abs() < config.distance || dx.abs() < dy.abs() {
+                    return TouchFilter::Forward;
+                }
+
+                let direction = if dx < 0.0 {
+                    SwitchDirection::Next
+                } else {
+                    SwitchDirection::Previous
+                };
+                self.state = State::Claimed {
+                    slots: points.keys().copied().collect(),
+                };
+                if fingers == WORKSPACE_SWIPE_FINGERS {
+                    TouchFilter::Recognized(Gesture::SwitchWorkspace(direction))
+                } else {
+                    TouchFilter::Recognized(Gesture::SwitchApp(direction))
+                }
+            }
+            State::Claimed { .. } => TouchFilter::Intercept,
+        }
+    }
+
+    pub fn up(&mut self, slot: TouchSlot) -> TouchFilter {
+        match &mut self.state {
+            State::Idle => TouchFilter::Forward,
+            State::Edge { slot: edge_slot, .. } => {
+                // Lifted before travelling far enough, it was a tap or a drag the client handles.
+                if *edge_slot == slot {
+                    self.state = State::Idle;
+                }
+                TouchFilter::Forward
+            }
+            State::Forwarding { points } => {
+                points.remove(&slot);
+                if points.is_empty() {
+                    self.state = State::Idle;
+                }
+                TouchFilter::Forward
+            }
+            State::Claimed { slots } => {
+                slots.retain(|s| *s != slot);
+                if slots.is_empty() {
+                    self.state = State::Idle;
+                }
+                TouchFilter::Intercept
+            }
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.state = State::Idle;
+    }
+}
+
+fn edge_at(
+    config: &GestureConfig,
+    output_geometry: Rectangle<i32, Logical>,
+    location: Point<f64, Logical>,
+) -> Option<Edge> {
+    let geo = output_geometry.to_f64();
+    let local = location - geo.loc;
+
+    if local.y >= geo.size.h - config.edge_size {
+        Some(Edge::Bottom)
+    } else if local.x <= config.edge_size {
+        Some(Edge::Left)
+    } else if local.x >= geo.size.w - config.edge_size {
+        Some(Edge::Right)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(id: u32) -> TouchSlot {
+        Some(id).into()
+    }
+
+    fn point(x: f64, y: f64) -> Point<f64, Logical> {
+        (x, y).into()
+    }
+
+    fn output() -> Option<Rectangle<i32, Logical>> {
+        Some(Rectangle::from_loc_and_size((0, 0), (1000, 800)))
+    }
+
+    /*
+    Puts fingers down at the given locations, moves each of them by `delta` one after
+    the other and returns what the recognizer made of every motion.
+    */
+    fn swipe(
+        recognizer: &mut GestureRecognizer,
+        config: &GestureConfig,
+        fingers: &[Point<f64, Logical>],
+        delta: Point<f64, Logical>,
+    ) -> Vec<TouchFilter> {
+        for (id, location) in fingers.iter().enumerate() {
+            assert_eq!(
+                recognizer.down(config, slot(id as u32), *location, output()),
+                TouchFilter::Forward
+            );
+        }
+        fingers
+            .iter()
+            .enumerate()
+            .map(|(id, location)| recognizer.motion(config, slot(id as u32), *location + delta))
+            .collect()
+    }
+
+    #[test]
+    fn edge_swipes_are_recognised() {
+        let config = GestureConfig::default();
+        for (start, end, gesture) in [
+            (point(500.0, 795.0), point(500.0, 700.0), Gesture::Home),
+            (point(5.0, 400.0), point(100.0, 400.0), Gesture::Back),
+            (point(995.0, 400.0), point(900.0, 400.0), Gesture::Back),
+        ] {
+            let mut recognizer = GestureRecognizer::new();
+            assert_eq!(recognizer.down(&config, slot(0), start, output()), TouchFilter::Forward);
+            // Not far enough yet, the client still sees the finger.
+            let halfway = start + (end - start).downscale(4.0);
+            assert_eq!(recognizer.motion(&config, slot(0), halfway), TouchFilter::Forward);
+            assert_eq!(
+                recognizer.motion(&config, slot(0), end),
+                TouchFilter::Recognized(gesture)
+            );
+            assert_eq!(recognizer.motion(&config, slot(0), end), TouchFilter::Intercept);
+            assert_eq!(recognizer.up(slot(0)), TouchFilter::Intercept);
+        }
+    }
+
+    #[test]
+    fn edge_taps_reach_clients() {
+        let config = GestureConfig::default();
+        let mut recognizer = GestureRecognizer::new();
+        assert_eq!(
+            recognizer.down(&config, slot(0), point(5.0, 400.0), output()),
+            TouchFilter::Forward
+        );
+        assert_eq!(recognizer.motion(&config, slot(0), point(8.0, 402.0)), TouchFilter::Forward);
+        assert_eq!(recognizer.up(slot(0)), TouchFilter::Forward);
+
+        // Moving towards the edge is no swipe either.
+        assert_eq!(
+            recognizer.down(&config, slot(0), point(500.0, 790.0), output()),
+            TouchFilter::Forward
+        );
+        assert_eq!(recognizer.motion(&config, slot(0), point(500.0, 900.0)), TouchFilter::Forward);
+        assert_eq!(recognizer.up(slot(0)), TouchFilter::Forward);
+
+        // The next sequence starts from scratch.
+        assert_eq!(
+            recognizer.down(&config, slot(1), point(5.0, 400.0), output()),
+            TouchFilter::Forward
+        );
+        assert_eq!(
+            recognizer.motion(&config, slot(1), point(100.0, 400.0)),
+            TouchFilter::Recognized(Gesture::Back)
+        );
+    }
+
+    #[test]
+    fn three_finger_swipes_switch_apps() {
+        let config = GestureConfig::default();
+        let fingers = [point(400.0, 400.0), point(500.0, 400.0), point(600.0, 400.0)];
+
+        let mut recognizer = GestureRecognizer::new();
+        let filters = swipe(&mut recognizer, &config, &fingers, point(-100.0, 10.0));
+        assert_eq!(
+            filters,
+            [
+                TouchFilter::Forward,
+                TouchFilter::Forward,
+                TouchFilter::Recognized(Gesture::SwitchApp(SwitchDirection::Next)),
+            ]
+        );
+        for id in 0..3 {
+            assert_eq!(recognizer.up(slot(id)), TouchFilter::Intercept);
+        }
+
+        let mut recognizer = GestureRecognizer::new();
+        let filters = swipe(&mut recognizer, &config, &fingers, point(100.0, 0.0));
+        assert_eq!(
+            filters.last(),
+            Some(&TouchFilter::Recognized(Gesture::SwitchApp(SwitchDirection::Previous)))
+        );
+
+        // Vertical swipes are left to clients, eg. for scrolling.
+        let mut recognizer = GestureRecognizer::new();
+        let filters = swipe(&mut recognizer, &config, &fingers, point(90.0, 200.0));
+        assert!(filters.iter().all(|filter| *filter == TouchFilter::Forward));
+    }
+
+    #[test]
+    fn four_finger_swipes_switch_workspaces() {
+        let config = GestureConfig::default();
+        let fingers = [
+            point(300.0, 400.0),
+            point(400.0, 400.0),
+            point(500.0, 400.0),
+            point(600.0, 400.0),
+        ];
+
+        let mut recognizer = GestureRecognizer::new();
+        let filters = swipe(&mut recognizer, &config, &fingers, point(-100.0, 0.0));
+        assert_eq!(
+            filters.last(),
+            Some(&TouchFilter::Recognized(Gesture::SwitchWorkspace(SwitchDirection::Next)))
+        );
+
+        let mut recognizer = GestureRecognizer::new();
+        let filters = swipe(&mut recognizer, &config, &fingers, point(100.0, 0.0));
+        assert_eq!(
+            filters.last(),
+            Some(&TouchFilter::Recognized(Gesture::SwitchWorkspace(
+                SwitchDirection::Previous
+            )))
+        );
+    }
+
+    #[test]
+    fn a_second_finger_stops_the_edge_swipe() {
+        let config = GestureConfig::default();
+        let mut recognizer = GestureRecognizer::new();
+        assert_eq!(
+            recognizer.down(&config, slot(0), point(5.0, 400.0), output()),
+            TouchFilter::Forward
+        );
+        assert_eq!(
+            recognizer.down(&config, slot(1), point(500.0, 400.0), output()),
+            TouchFilter::Forward
+        );
+        // A pinch starting at the edge stays with the client.
+        assert_eq!(recognizer.motion(&config, slot(0), point(200.0, 400.0)), TouchFilter::Forward);
+        assert_eq!(recognizer.motion(&config, slot(1), point(450.0, 400.0)), TouchFilter::Forward);
+
+        // With a third finger, the sequence can still be an app switching swipe.
+        assert_eq!(
+            recognizer.down(&config, slot(2), point(600.0, 400.0), output()),
+            TouchFilter::Forward
+        );
+        recognizer.motion(&config, slot(0), point(100.0, 400.0));
+        recognizer.motion(&config, slot(1), point(350.0, 400.0));
+        assert_eq!(
+            recognizer.motion(&config, slot(2), point(400.0, 400.0)),
+            TouchFilter::Recognized(Gesture::SwitchApp(SwitchDirection::Next))
+        );
+    }
+
+    #[test]
+    fn disabled_gestures_forward_everything() {
+        let config = GestureConfig {
+            enabled: false,
+            ..GestureConfig::default()
+        };
+
+        let mut recognizer = GestureRecognizer::new();
+        assert_eq!(
+            recognizer.down(&config, slot(0), point(500.0, 795.0), output()),
+            TouchFilter::Forward
+        );
+        assert_eq!(recognizer.motion(&config, slot(0), point(500.0, 500.0)), TouchFilter::Forward);
+        assert_eq!(recognizer.up(slot(0)), TouchFilter::Forward);
+
+        let fingers = [point(400.0, 400.0), point(500.0, 400.0), point(600.0, 400.0)];
+        let filters = swipe(&mut recognizer, &config, &fingers, point(-200.0, 0.0));
+        assert!(filters.iter().all(|filter| *filter == TouchFilter::Forward));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{sync::atomic::Ordering, time::Duration};
+
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::OutputDamageTracker,
+            pixman::{PixmanError, PixmanRenderer},
+            Bind, DebugFlags, ImportMemWl, Offscreen, Renderer,
+        },
+    },
+    input::keyboard::LedState,
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            EventLoop,
+        },
+        pixman::Image,
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::{protocol::wl_surface, Display},
+    },
+    utils::{Physical, Size, Transform},
+    wayland::presentation::Refresh,
+};
+
+use crate::{cli::CliArgs, config::Config, renderer::{render_output, CustomRenderElements}, snapshot::update_background_snapshots, state::{take_presentation_feedback, AuroraState, Backend, InitError}};
+
+pub const OUTPUT_NAME: &str = "headless";
+
+/*
+A virtual output of the headless backend.
+Each output renders into its own offscreen pixman image, which can be inspected
+by tests (eg. to take screenshots) through `HeadlessData::output_buffer`.
+*/
+pub struct HeadlessOutput {
+    pub output: Output,
+    pub buffer: Image<'static, 'static>,
+    damage_tracker: OutputDamageTracker,
+    full_redraw: u8,
+}
+
+pub struct HeadlessData {
+    renderer: PixmanRenderer,
+    outputs: Vec<HeadlessOutput>,
+}
+
+impl HeadlessData {
+    pub fn new() -> Result<Self, PixmanError> {
+        Ok(HeadlessData {
+            renderer: PixmanRenderer::new()?,
+            outputs: Vec::new(),
+        })
+    }
+
+    pub fn renderer(&mut self) -> &mut PixmanRenderer {
+        &mut self.renderer
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &HeadlessOutput> {
+        self.outputs.iter()
+    }
+
+    /*
+    Returns the offscreen buffer the given output was last rendered into.
+    */
+    pub fn output_buffer(&self, output: &Output) -> Option<&Image<'static, 'static>> {
+        self.outputs
+            .iter()
+            .find(|o| &o.output == output)
+            .map(|o| &o.buffer)
+    }
+}
+
+impl Backend for HeadlessData {
+    fn seat_name(&self) -> String {
+        String::from("headless")
+    }
+    fn reset_buffers(&mut self, output: &Output) {
+        if let Some(output) = self.outputs.iter_mut().find(|o| &o.output == output) {
+            output.full_redraw = 4;
+        }
+    }
+    fn early_import(&mut self, _surface: &wl_surface::WlSurface) {}
+    fn update_led_state(&mut self, _led_state: LedState) {}
+    fn change_vt(&mut self, _vt: i32) {}
+    fn debug_flags(&self) -> DebugFlags {
+        self.renderer.debug_flags()
+    }
+    fn set_debug_flags(&mut self, flags: DebugFlags) {
+        self.renderer.set_debug_flags(flags);
+    }
+}
+
+impl AuroraState<HeadlessData> {
+    /*
+    Creates a new virtual output, advertises it to clients and maps it into the space
+    at its configured position, or right of all existing outputs.
+    */
+    pub fn add_virtual_output(
+        &mut self,
+        name: &str,
+        size: Size<i32, Physical>,
+        refresh: i32,
+    ) -> Result<Output, PixmanError> {
+        let output_config = self.config.output(name).cloned().unwrap_or_default();
+        let refresh = output_config.refresh_mhz().unwrap_or(refresh);
+        let mode = Mode { size, refresh };
+        let output = Output::new(
+            name.to_string(),
+            PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: "Smithay".into(),
+                model: "Headless".into(),
+            },
+        );
+        let _global = output.create_global::<AuroraState<HeadlessData>>(&self.display_handle);
+        output.change_current_state(Some(mode), Some(Transform::Normal), None, None);
+        output_config.apply(&output);
+        output.set_preferred(mode);
+
+        // Without a configured position, place the output to the right of every existing one.
+        let position = output_config.position().unwrap_or_else(|| {
+            let x = self
+                .space
+                .outputs()
+                .filter_map(|o| self.space.output_geometry(o))
+                .map(|geo| geo.loc.x + geo.size.w)
+                .max()
+                .unwrap_or(0);
+            (x, 0).into()
+        });
+        self.space.map_output(&output, position);
+        self.window_manager.output_added(&output, &mut self.space);
+
+        let buffer: Image<'static, 'static> = self
+            .backend_data
+            .renderer
+            .create_buffer(Fourcc::Argb8888, size.to_logical(1).to_buffer(1, Transform::Normal))?;
+
+        self.backend_data.outputs.push(HeadlessOutput {
+            damage_tracker: OutputDamageTracker::from_output(&output),
+            output: output.clone(),
+            buffer,
+            full_redraw: 0,
+        });
+
+        tracing::info!(name, ?size, "Added virtual output");
+        Ok(output)
+    }
+
+    /*
+    Renders one frame for every virtual output into its offscreen buffer, following the
+    same `pre_repaint` -> render -> `post_repaint` flow as the winit backend.
+    */
+    pub fn render_headless(&mut self) {
+        for index in 0..self.backend_data.outputs.len() {
+            let output = self.backend_data.outputs[index].output.clone();
+            let frame_duration = output
+                .current_mode()
+                .map(|mode| Duration::from_secs_f64(1_000f64 / mode.refresh as f64))
+                .unwrap_or_default();
+            let frame_target = self.clock.now() + frame_duration;
+            self.pre_repaint(&output, frame_target);
+
+            let backend = &mut self.backend_data;
+            let headless_output = &mut backend.outputs[index];
+
+            headless_output.full_redraw = headless_output.full_redraw.saturating_sub(1);
+            // The offscreen buffer keeps its content between frames, so it always has an age of 1
+            // unless a full redraw was requested.
+            let age = if headless_output.full_redraw > 0 { 0 } else { 1 };
+
+            if let Err(err) = backend.renderer.bind(headless_output.buffer.clone()) {
+                tracing::warn!(output = output.name(), "Failed to bind offscreen buffer: {}", err);
+                continue;
+            }
+
+            update_background_snapshots(&mut backend.renderer, &self.space, &*self.window_manager, &output);
+
+            let elements: Vec<CustomRenderElements<PixmanRenderer>> = Vec::<CustomRenderElements<PixmanRenderer>>::new();
+            let render_res = render_output(
+                &output,
+                &self.space,
+                elements,
+                &mut backend.renderer,
+                &mut headless_output.damage_tracker,
+                age,
+                self.overview.as_ref(),
+            );
+
+            match render_res {
+                Ok(render_output_result) => {
+                    let states = render_output_result.states;
+                    if render_output_result.damage.is_some() {
+                        let mut output_presentation_feedback = take_presentation_feedback(&output, &self.space, &states);
+                        output_presentation_feedback.presented(
+                            frame_target,
+                            Refresh::fixed(frame_duration),
+                            0,
+                            wp_presentation_feedback::Kind::empty(),
+                        );
+                    }
+
+                    // Send frame events so that client start drawing their next frame
+                    self.post_repaint(&output, frame_target, None, &states);
+                }
+                Err(err) => tracing::warn!(output = output.name(), "Rendering error: {:?}", err),
+            }
+        }
+    }
+}
+
+pub fn run_headless(args: &CliArgs, config: Config) -> Result<(), InitError> {
+    tracing::info!("Running with headless backend");
+
+    let mut event_loop = EventLoop::try_new().unwrap();
+    let display = Display::new().unwrap();
+    let mut display_handle = display.handle();
+
+    let data = match HeadlessData::new() {
+        Ok(data) => data,
+        Err(err) => {
+            tracing::error!("Failed to initialize pixman renderer: {}", err);
+            return Ok(());
+        }
+    };
+
+    let listening_socket = args.listening_socket(&config);
+    let mut state = AuroraState::init(display, event_loop.handle(), data, listening_socket, config)?;
+    state
+        .shm_state
+        .update_formats(state.backend_data.renderer.shm_formats());
+
+    if let Err(err) = state.add_virtual_output(OUTPUT_NAME, (1920, 1080).into(), 60_000) {
+        tracing::error!("Failed to create virtual output: {}", err);
+        return Ok(());
+    }
+
+    // Drive repaints from a timer, since there is no vblank to wait for.
+    let frame_duration = Duration::from_micros(16_667);
+    event_loop
+        .handle()
+        .insert_source(Timer::immediate(), move |_, _, state| {
+            state.render_headless();
+            TimeoutAction::ToDuration(frame_duration)
+        })
+        .expect("Failed to init repaint timer");
+
+    // The socket is ready, launch the requested client.
+    state.spawn(&args.command);
+
+    tracing::info!("Initialization completed, starting the main loop.");
+
+    while state.running.load(Ordering::SeqCst) {
+        let result = event_loop.dispatch(Some(frame_duration), &mut state);
+        if result.is_err() {
+            state.running.store(false, Ordering::SeqCst);
+        } else {
+            state.space.refresh();
+            state.popups.cleanup();
+            state.update_background_states();
+            display_handle.flush_clients().unwrap();
+        }
+    }
+
+    Ok(())
+}
@@ -1,17 +1,45 @@
-use crate::{state::Backend, AuroraState};
+use std::sync::atomic::Ordering;
+
+use crate::{
+    focus::{KeyboardFocusTarget, PointerFocusTarget},
+    gestures::{Gesture, TouchFilter},
+    overview::OverviewKey,
+    shell::{PointerDividerGrab, PointerPipGrab, TouchDividerGrab, TouchPipGrab, WindowElement},
+    state::Backend,
+    AuroraState,
+};
 
 use smithay::{
-    backend::input::{
-        Event, InputBackend, InputEvent, KeyboardKeyEvent,
+    backend::{
+        input::{
+            AbsolutePositionEvent, Axis, AxisSource, ButtonState, Event, InputBackend, InputEvent, KeyState,
+            KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent, Switch, SwitchState,
+            SwitchToggleEvent, TouchEvent, TouchSlot,
+        },
+        renderer::DebugFlags,
+    },
+    desktop::{layer_map_for_output, LayerSurface, PopupUngrabStrategy, WindowSurfaceType},
+    input::{
+        keyboard::{keysyms as xkb, FilterResult, Keysym, KeysymHandle, ModifiersState},
+        pointer::{
+            AxisFrame, ButtonEvent, Focus, GrabStartData as PointerGrabStartData, MotionEvent, RelativeMotionEvent,
+        },
+        touch::{DownEvent, GrabStartData as TouchGrabStartData, MotionEvent as TouchMotionEvent, UpEvent},
+    },
+    output::{Output, Scale},
+    reexports::{
+        wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1, wayland_server::Resource,
+    },
+    utils::{IsAlive, Logical, Point, Serial, Transform, SERIAL_COUNTER},
+    wayland::{
+        keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitorSeat, seat::WaylandFocus,
+        shell::wlr_layer::Layer as WlrLayer,
     },
-    input::keyboard::FilterResult,
-
-    utils::SERIAL_COUNTER,
 };
 
 
 impl <BackendData: Backend> AuroraState<BackendData> {
-    fn keyboard_key_to_action<B: InputBackend>(&mut self, evt: B::KeyboardKeyEvent) {
+    fn keyboard_key_to_action<B: InputBackend>(&mut self, evt: B::KeyboardKeyEvent) -> KeyAction {
         let keycode = evt.key_code();
         let state = evt.state();
         tracing::debug!(?keycode, ?state, "key");
@@ -20,50 +48,1010 @@ impl <BackendData: Backend> AuroraState<BackendData> {
         let time = Event::time_msec(&evt);
 
         let keyboard = self.seat.get_keyboard().unwrap();
-        keyboard.input(self, keycode, state, serial, time, |_, _modifiers, _handle| {
-            FilterResult::Forward
-        }).unwrap_or(KeyAction::None);
+
+        // Clients holding an active shortcuts inhibitor (eg. remote desktop or VM viewers) get every key.
+        let inhibited = keyboard
+            .current_focus()
+            .and_then(|focus| focus.wl_surface().map(|surface| surface.into_owned()))
+            .and_then(|surface| self.seat.keyboard_shortcuts_inhibitor_for_surface(&surface))
+            .map(|inhibitor| inhibitor.is_active())
+            .unwrap_or(false);
+
+        keyboard.input(self, keycode, state, serial, time, |data, modifiers, handle| {
+            if state == KeyState::Pressed {
+                if inhibited {
+                    return FilterResult::Forward;
+                }
+
+                match data.key_bindings.action_for(modifiers, &handle) {
+                    Some(action) => {
+                        // Remember the key, so its release is hidden from the client as well.
+                        data.suppressed_keys.push(keycode);
+                        FilterResult::Intercept(action)
+                    }
+                    // Keys do not reach any client while the overview is open.
+                    None if data.overview.is_some() => {
+                        data.suppressed_keys.push(keycode);
+                        let action = OverviewKey::from_keysym(handle.modified_sym())
+                            .map(KeyAction::Overview)
+                            .unwrap_or(KeyAction::None);
+                        FilterResult::Intercept(action)
+                    }
+                    None => FilterResult::Forward,
+                }
+            } else if data.suppressed_keys.contains(&keycode) {
+                data.suppressed_keys.retain(|k| *k != keycode);
+                FilterResult::Intercept(KeyAction::None)
+            } else {
+                FilterResult::Forward
+            }
+        }).unwrap_or(KeyAction::None)
     }
 
-    pub fn process_input_event_windowed<B: InputBackend>(&mut self, event: InputEvent<B>, _output_name: &str) {
+    pub fn process_input_event_windowed<B: InputBackend>(&mut self, event: InputEvent<B>, output_name: &str) {
         match event {
-            InputEvent::PointerButton { event } => {
-                // TODO: Implement this event.
+            InputEvent::Keyboard { event } => {
+                self.focus_active_window();
+                let action = self.keyboard_key_to_action::<B>(event);
+                self.execute_action(action);
             },
 
+            InputEvent::PointerMotionAbsolute { event } => {
+                let output = self
+                    .space
+                    .outputs()
+                    .find(|o| o.name() == output_name)
+                    .cloned();
+                if let Some(output_geo) = output.and_then(|o| self.space.output_geometry(&o)) {
+                    let location = event.position_transformed(output_geo.size) + output_geo.loc.to_f64();
+                    self.on_pointer_motion_absolute(location, event.time_msec());
+                }
+            },
+
+            InputEvent::PointerButton { event } => self.on_pointer_button::<B>(event),
+
+            InputEvent::PointerAxis { event } => self.on_pointer_axis::<B>(event),
+
+            InputEvent::TouchDown { event } => {
+                if let Some(location) = self.touch_location_windowed(&event, output_name) {
+                    self.on_touch_down(event.slot(), location, event.time_msec());
+                }
+            },
+            InputEvent::TouchMotion { event } => {
+                if let Some(location) = self.touch_location_windowed(&event, output_name) {
+                    self.on_touch_motion(event.slot(), location, event.time_msec());
+                }
+            },
+            InputEvent::TouchUp { event } => self.on_touch_up(event.slot(), event.time_msec()),
+            InputEvent::TouchFrame { event: _ } => self.on_touch_frame(),
+            InputEvent::TouchCancel { event: _ } => self.on_touch_cancel(),
+
+            _ => (),
+        }
+    }
+
+    /*
+    Handles input events coming from a real seat (eg. libinput on a TTY), where there is
+    no host window and the events are not tied to a single output.
+    */
+    pub fn process_input_event<B: InputBackend>(&mut self, event: InputEvent<B>) {
+        match event {
             InputEvent::Keyboard { event } => {
-                // Add keyboard focus to active window
-                let keyboard = self.seat.get_keyboard().unwrap();
+                self.focus_active_window();
+                let action = self.keyboard_key_to_action::<B>(event);
+                self.execute_action(action);
+            },
 
-                if !self.window_manager.is_empty() {
-                    let active_window = self.window_manager.get_active_window().unwrap().clone();
-                    keyboard.set_focus(self, Some(active_window.into()), SERIAL_COUNTER.next_serial());
-                    self.keyboard_key_to_action::<B>(event)
-                };
+            InputEvent::PointerMotion { event } => self.on_pointer_motion::<B>(event),
+
+            InputEvent::PointerMotionAbsolute { event } => {
+                // Absolute devices (eg. tablets in absolute mode) span the bounding box of all outputs.
+                let bounding_box = self
+                    .space
+                    .outputs()
+                    .filter_map(|o| self.space.output_geometry(o))
+                    .reduce(|acc, geo| acc.merge(geo));
+                if let Some(bounding_box) = bounding_box {
+                    let location = event.position_transformed(bounding_box.size) + bounding_box.loc.to_f64();
+                    self.on_pointer_motion_absolute(location, event.time_msec());
+                }
+            },
+
+            InputEvent::PointerButton { event } => self.on_pointer_button::<B>(event),
+
+            InputEvent::PointerAxis { event } => self.on_pointer_axis::<B>(event),
+
+            InputEvent::TouchDown { event } => {
+                if let Some(location) = self.touch_location_transformed(&event) {
+                    self.on_touch_down(event.slot(), location, event.time_msec());
+                }
+            },
+            InputEvent::TouchMotion { event } => {
+                if let Some(location) = self.touch_location_transformed(&event) {
+                    self.on_touch_motion(event.slot(), location, event.time_msec());
+                }
+            },
+            InputEvent::TouchUp { event } => self.on_touch_up(event.slot(), event.time_msec()),
+            InputEvent::TouchFrame { event: _ } => self.on_touch_frame(),
+            InputEvent::TouchCancel { event: _ } => self.on_touch_cancel(),
+
+            // Convertibles report when the keyboard is folded away, that is tablet mode.
+            InputEvent::SwitchToggle { event } if event.switch() == Some(Switch::TabletMode) => {
+                self.set_desktop_mode(event.state() == SwitchState::Off);
             },
 
             _ => (),
         }
     }
+
+    /*
+    Moves the keyboard focus to the active window of the window manager.
+    A layer surface (eg. a launcher) that got the focus from a click keeps it.
+    */
+    fn focus_active_window(&mut self) {
+        if self.overview.is_some() {
+            return;
+        }
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+        match keyboard.current_focus() {
+            Some(KeyboardFocusTarget::LayerSurface(layer)) if layer.alive() => return,
+            // Grabbing popups keep the focus until they are dismissed.
+            Some(KeyboardFocusTarget::Popup(popup)) if popup.alive() => return,
+            _ => {}
+        }
+
+        if let Some(active_window) = self.window_manager.get_active_window().cloned() {
+            keyboard.set_focus(self, Some(active_window.into()), SERIAL_COUNTER.next_serial());
+        }
+    }
+
+    fn on_pointer_motion<B: InputBackend>(&mut self, evt: B::PointerMotionEvent) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let location = self.clamp_coords(self.pointer.current_location() + evt.delta());
+        self.focus_output_at(location);
+        let under = self.pointer_focus_under(location);
+
+        let pointer = self.pointer.clone();
+        pointer.motion(
+            self,
+            under.clone(),
+            &MotionEvent {
+                location,
+                serial,
+                time: evt.time_msec(),
+            },
+        );
+        pointer.relative_motion(
+            self,
+            under,
+            &RelativeMotionEvent {
+                delta: evt.delta(),
+                delta_unaccel: evt.delta_unaccel(),
+                utime: evt.time(),
+            },
+        );
+        pointer.frame(self);
+    }
+
+    fn on_pointer_motion_absolute(&mut self, location: Point<f64, Logical>, time: u32) {
+        let serial = SERIAL_COUNTER.next_serial();
+        self.focus_output_at(location);
+        let under = self.pointer_focus_under(location);
+
+        let pointer = self.pointer.clone();
+        pointer.motion(self, under, &MotionEvent { location, serial, time });
+        pointer.frame(self);
+    }
+
+    fn on_pointer_button<B: InputBackend>(&mut self, evt: B::PointerButtonEvent) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let button = evt.button_code();
+        let state = evt.state();
+
+        let pointer = self.pointer.clone();
+        if self.overview.is_some() {
+            match state {
+                ButtonState::Pressed => self.overview_press(None, pointer.current_location()),
+                ButtonState::Released => self.overview_release(None),
+            }
+            return;
+        }
+
+        if state == ButtonState::Pressed && !pointer.is_grabbed() {
+            let location = pointer.current_location();
+            let start_data = PointerGrabStartData {
+                focus: None,
+                button,
+                location,
+            };
+            if self.window_manager.divider_under(location, &self.space) {
+                pointer.set_grab(self, PointerDividerGrab { start_data }, serial, Focus::Clear);
+            } else if let Some((window, initial_window_location)) = self.pip_under(location) {
+                let grab = PointerPipGrab {
+                    start_data,
+                    window,
+                    initial_window_location,
+                    last_location: location,
+                };
+                pointer.set_grab(self, grab, serial, Focus::Clear);
+            } else {
+                self.focus_under(location, serial);
+            }
+        }
+
+        pointer.button(
+            self,
+            &ButtonEvent {
+                button,
+                state: state.into(),
+                serial,
+                time: evt.time_msec(),
+            },
+        );
+        pointer.frame(self);
+    }
+
+    fn on_pointer_axis<B: InputBackend>(&mut self, evt: B::PointerAxisEvent) {
+        if self.overview.is_some() {
+            return;
+        }
+
+        let source = evt.source();
+        // Wheels without continuous values report 120 per detent, which is 15 logical pixels.
+        let horizontal_amount = evt
+            .amount(Axis::Horizontal)
+            .unwrap_or_else(|| evt.amount_v120(Axis::Horizontal).unwrap_or(0.0) * 15.0 / 120.0);
+        let vertical_amount = evt
+            .amount(Axis::Vertical)
+            .unwrap_or_else(|| evt.amount_v120(Axis::Vertical).unwrap_or(0.0) * 15.0 / 120.0);
+
+        let mut frame = AxisFrame::new(evt.time_msec()).source(source);
+        if horizontal_amount != 0.0 {
+            frame = frame
+                .relative_direction(Axis::Horizontal, evt.relative_direction(Axis::Horizontal))
+                .value(Axis::Horizontal, horizontal_amount);
+            if let Some(discrete) = evt.amount_v120(Axis::Horizontal) {
+                frame = frame.v120(Axis::Horizontal, discrete as i32);
+            }
+        }
+        if vertical_amount != 0.0 {
+            frame = frame
+                .relative_direction(Axis::Vertical, evt.relative_direction(Axis::Vertical))
+                .value(Axis::Vertical, vertical_amount);
+            if let Some(discrete) = evt.amount_v120(Axis::Vertical) {
+                frame = frame.v120(Axis::Vertical, discrete as i32);
+            }
+        }
+        // Fingers lifted from a touchpad end kinetic scrolling.
+        if source == AxisSource::Finger {
+            if evt.amount(Axis::Horizontal) == Some(0.0) {
+                frame = frame.stop(Axis::Horizontal);
+            }
+            if evt.amount(Axis::Vertical) == Some(0.0) {
+                frame = frame.stop(Axis::Vertical);
+            }
+        }
+
+        let pointer = self.pointer.clone();
+        pointer.axis(self, frame);
+        pointer.frame(self);
+    }
+
+    /*
+    The surface the pointer should focus at a location. While the overview is open, the
+    pointer only drives the previews and no surface gets focused.
+    */
+    fn pointer_focus_under(&mut self, location: Point<f64, Logical>) -> Option<(PointerFocusTarget, Point<f64, Logical>)> {
+        if self.overview.is_some() {
+            self.overview_motion(None, location);
+            return None;
+        }
+        self.surface_under(location)
+    }
+
+    /*
+    The output touchscreens are mapped to: the configured one, the internal panel or the first output.
+    */
+    fn touch_output(&self) -> Option<Output> {
+        let configured = self.config.input.touch_output.as_deref();
+        configured
+            .and_then(|name| self.space.outputs().find(|o| o.name() == name))
+            .or_else(|| self.space.outputs().find(|o| o.name().starts_with("eDP")))
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+    }
+
+    /*
+    Maps a touchscreen position to global coordinates.
+    The touchscreen is mounted on the panel, so its axes follow the panel and not the
+    (possibly rotated) image the output shows.
+    */
+    fn touch_location_transformed<B: InputBackend, E: AbsolutePositionEvent<B>>(
+        &self,
+        evt: &E,
+    ) -> Option<Point<f64, Logical>> {
+        let output = self.touch_output()?;
+        let output_geo = self.space.output_geometry(&output)?;
+        let transform = output.current_transform();
+        let size = transform.invert().transform_size(output_geo.size);
+        Some(transform.transform_point_in(evt.position_transformed(size), &size.to_f64()) + output_geo.loc.to_f64())
+    }
+
+    /*
+    Maps a touch position inside the host window to global coordinates, like pointer motion.
+    */
+    fn touch_location_windowed<B: InputBackend, E: AbsolutePositionEvent<B>>(
+        &self,
+        evt: &E,
+        output_name: &str,
+    ) -> Option<Point<f64, Logical>> {
+        let output = self.space.outputs().find(|o| o.name() == output_name)?;
+        let output_geo = self.space.output_geometry(output)?;
+        Some(evt.position_transformed(output_geo.size) + output_geo.loc.to_f64())
+    }
+
+    pub(crate) fn on_touch_down(&mut self, slot: TouchSlot, location: Point<f64, Logical>, time: u32) {
+        self.focus_output_at(location);
+        if self.overview.is_some() {
+            self.overview_press(Some(slot), location);
+            return;
+        }
+
+        let output_geometry = self
+            .space
+            .output_under(location)
+            .next()
+            .and_then(|o| self.space.output_geometry(o));
+        let filter = self.gestures.down(&self.config.gestures, slot, location, output_geometry);
+        if !self.apply_touch_filter(filter) {
+            return;
+        }
+
+        let serial = SERIAL_COUNTER.next_serial();
+
+        // The first finger on the divider between tiled apps or on the picture-in-picture tile
+        // drags it, no client sees the touch.
+        let touch = self.touch.clone();
+        let mut grabbed = false;
+        if self.touch_slots.is_empty() && !touch.is_grabbed() {
+            let start_data = TouchGrabStartData {
+                focus: None,
+                slot,
+                location,
+            };
+            if self.window_manager.divider_under(location, &self.space) {
+                touch.set_grab(self, TouchDividerGrab { start_data }, serial);
+                grabbed = true;
+            } else if let Some((window, initial_window_location)) = self.pip_under(location) {
+                let grab = TouchPipGrab {
+                    start_data,
+                    window,
+                    initial_window_location,
+                    last_location: location,
+                };
+                touch.set_grab(self, grab, serial);
+                grabbed = true;
+            }
+        }
+        if grabbed {
+            self.touch_slots.insert(slot, None);
+            touch.down(
+                self,
+                None,
+                &DownEvent {
+                    slot,
+                    location,
+                    serial,
+                    time,
+                },
+            );
+            return;
+        }
+
+        let under = self.surface_under(location);
+
+        // Popup grabs only cover the pointer and keyboard, so a tap outside has to dismiss them here.
+        self.dismiss_popups_outside(under.as_ref().map(|(focus, _)| focus));
+
+        // The first finger moves the keyboard focus, like a click.
+        if self.touch_slots.is_empty() {
+            self.focus_under(location, serial);
+        }
+
+        self.touch_slots.insert(slot, under.clone());
+
+        touch.down(
+            self,
+            under,
+            &DownEvent {
+                slot,
+                location,
+                serial,
+                time,
+            },
+        );
+    }
+
+    pub(crate) fn on_touch_motion(&mut self, slot: TouchSlot, location: Point<f64, Logical>, time: u32) {
+        if self.overview.is_some() {
+            self.overview_motion(Some(slot), location);
+            return;
+        }
+
+        let filter = self.gestures.motion(&self.config.gestures, slot, location);
+        if !self.apply_touch_filter(filter) {
+            return;
+        }
+
+        // A touch point stays on the surface it started on, even when the finger leaves it.
+        let Some(focus) = self.touch_slots.get(&slot).cloned() else {
+            return;
+        };
+
+        let touch = self.touch.clone();
+        touch.motion(self, focus, &TouchMotionEvent { slot, location, time });
+    }
+
+    pub(crate) fn on_touch_up(&mut self, slot: TouchSlot, time: u32) {
+        if self.overview.is_some() {
+            self.overview_release(Some(slot));
+            return;
+        }
+
+        let filter = self.gestures.up(slot);
+        if !self.apply_touch_filter(filter) {
+            return;
+        }
+
+        if self.touch_slots.remove(&slot).is_none() {
+            return;
+        }
+
+        let serial = SERIAL_COUNTER.next_serial();
+        let touch = self.touch.clone();
+        touch.up(self, &UpEvent { slot, serial, time });
+    }
+
+    pub(crate) fn on_touch_frame(&mut self) {
+        if self.overview.is_some() {
+            return;
+        }
+
+        let touch = self.touch.clone();
+        touch.frame(self);
+    }
+
+    fn on_touch_cancel(&mut self) {
+        self.overview_cancel();
+        self.gestures.cancel();
+        self.touch_slots.clear();
+        let touch = self.touch.clone();
+        touch.cancel(self);
+    }
+
+    /*
+    Acts on the gesture recognizer's verdict for a touch event.
+    Returns whether the event should still be forwarded to clients.
+    */
+    fn apply_touch_filter(&mut self, filter: TouchFilter) -> bool {
+        match filter {
+            TouchFilter::Forward => true,
+            TouchFilter::Intercept => false,
+            TouchFilter::Recognized(gesture) => {
+                // Clients already saw the beginning of the sequence, tell them it is gone.
+                if !self.touch_slots.is_empty() {
+                    self.touch_slots.clear();
+                    let touch = self.touch.clone();
+                    touch.cancel(self);
+                }
+                self.execute_gesture(gesture);
+                false
+            }
+        }
+    }
+
+    /*
+    Runs the window manager action of a recognised navigation gesture.
+    */
+    pub fn execute_gesture(&mut self, gesture: Gesture) {
+        tracing::debug!(?gesture, "Gesture recognised");
+        self.window_manager.gesture(gesture, &mut self.space);
+        self.refocus_active_window();
+    }
+
+    /*
+    Gives the keyboard focus to the active window of the window manager, after the
+    window manager switched apps.
+    */
+    pub fn refocus_active_window(&mut self) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let focus = self.window_manager.get_active_window().cloned().map(Into::into);
+        keyboard.set_focus(self, focus, SERIAL_COUNTER.next_serial());
+    }
+
+    /*
+    Finds the surface under a point in global coordinates, together with the
+    surface's origin in global coordinates.
+
+    Surfaces are tested top to bottom: overlay and top layer surfaces, windows, then
+    bottom and background layer surfaces. Popups are found through their parent
+    window or layer surface.
+    */
+    pub fn surface_under(&self, location: Point<f64, Logical>) -> Option<(PointerFocusTarget, Point<f64, Logical>)> {
+        let output = self.space.output_under(location).next()?;
+        let output_geo = self.space.output_geometry(output)?;
+        let layers = layer_map_for_output(output);
+        let output_location = location - output_geo.loc.to_f64();
+
+        let layer_surface_under = |layer: WlrLayer| {
+            let layer_surface = layers.layer_under(layer, output_location)?;
+            let layer_loc = layers.layer_geometry(layer_surface)?.loc;
+            layer_surface
+                .surface_under(output_location - layer_loc.to_f64(), WindowSurfaceType::ALL)
+                .map(|(surface, loc)| {
+                    (
+                        PointerFocusTarget::WlSurface(surface),
+                        (loc + layer_loc + output_geo.loc).to_f64(),
+                    )
+                })
+        };
+
+        layer_surface_under(WlrLayer::Overlay)
+            .or_else(|| layer_surface_under(WlrLayer::Top))
+            .or_else(|| {
+                self.space.element_under(location).and_then(|(window, window_loc)| {
+                    window
+                        .surface_under(location - window_loc.to_f64(), WindowSurfaceType::ALL)
+                        .map(|(target, loc)| (target, (loc + window_loc).to_f64()))
+                })
+            })
+            .or_else(|| layer_surface_under(WlrLayer::Bottom))
+            .or_else(|| layer_surface_under(WlrLayer::Background))
+    }
+
+    /*
+    The picture-in-picture tile under a point, with its location in the space.
+    */
+    fn pip_under(&self, location: Point<f64, Logical>) -> Option<(WindowElement, Point<i32, Logical>)> {
+        let window = self.window_manager.pip_window()?;
+        let geometry = self.space.element_geometry(window)?;
+        geometry
+            .to_f64()
+            .contains(location)
+            .then(|| (window.clone(), geometry.loc))
+    }
+
+    /*
+    Closes the whole popup chain of the active popup grab, unless the touched surface belongs
+    to the client owning it.
+    */
+    fn dismiss_popups_outside(&mut self, focus: Option<&PointerFocusTarget>) {
+        let Some(mut grab) = self.popup_grab.take() else {
+            return;
+        };
+        if grab.has_ended() {
+            return;
+        }
+
+        let inside = grab
+            .current_grab()
+            .and_then(|popup| popup.wl_surface().map(|surface| surface.id()))
+            .zip(focus)
+            .map(|(id, focus)| focus.same_client_as(&id))
+            .unwrap_or(false);
+        if inside {
+            self.popup_grab = Some(grab);
+            return;
+        }
+
+        grab.ungrab(PopupUngrabStrategy::All);
+    }
+
+    /*
+    Gives the keyboard focus to whatever was clicked: a layer surface accepting keyboard
+    input or a window, which is also raised.
+    */
+    fn focus_under(&mut self, location: Point<f64, Logical>, serial: Serial) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+
+        if let Some(layer) = self.keyboard_layer_under(location, &[WlrLayer::Overlay, WlrLayer::Top]) {
+            keyboard.set_focus(self, Some(KeyboardFocusTarget::LayerSurface(layer)), serial);
+            return;
+        }
+
+        if let Some((window, _)) = self.space.element_under(location).map(|(w, loc)| (w.clone(), loc)) {
+            // The window manager has to know, eg. to keep the app used last in a split active.
+            self.window_manager.activate(window.id(), &mut self.space);
+            self.space.raise_element(&window, true);
+            keyboard.set_focus(self, Some(window.into()), serial);
+            return;
+        }
+
+        if let Some(layer) = self.keyboard_layer_under(location, &[WlrLayer::Bottom, WlrLayer::Background]) {
+            keyboard.set_focus(self, Some(KeyboardFocusTarget::LayerSurface(layer)), serial);
+        }
+    }
+
+    fn keyboard_layer_under(&self, location: Point<f64, Logical>, layers: &[WlrLayer]) -> Option<LayerSurface> {
+        let output = self.space.output_under(location).next()?;
+        let output_geo = self.space.output_geometry(output)?;
+        let map = layer_map_for_output(output);
+        layers
+            .iter()
+            .find_map(|layer| map.layer_under(*layer, location - output_geo.loc.to_f64()))
+            .filter(|layer| layer.can_receive_keyboard_focus())
+            .cloned()
+    }
+
+    /*
+    Keeps relative pointer motion inside the outputs, moving it to the closest point of the
+    closest output when it would leave them.
+    */
+    fn clamp_coords(&self, location: Point<f64, Logical>) -> Point<f64, Logical> {
+        self.space
+            .outputs()
+            .filter_map(|o| self.space.output_geometry(o))
+            .map(|geo| {
+                let x = location.x.clamp(geo.loc.x as f64, (geo.loc.x + geo.size.w - 1) as f64);
+                let y = location.y.clamp(geo.loc.y as f64, (geo.loc.y + geo.size.h - 1) as f64);
+                Point::<f64, Logical>::from((x, y))
+            })
+            .min_by(|a, b| {
+                let distance = |p: &Point<f64, Logical>| (p.x - location.x).powi(2) + (p.y - location.y).powi(2);
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap_or(location)
+    }
+
+    /*
+    Runs a compositor action, usually triggered by a key binding.
+    */
+    pub fn execute_action(&mut self, action: KeyAction) {
+        if let KeyAction::Overview(key) = action {
+            self.overview_key(key);
+            return;
+        }
+
+        // The window manager gets the first look, so a policy can take over any binding.
+        if self.window_manager.key_action(&action, &mut self.space) {
+            self.refocus_active_window();
+            return;
+        }
+
+        match action {
+            // Only policies with a split, picture-in-picture or workspaces handle these, and they did above.
+            KeyAction::None
+            | KeyAction::Overview(_)
+            | KeyAction::ToggleSplit
+            | KeyAction::TogglePip
+            | KeyAction::MoveToScreen(_)
+            | KeyAction::ToggleDesktopMode
+            | KeyAction::MoveTileNext
+            | KeyAction::MoveTilePrevious
+            | KeyAction::PromoteTile
+            | KeyAction::GrowMaster
+            | KeyAction::ShrinkMaster => {}
+            KeyAction::Quit => {
+                tracing::info!("Quitting.");
+                self.running.store(false, Ordering::SeqCst);
+            }
+            KeyAction::VtSwitch(vt) => {
+                tracing::info!(to = vt, "Trying to switch vt");
+                self.backend_data.change_vt(vt);
+            }
+            KeyAction::Run(cmd) => {
+                tracing::info!(cmd, "Starting program");
+                self.spawn(&["sh".to_string(), "-c".to_string(), cmd]);
+            }
+            // Policies with workspaces switch them instead.
+            KeyAction::Screen(num) => {
+                let geometry = self
+                    .space
+                    .outputs()
+                    .nth(num)
+                    .and_then(|o| self.space.output_geometry(o));
+
+                if let Some(geometry) = geometry {
+                    let x = geometry.loc.x as f64 + geometry.size.w as f64 / 2.0;
+                    let y = geometry.loc.y as f64 + geometry.size.h as f64 / 2.0;
+                    self.warp_pointer((x, y).into());
+                }
+            }
+            KeyAction::ScaleUp => self.change_output_scale(0.25),
+            KeyAction::ScaleDown => self.change_output_scale(-0.25),
+            KeyAction::TogglePreview => {
+                self.toggle_overview();
+            }
+            KeyAction::RotateOutput => {
+                if let Some(output) = self.output_under_pointer() {
+                    let new_transform = match output.current_transform() {
+                        Transform::Normal => Transform::_90,
+                        Transform::_90 => Transform::_180,
+                        Transform::_180 => Transform::_270,
+                        Transform::_270 => Transform::Flipped,
+                        Transform::Flipped => Transform::Flipped90,
+                        Transform::Flipped90 => Transform::Flipped180,
+                        Transform::Flipped180 => Transform::Flipped270,
+                        Transform::Flipped270 => Transform::Normal,
+                    };
+                    tracing::info!(output = output.name(), ?new_transform, "Rotating output");
+                    output.change_current_state(None, Some(new_transform), None, None);
+                    crate::shell::fixup_positions(&mut self.space, &self.config, self.pointer.current_location());
+                    self.window_manager.output_resized(&output, &mut self.space);
+                    self.backend_data.reset_buffers(&output);
+                }
+            }
+            KeyAction::ToggleTint => {
+                let mut debug_flags = self.backend_data.debug_flags();
+                debug_flags.toggle(DebugFlags::TINT);
+                self.backend_data.set_debug_flags(debug_flags);
+            }
+            KeyAction::FocusNext => {
+                self.window_manager.focus_next(&mut self.space);
+                self.refocus_active_window();
+            }
+            KeyAction::FocusPrevious => {
+                self.window_manager.focus_prev(&mut self.space);
+                self.refocus_active_window();
+            }
+            KeyAction::CloseWindow => {
+                if let Some(id) = self.window_manager.get_active_window().map(|w| w.id()) {
+                    self.close_window(id);
+                }
+            }
+            KeyAction::ToggleDecorations => {
+                use zxdg_toplevel_decoration_v1::Mode;
+
+                for window in self.window_manager.windows() {
+                    #[allow(irrefutable_let_patterns)]
+                    if let Some(toplevel) = window.0.toplevel() {
+                        let mode_changed = toplevel.with_pending_state(|state| {
+                            if let Some(current_mode) = state.decoration_mode {
+                                let new_mode = if current_mode == Mode::ClientSide {
+                                    Mode::ServerSide
+                                } else {
+                                    Mode::ClientSide
+                                };
+                                state.decoration_mode = Some(new_mode);
+                                true
+                            } else {
+                                false
+                            }
+                        });
+                        if mode_changed && toplevel.is_initial_configure_sent() {
+                            toplevel.send_pending_configure();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /*
+    Tells the window manager the user went to the output at this point,
+    it is where new apps open.
+    */
+    fn focus_output_at(&mut self, location: Point<f64, Logical>) {
+        if let Some(output) = self.space.output_under(location).next().cloned() {
+            self.window_manager.output_focused(&output);
+        }
+    }
+
+    pub(crate) fn output_under_pointer(&self) -> Option<smithay::output::Output> {
+        let pos = self.pointer.current_location();
+        self.space
+            .output_under(pos)
+            .next()
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+    }
+
+    fn change_output_scale(&mut self, delta: f64) {
+        let Some(output) = self.output_under_pointer() else {
+            return;
+        };
+        let output_location = self.space.output_geometry(&output).unwrap().loc;
+        let scale = output.current_scale().fractional_scale();
+        let new_scale = f64::max(1.0, scale + delta);
+        if new_scale == scale {
+            return;
+        }
+
+        tracing::info!(output = output.name(), new_scale, "Changing output scale");
+        output.change_current_state(None, None, Some(Scale::Fractional(new_scale)), None);
+
+        // Keep the pointer on the same relative spot of the rescaled output.
+        let rescale = scale / new_scale;
+        let output_location = output_location.to_f64();
+        let mut pointer_output_location = self.pointer.current_location() - output_location;
+        pointer_output_location.x *= rescale;
+        pointer_output_location.y *= rescale;
+        let pointer_location = output_location + pointer_output_location;
+
+        crate::shell::fixup_positions(&mut self.space, &self.config, pointer_location);
+        self.window_manager.output_resized(&output, &mut self.space);
+        self.warp_pointer(pointer_location);
+        self.backend_data.reset_buffers(&output);
+    }
+
+    fn warp_pointer(&mut self, location: Point<f64, Logical>) {
+        let under = self.surface_under(location);
+        let pointer = self.pointer.clone();
+        pointer.motion(
+            self,
+            under,
+            &MotionEvent {
+                location,
+                serial: SERIAL_COUNTER.next_serial(),
+                time: self.clock.now().as_millis(),
+            },
+        );
+        pointer.frame(self);
+    }
 }
 
-#[allow(dead_code)] // some of these are only read if udev is enabled
-#[derive(Debug)]
-enum KeyAction {
+/*
+Actions a key binding can trigger.
+In the config file unit actions are plain strings (`"quit"`, `"scale_up"`) and the others
+tables (`{ run = "foot" }`, `{ vt_switch = 2 }`).
+*/
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAction {
     /// Quit the compositor
     Quit,
     /// Trigger a vt-switch
     VtSwitch(i32),
     /// run a command
     Run(String),
-    /// Switch the current screen
+    /// Switch the current screen, the workspace of the output in use with the default policy
     Screen(usize),
+    /// Move the active app to another workspace of its output
+    MoveToScreen(usize),
     ScaleUp,
     ScaleDown,
     TogglePreview,
     RotateOutput,
     ToggleTint,
     ToggleDecorations,
+    /// Switch to the next running app
+    FocusNext,
+    /// Switch back to the previous running app
+    FocusPrevious,
+    /// Close the active app
+    CloseWindow,
+    /// Show the active app side by side with the previous one, or back alone
+    ToggleSplit,
+    /// Demote the active app to a picture-in-picture tile, or bring the tile back
+    TogglePip,
+    /// Switch between tablet mode and desktop mode, with floating windows
+    ToggleDesktopMode,
+    /// Swap the active tile with the next one
+    MoveTileNext,
+    /// Swap the active tile with the previous one
+    MoveTilePrevious,
+    /// Make the active tile the master
+    PromoteTile,
+    /// Give the master tile a larger share of the output
+    GrowMaster,
+    /// Give the master tile a smaller share of the output
+    ShrinkMaster,
+    /// A key pressed while the overview is open, not available for bindings
+    #[serde(skip_deserializing)]
+    Overview(OverviewKey),
     /// Do nothing more
     None,
-}
\ No newline at end of file
+}
+
+bitflags::bitflags! {
+    /*
+    Modifiers that have to be held for a key binding to trigger.
+    */
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Modifiers: u8 {
+        const CTRL = 1;
+        const ALT = 1 << 1;
+        const SHIFT = 1 << 2;
+        const LOGO = 1 << 3;
+    }
+}
+
+impl From<&ModifiersState> for Modifiers {
+    fn from(state: &ModifiersState) -> Self {
+        let mut modifiers = Modifiers::empty();
+        modifiers.set(Modifiers::CTRL, state.ctrl);
+        modifiers.set(Modifiers::ALT, state.alt);
+        modifiers.set(Modifiers::SHIFT, state.shift);
+        modifiers.set(Modifiers::LOGO, state.logo);
+        modifiers
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBinding {
+    pub modifiers: Modifiers,
+    pub keysym: Keysym,
+    pub action: KeyAction,
+}
+
+impl KeyBinding {
+    pub fn new(modifiers: Modifiers, keysym: Keysym, action: KeyAction) -> Self {
+        KeyBinding { modifiers, keysym, action }
+    }
+}
+
+/*
+The set of key bindings the keyboard filter intercepts.
+
+A binding matches when exactly its modifiers are held and its keysym is produced by the key,
+either without modifiers applied (so `Logo+Shift+M` matches the `m` key) or with them
+(so `Ctrl+Alt+XF86Switch_VT_1` matches `F1`).
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings(pub Vec<KeyBinding>);
+
+impl KeyBindings {
+    pub fn action_for(&self, modifiers: &ModifiersState, handle: &KeysymHandle<'_>) -> Option<KeyAction> {
+        let modifiers = Modifiers::from(modifiers);
+        let modified_sym = handle.modified_sym();
+        let raw_syms = handle.raw_syms();
+
+        self.0
+            .iter()
+            .find(|binding| {
+                binding.modifiers == modifiers
+                    && (binding.keysym == modified_sym || raw_syms.contains(&binding.keysym))
+            })
+            .map(|binding| binding.action.clone())
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let logo_shift = Modifiers::LOGO | Modifiers::SHIFT;
+        let mut bindings = vec![
+            KeyBinding::new(Modifiers::CTRL | Modifiers::ALT, Keysym::BackSpace, KeyAction::Quit),
+            KeyBinding::new(Modifiers::LOGO, Keysym::q, KeyAction::Quit),
+            KeyBinding::new(Modifiers::LOGO, Keysym::Return, KeyAction::Run("weston-terminal".into())),
+            KeyBinding::new(logo_shift, Keysym::m, KeyAction::ScaleDown),
+            KeyBinding::new(logo_shift, Keysym::p, KeyAction::ScaleUp),
+            KeyBinding::new(logo_shift, Keysym::w, KeyAction::TogglePreview),
+            KeyBinding::new(logo_shift, Keysym::r, KeyAction::RotateOutput),
+            KeyBinding::new(logo_shift, Keysym::t, KeyAction::ToggleTint),
+            KeyBinding::new(logo_shift, Keysym::d, KeyAction::ToggleDecorations),
+            KeyBinding::new(Modifiers::LOGO, Keysym::Tab, KeyAction::FocusNext),
+            KeyBinding::new(logo_shift, Keysym::Tab, KeyAction::FocusPrevious),
+            KeyBinding::new(logo_shift, Keysym::c, KeyAction::CloseWindow),
+            KeyBinding::new(logo_shift, Keysym::s, KeyAction::ToggleSplit),
+            KeyBinding::new(logo_shift, Keysym::i, KeyAction::TogglePip),
+            KeyBinding::new(logo_shift, Keysym::f, KeyAction::ToggleDesktopMode),
+            KeyBinding::new(Modifiers::LOGO, Keysym::j, KeyAction::FocusNext),
+            KeyBinding::new(Modifiers::LOGO, Keysym::k, KeyAction::FocusPrevious),
+            KeyBinding::new(logo_shift, Keysym::j, KeyAction::MoveTileNext),
+            KeyBinding::new(logo_shift, Keysym::k, KeyAction::MoveTilePrevious),
+            KeyBinding::new(logo_shift, Keysym::Return, KeyAction::PromoteTile),
+            KeyBinding::new(Modifiers::LOGO, Keysym::l, KeyAction::GrowMaster),
+            KeyBinding::new(Modifiers::LOGO, Keysym::h, KeyAction::ShrinkMaster),
+        ];
+
+        // Ctrl+Alt+F1..F12 produce the XF86Switch_VT_N keysyms
+        bindings.extend((0..12).map(|vt| {
+            KeyBinding::new(
+                Modifiers::CTRL | Modifiers::ALT,
+                Keysym::from(xkb::KEY_XF86Switch_VT_1 + vt as u32),
+                KeyAction::VtSwitch(vt + 1),
+            )
+        }));
+        // Logo+1..9 switch screens, with Shift the active app is moved there
+        bindings.extend((0..9).flat_map(|screen| {
+            let keysym = Keysym::from(xkb::KEY_1 + screen as u32);
+            [
+                KeyBinding::new(Modifiers::LOGO, keysym, KeyAction::Screen(screen)),
+                KeyBinding::new(logo_shift, keysym, KeyAction::MoveToScreen(screen)),
+            ]
+        }));
+
+        KeyBindings(bindings)
+    }
+}
@@ -4,6 +4,16 @@ pub mod focus;
 pub mod input_handler;
 pub mod window_manager;
 pub mod renderer;
+pub mod cli;
+pub mod config;
+pub mod gestures;
+pub mod overview;
+pub mod snapshot;
+#[cfg(feature = "winit")]
 pub mod winit;
+#[cfg(feature = "headless")]
+pub mod headless;
+#[cfg(feature = "udev")]
+pub mod udev;
 
 pub use state::{AuroraState, ClientState};
\ No newline at end of file
@@ -1,13 +1,88 @@
+use std::{fs::File, sync::Mutex};
+
+use aurora::{
+    cli::{BackendKind, CliArgs, CliError, USAGE},
+    config::Config,
+};
+
 fn main() {
-    if let Ok(env_filter) = tracing_subscriber::EnvFilter::try_from_default_env() {
+    let args = match CliArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(CliError::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("aurora: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    if let Some(log_file) = &args.log_file {
+        let file = match File::create(log_file) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("aurora: failed to open log file `{}`: {}", log_file.display(), err);
+                std::process::exit(1);
+            }
+        };
         tracing_subscriber::fmt()
             .compact()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
             .with_env_filter(env_filter)
             .init();
     } else {
-        tracing_subscriber::fmt().compact().init();
+        tracing_subscriber::fmt()
+            .compact()
+            .with_env_filter(env_filter)
+            .init();
     }
 
     tracing::info!("Welcome to Aurora!");
-    aurora::winit::run_winit();
+
+    // An explicitly given config has to exist, the default one is optional: while it is
+    // missing the defaults are used, and it is still watched so it applies once created.
+    let config_path = args.config_path.clone().or_else(Config::default_path);
+    let config = match &config_path {
+        Some(path) if args.config_path.is_none() && !path.exists() => Config {
+            path: Some(path.clone()),
+            ..Config::default()
+        },
+        Some(path) => match Config::load(path) {
+            Ok(config) => {
+                tracing::info!(path = %path.display(), "Loaded configuration");
+                config
+            }
+            Err(err) => {
+                tracing::error!("{}", err);
+                if args.log_file.is_some() {
+                    eprintln!("aurora: {}", err);
+                }
+                std::process::exit(1);
+            }
+        },
+        None => Config::default(),
+    };
+
+    let result = match args.backend {
+        #[cfg(feature = "winit")]
+        BackendKind::Winit => aurora::winit::run_winit(&args, config),
+        #[cfg(feature = "headless")]
+        BackendKind::Headless => aurora::headless::run_headless(&args, config),
+        #[cfg(feature = "udev")]
+        BackendKind::Udev => aurora::udev::run_udev(&args, config),
+        // `CliArgs::parse` already rejects backends that were not compiled in.
+        #[allow(unreachable_patterns)]
+        backend => unreachable!("backend {} is not compiled in", backend),
+    };
+    if let Err(err) = result {
+        if args.log_file.is_some() {
+            tracing::error!("{}", err);
+        }
+        eprintln!("aurora: {}", err);
+        std::process::exit(1);
+    }
 }
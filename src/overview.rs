@@ -0,0 +1,361 @@
+use smithay::{
+    backend::input::TouchSlot,
+    input::{keyboard::Keysym, pointer::MotionEvent},
+    desktop::Space,
+    output::Output,
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+
+use crate::{
+    renderer::{preview_layout, PREVIEW_COLUMNS},
+    shell::WindowElement,
+    state::{AuroraState, Backend},
+};
+
+/// How far a preview has to be swiped up to close its app, in logical pixels
+const CLOSE_SWIPE_DISTANCE: f64 = 120.0;
+/// Movement under which a press still counts as a tap
+pub(crate) const TAP_SLOP: f64 = 10.0;
+/// Color of the frame around the preview selected with the keyboard
+pub const HIGHLIGHT_COLOR: [f32; 4] = [0.3, 0.5, 0.9, 1.0];
+/// Width of that frame, in logical pixels
+pub const HIGHLIGHT_WIDTH: i32 = 4;
+
+/*
+Keys with a meaning in the overview.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverviewKey {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Switch to the selected app
+    Activate,
+    /// Leave the overview without switching
+    Close,
+}
+
+impl OverviewKey {
+    pub fn from_keysym(keysym: Keysym) -> Option<Self> {
+        match keysym {
+            Keysym::Left => Some(OverviewKey::Left),
+            Keysym::Right => Some(OverviewKey::Right),
+            Keysym::Up => Some(OverviewKey::Up),
+            Keysym::Down => Some(OverviewKey::Down),
+            Keysym::Return | Keysym::KP_Enter => Some(OverviewKey::Activate),
+            Keysym::Escape => Some(OverviewKey::Close),
+            _ => None,
+        }
+    }
+}
+
+/*
+A pointer button or touch point pressed in the overview.
+*/
+#[derive(Debug)]
+struct Press {
+    /// `None` for the pointer
+    slot: Option<TouchSlot>,
+    /// The preview it started on, `None` for the empty space around the previews
+    window: Option<WindowElement>,
+    start: Point<f64, Logical>,
+    current: Point<f64, Logical>,
+}
+
+/*
+State of the overview, a grid of previews of the running apps.
+
+While it is open, no client gets any input: taps and clicks go to the previews,
+keys move the selection.
+*/
+#[derive(Debug)]
+pub struct Overview {
+    /// Every running app, the most recently used first, with the output the window manager put it on
+    windows: Vec<(WindowElement, Option<Output>)>,
+    /// The output and index of the preview selected with the keyboard
+    selected: Option<(Output, usize)>,
+    press: Option<Press>,
+}
+
+impl Overview {
+    /*
+    The windows shown in the overview of an output, in grid order.
+
+    Apps are shown on the output the window manager assigned them to, or else on the one
+    they are mapped on. The others go to the first output.
+    */
+    pub fn windows_on(&self, space: &Space<WindowElement>, output: &Output) -> Vec<WindowElement> {
+        let first_output = space.outputs().next();
+        self.windows
+            .iter()
+            .filter(|(window, assigned)| {
+                let shown_on = assigned
+                    .clone()
+                    .filter(|assigned| space.outputs().any(|o| o == assigned))
+                    .or_else(|| space.outputs_for_element(window).first().cloned());
+                match shown_on {
+                    Some(shown_on) => &shown_on == output,
+                    None => first_output == Some(output),
+                }
+            })
+            .map(|(window, _)| window.clone())
+            .collect()
+    }
+
+    /*
+    Returns the index of the selected preview, if it is on this output.
+    */
+    pub fn selected_on(&self, output: &Output) -> Option<usize> {
+        self.selected
+            .as_ref()
+            .filter(|(selected_output, _)| selected_output == output)
+            .map(|(_, index)| *index)
+    }
+
+    /*
+    How far the preview of a window is dragged up, it follows the finger while it is swiped away.
+    */
+    pub fn swipe_offset(&self, window: &WindowElement) -> i32 {
+        self.press
+            .as_ref()
+            .filter(|press| press.window.as_ref() == Some(window))
+            .map(|press| (press.start.y - press.current.y).max(0.0).round() as i32)
+            .unwrap_or(0)
+    }
+}
+
+impl<BackendData: Backend> AuroraState<BackendData> {
+    pub fn toggle_overview(&mut self) {
+        if self.overview.is_some() {
+            self.close_overview();
+        } else {
+            self.open_overview();
+        }
+    }
+
+    pub fn open_overview(&mut self) {
+        if self.overview.is_some() {
+            return;
+        }
+
+        let mut overview = Overview {
+            windows: Vec::new(),
+            selected: None,
+            press: None,
+        };
+        overview.windows = self.running_apps();
+
+        // The active app is selected, on the output the user is looking at.
+        overview.selected = self.output_under_pointer().map(|output| {
+            let index = self
+                .window_manager
+                .get_active_window()
+                .and_then(|active| overview.windows_on(&self.space, &output).iter().position(|w| w == active))
+                .unwrap_or(0);
+            (output, index)
+        });
+        self.overview = Some(overview);
+
+        // Nothing may leak to the clients below: they lose the keyboard and pointer focus,
+        // and touch points already sent to them are cancelled.
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.set_focus(self, None, SERIAL_COUNTER.next_serial());
+        self.refresh_pointer_focus();
+
+        self.gestures.cancel();
+        if !self.touch_slots.is_empty() {
+            self.touch_slots.clear();
+            let touch = self.touch.clone();
+            touch.cancel(self);
+        }
+    }
+
+    pub fn close_overview(&mut self) {
+        if self.overview.take().is_none() {
+            return;
+        }
+        self.refocus_active_window();
+        self.refresh_pointer_focus();
+    }
+
+    /*
+    Picks up apps that were opened or closed while the overview is shown.
+    Called before every repaint.
+    */
+    pub fn refresh_overview(&mut self) {
+        if self.overview.is_none() {
+            return;
+        }
+        let windows = self.running_apps();
+        if let Some(overview) = self.overview.as_mut() {
+            overview.windows = windows;
+        }
+    }
+
+    fn running_apps(&self) -> Vec<(WindowElement, Option<Output>)> {
+        self.window_manager
+            .mru()
+            .into_iter()
+            .filter_map(|id| {
+                let window = self.window_manager.window(id)?.clone();
+                Some((window, self.window_manager.window_output(id)))
+            })
+            .collect()
+    }
+
+    /*
+    Sends a motion to the pointer at its current location, so it enters the surface below
+    again, or leaves every surface while the overview is open.
+    */
+    fn refresh_pointer_focus(&mut self) {
+        let location = self.pointer.current_location();
+        let under = if self.overview.is_some() {
+            None
+        } else {
+            self.surface_under(location)
+        };
+
+        let pointer = self.pointer.clone();
+        pointer.motion(
+            self,
+            under,
+            &MotionEvent {
+                location,
+                serial: SERIAL_COUNTER.next_serial(),
+                time: self.clock.now().as_millis(),
+            },
+        );
+        pointer.frame(self);
+    }
+
+    /*
+    Finds the preview under a point in global coordinates, with its output and index.
+    */
+    fn preview_under(&self, location: Point<f64, Logical>) -> Option<(Output, usize, WindowElement)> {
+        let output = self.space.output_under(location).next()?.clone();
+        let output_geo = self.space.output_geometry(&output)?;
+        let local = (location - output_geo.loc.to_f64()).to_i32_round();
+
+        let windows = self.overview.as_ref()?.windows_on(&self.space, &output);
+        let index = preview_layout(&output, windows.len())
+            .iter()
+            .position(|preview| preview.contains(local))?;
+        let window = windows.get(index)?.clone();
+        Some((output, index, window))
+    }
+
+    /*
+    A pointer button (`slot` is `None`) or a finger went down in the overview.
+    */
+    pub fn overview_press(&mut self, slot: Option<TouchSlot>, location: Point<f64, Logical>) {
+        let preview = self.preview_under(location);
+        let Some(overview) = self.overview.as_mut() else {
+            return;
+        };
+        if overview.press.is_some() {
+            return;
+        }
+
+        let window = preview.map(|(output, index, window)| {
+            overview.selected = Some((output, index));
+            window
+        });
+        overview.press = Some(Press {
+            slot,
+            window,
+            start: location,
+            current: location,
+        });
+    }
+
+    pub fn overview_motion(&mut self, slot: Option<TouchSlot>, location: Point<f64, Logical>) {
+        let Some(press) = self.overview.as_mut().and_then(|o| o.press.as_mut()) else {
+            return;
+        };
+        if press.slot == slot {
+            press.current = location;
+        }
+    }
+
+    /*
+    A pointer button or finger was lifted: a tap on a preview switches to its app, a tap
+    next to the previews goes back to the active app and a swipe up closes the app.
+    */
+    pub fn overview_release(&mut self, slot: Option<TouchSlot>) {
+        let Some(overview) = self.overview.as_mut() else {
+            return;
+        };
+        if overview.press.as_ref().map(|press| press.slot != slot).unwrap_or(true) {
+            return;
+        }
+        let press = overview.press.take().unwrap();
+
+        let delta = press.current - press.start;
+        let distance = (delta.x * delta.x + delta.y * delta.y).sqrt();
+        match press.window {
+            Some(window) if -delta.y >= CLOSE_SWIPE_DISTANCE => {
+                self.close_window(window.id());
+            }
+            Some(window) if distance <= TAP_SLOP => {
+                self.window_manager.activate(window.id(), &mut self.space);
+                self.close_overview();
+            }
+            None if distance <= TAP_SLOP => self.close_overview(),
+            _ => {}
+        }
+    }
+
+    /*
+    Drops a press without acting on it, eg. when the touch sequence was cancelled.
+    */
+    pub fn overview_cancel(&mut self) {
+        if let Some(overview) = self.overview.as_mut() {
+            overview.press = None;
+        }
+    }
+
+    /*
+    Moves the selection through the grid with the arrow keys, Enter switches to the selected app.
+    */
+    pub fn overview_key(&mut self, key: OverviewKey) {
+        let Some(output) = self.output_under_pointer() else {
+            return;
+        };
+        let Some(overview) = self.overview.as_mut() else {
+            return;
+        };
+        let windows = overview.windows_on(&self.space, &output);
+        if windows.is_empty() {
+            if key == OverviewKey::Activate || key == OverviewKey::Close {
+                self.close_overview();
+            }
+            return;
+        }
+
+        // The selection starts over when the pointer moved to another output.
+        let index = overview
+            .selected_on(&output)
+            .map(|index| index.min(windows.len() - 1))
+            .unwrap_or(0);
+        let columns = PREVIEW_COLUMNS.min(windows.len());
+        let last = windows.len() - 1;
+        let index = match key {
+            OverviewKey::Left => index.saturating_sub(1),
+            OverviewKey::Right => (index + 1).min(last),
+            OverviewKey::Up => index.checked_sub(columns).unwrap_or(index),
+            OverviewKey::Down => (index + columns).min(last),
+            OverviewKey::Activate => {
+                let id = windows[index].id();
+                self.window_manager.activate(id, &mut self.space);
+                self.close_overview();
+                return;
+            }
+            OverviewKey::Close => {
+                self.close_overview();
+                return;
+            }
+        };
+        overview.selected = Some((output, index));
+    }
+}
@@ -2,24 +2,26 @@ use smithay::{
     backend::renderer::{
         damage::{Error as OutputDamageTrackerError, OutputDamageTracker, RenderOutputResult},
         element::{
+            solid::{SolidColorBuffer, SolidColorRenderElement},
             surface::WaylandSurfaceRenderElement,
-            utils::{
-                ConstrainAlign, ConstrainScaleBehavior, CropRenderElement, RelocateRenderElement,
-                RescaleRenderElement,
-            },
-            AsRenderElements, RenderElement, Wrap,
+            texture::TextureRenderElement,
+            utils::CropRenderElement,
+            AsRenderElements, Kind, RenderElement, Wrap,
         },
         ImportAll, ImportMem, Renderer,
     },
-    desktop::space::{
-        constrain_space_element, ConstrainBehavior, ConstrainReference, Space, SpaceRenderElements,
-    },
+    desktop::space::{Space, SpaceElement, SpaceRenderElements},
     output::Output,
-    utils::{Point, Rectangle, Size},
+    utils::{Logical, Point, Rectangle, Scale, Size},
 };
 
+use std::cell::RefCell;
 
-use crate::shell::{FullscreenSurface, WindowElement, WindowRenderElement};
+use crate::{
+    overview::{Overview, HIGHLIGHT_COLOR, HIGHLIGHT_WIDTH},
+    shell::{FullscreenSurface, WindowElement, WindowRenderElement},
+    snapshot::{snapshot_elements, update_snapshot},
+};
 
 smithay::backend::renderer::element::render_elements! {
     pub CustomRenderElements<R> where
@@ -54,7 +56,8 @@ smithay::backend::renderer::element::render_elements! {
     Space=SpaceRenderElements<R, E>,
     Window=Wrap<E>,
     Custom=CustomRenderElements<R>,
-    Preview=CropRenderElement<RelocateRenderElement<RescaleRenderElement<WindowRenderElement<R>>>>,
+    Preview=CropRenderElement<TextureRenderElement<<R as Renderer>::TextureId>>,
+    Highlight=SolidColorRenderElement,
 }
 
 impl<R: Renderer + ImportAll + ImportMem, E: RenderElement<R> + std::fmt::Debug> std::fmt::Debug
@@ -66,42 +69,31 @@ impl<R: Renderer + ImportAll + ImportMem, E: RenderElement<R> + std::fmt::Debug>
             Self::Window(arg0) => f.debug_tuple("Window").field(arg0).finish(),
             Self::Custom(arg0) => f.debug_tuple("Custom").field(arg0).finish(),
             Self::Preview(arg0) => f.debug_tuple("Preview").field(arg0).finish(),
+            Self::Highlight(arg0) => f.debug_tuple("Highlight").field(arg0).finish(),
             Self::_GenericCatcher(arg0) => f.debug_tuple("_GenericCatcher").field(arg0).finish(),
         }
     }
 }
+
+/// Maximum number of previews per row of the overview grid.
+pub const PREVIEW_COLUMNS: usize = 4;
+/// Padding around each preview in the grid.
+const PREVIEW_PADDING: i32 = 10;
+
 /*
-This function generates a collection of renderable preview elements for all windows in a given space on a specific output.
+Lays out `count` previews in a grid of up to `PREVIEW_COLUMNS` columns filling the output.
+Returns the box of every preview, relative to the output, in logical coordinates.
 
-The primary purpose of this function is to create a "preview" of all the elements (like windows) that exist on a specific output.
-It arranges these elements in a grid layout, ensuring that each window fits within a small preview frame with padding between previews.
-This can be useful for features like an "overview mode" where users can see and select windows from a grid of previews.
+Rendering and hit testing both go through this, so a tap always lands on the preview it sees.
 */
-pub fn space_preview_elements<'a, R, C>(
-    renderer: &'a mut R,
-    space: &'a Space<WindowElement>,
-    output: &'a Output,
-) -> impl Iterator<Item = C> + 'a
-where
-    R: Renderer + ImportAll + ImportMem, // The renderer must support texture imports and memory imports
-    R::TextureId: Clone + 'static, // The texture ID must be clonable and have a static lifetime
-    C: From<CropRenderElement<RelocateRenderElement<RescaleRenderElement<WindowRenderElement<R>>>>> + 'a, // Complex conversion trait for creating preview elements
-{
-    // **1. Layout Constraints**
-    // The behavior for how each preview is constrained within its bounding box.
-    let constrain_behavior = ConstrainBehavior {
-        reference: ConstrainReference::BoundingBox, // The preview is constrained relative to its bounding box.
-        behavior: ConstrainScaleBehavior::Fit, // Ensure the preview scales to fit inside its container.
-        align: ConstrainAlign::CENTER, // Center-align the window in its preview box.
-    };
-
-    let preview_padding = 10; // Padding around each preview in the grid.
-
-    // **2. Calculate the total number of elements and space constraints**
-    let elements_on_space = space.elements_for_output(output).count(); // Total number of windows/elements in the space.
+pub fn preview_layout(output: &Output, count: usize) -> Vec<Rectangle<i32, Logical>> {
+    if count == 0 {
+        return Vec::new();
+    }
+
     let output_scale = output.current_scale().fractional_scale(); // Current fractional scale factor of the output.
     let output_transform = output.current_transform(); // Transformation applied to the output (like rotation, etc.).
-    
+
     let output_size = output
         .current_mode()
         .map(|mode| {
@@ -112,47 +104,103 @@ where
         })
         .unwrap_or_default(); // Default to (0,0) size if the mode is not available.
 
-    // **3. Calculate the number of rows and columns in the preview grid**
-    let max_elements_per_row = 4; // Maximum number of previews per row.
-    let elements_per_row = usize::min(elements_on_space, max_elements_per_row); // Use either the max or the total number of elements, whichever is smaller.
-    let rows = f64::ceil(elements_on_space as f64 / elements_per_row as f64); // Total number of rows needed to display all elements.
+    // Calculate the number of rows and columns in the preview grid
+    let elements_per_row = usize::min(count, PREVIEW_COLUMNS);
+    let rows = f64::ceil(count as f64 / elements_per_row as f64);
 
-    // **4. Calculate the size for each preview box**
+    // Calculate the size for each preview box
     let preview_size = Size::from((
-        f64::round(output_size.w / elements_per_row as f64) as i32 - preview_padding * 2, // Width of each preview box.
-        f64::round(output_size.h / rows) as i32 - preview_padding * 2, // Height of each preview box.
+        f64::round(output_size.w / elements_per_row as f64) as i32 - PREVIEW_PADDING * 2,
+        f64::round(output_size.h / rows) as i32 - PREVIEW_PADDING * 2,
     ));
 
-    // **5. Arrange and render each element as a preview**
-    space
-        .elements_for_output(output) // Get all elements on the given output.
-        .enumerate() // Enumerate to get index (used for row/column calculation) and element.
-        .flat_map(move |(element_index, window)| {
-            // **6. Calculate which row and column this element should be in**
-            let column = element_index % elements_per_row; // Column index of the preview (based on modulo of total per row).
-            let row = element_index / elements_per_row; // Row index (based on integer division).
-            
-            // **7. Calculate the position of this preview in the grid**
+    (0..count)
+        .map(|index| {
+            let column = index % elements_per_row;
+            let row = index / elements_per_row;
             let preview_location = Point::from((
-                preview_padding + (preview_padding + preview_size.w) * column as i32, // X position
-                preview_padding + (preview_padding + preview_size.h) * row as i32, // Y position
+                PREVIEW_PADDING + (PREVIEW_PADDING + preview_size.w) * column as i32,
+                PREVIEW_PADDING + (PREVIEW_PADDING + preview_size.h) * row as i32,
             ));
-            
-            // **8. Constrain the element to fit inside the preview box**
-            let constrain = Rectangle::from_loc_and_size(preview_location, preview_size); // The bounding box for this preview.
-            
-            // **9. Use the constrain logic to render the window as a preview**
-            constrain_space_element(
-                renderer, // The renderer responsible for drawing the element.
-                window, // The current window to be constrained.
-                preview_location, // Position of the preview in the grid.
-                1.0, // Scale factor for this preview (1.0 = no scaling).
-                output_scale, // Scale factor of the output.
-                constrain, // The constraint bounds (where the element must fit inside).
-                constrain_behavior, // Behavior for how the element is constrained.
-            )
+            Rectangle::from_loc_and_size(preview_location, preview_size)
+        })
+        .collect()
+}
+
+/*
+This function generates a collection of renderable preview elements for the windows of the overview on a specific output.
+
+The windows are arranged in the grid of `preview_layout`, each scaled to fit inside its preview frame.
+Previews are drawn from the window snapshots, so apps in the background that are not mapped
+in the space show up as well. A preview that is being swiped away follows the finger.
+*/
+pub fn space_preview_elements<'a, R, C>(
+    renderer: &'a mut R,
+    space: &'a Space<WindowElement>,
+    output: &'a Output,
+    overview: &'a Overview,
+) -> impl Iterator<Item = C> + 'a
+where
+    R: Renderer + ImportAll + ImportMem, // The renderer must support texture imports and memory imports
+    R::TextureId: Clone + 'static, // The texture ID must be clonable and have a static lifetime
+    C: From<CropRenderElement<TextureRenderElement<R::TextureId>>> + 'a,
+{
+    let output_scale = output.current_scale().fractional_scale();
+
+    let windows = overview.windows_on(space, output);
+    let layout = preview_layout(output, windows.len());
+
+    windows
+        .into_iter()
+        .zip(layout)
+        .flat_map(move |(window, mut frame)| {
+            frame.loc.y -= overview.swipe_offset(&window);
+
+            update_snapshot(renderer, &window);
+            snapshot_elements::<R::TextureId>(&window, frame, output_scale)
         })
+        .map(C::from)
+}
+
+/*
+Buffer of the highlight drawn behind the preview selected with the keyboard,
+kept in the output's user data so its damage is tracked between frames.
+*/
+#[derive(Default)]
+struct PreviewHighlight(RefCell<SolidColorBuffer>);
+
+/*
+Returns the highlight of the selected preview of the overview on this output, if it is shown here.
+*/
+fn preview_highlight_element(
+    space: &Space<WindowElement>,
+    output: &Output,
+    overview: &Overview,
+) -> Option<SolidColorRenderElement> {
+    let index = overview.selected_on(output)?;
+    let windows = overview.windows_on(space, output);
+    let window = windows.get(index)?;
+    let mut frame = *preview_layout(output, windows.len()).get(index)?;
+    frame.loc.y -= overview.swipe_offset(window);
+
+    let size = Size::from((frame.size.w + HIGHLIGHT_WIDTH * 2, frame.size.h + HIGHLIGHT_WIDTH * 2));
+    let location = frame.loc - Point::from((HIGHLIGHT_WIDTH, HIGHLIGHT_WIDTH));
+
+    output.user_data().insert_if_missing(PreviewHighlight::default);
+    let highlight = output.user_data().get::<PreviewHighlight>().unwrap();
+    let mut buffer = highlight.0.borrow_mut();
+    buffer.update(size, HIGHLIGHT_COLOR);
+
+    let scale = output.current_scale().fractional_scale();
+    Some(SolidColorRenderElement::from_buffer(
+        &buffer,
+        location.to_physical_precise_round(scale),
+        scale,
+        1.0,
+        Kind::Unspecified,
+    ))
 }
+
 /*
 Generates the render elements for an output, including fullscreen windows, previews, and space elements.
 
@@ -161,7 +209,7 @@ Generates the render elements for an output, including fullscreen windows, previ
 - `space`: A reference to the space containing the window elements.
 - `custom_elements`: A collection of custom render elements to be included in the output.
 - `renderer`: The renderer used to create the render elements.
-- `show_window_preview`: A flag indicating whether to generate window previews.
+- `overview`: The overview state, when it is open only the window previews are shown.
 
 # Returns
 - A tuple containing:
@@ -175,21 +223,49 @@ pub fn output_elements<R>(
     space: &Space<WindowElement>,
     custom_elements: impl IntoIterator<Item = CustomRenderElements<R>>,
     renderer: &mut R,
-    show_window_preview: bool,
+    overview: Option<&Overview>,
 ) -> (Vec<OutputRenderElements<R, WindowRenderElement<R>>>, [f32; 4])
 where
     R: Renderer + ImportAll + ImportMem,
     R::TextureId: Clone + 'static,
 {
-    if let Some(window) = output
+    if let Some(overview) = overview {
+        // The previews replace the whole desktop, the highlight goes below the selected one.
+        let mut elements = custom_elements
+            .into_iter()
+            .map(OutputRenderElements::from)
+            .collect::<Vec<_>>();
+        elements.extend(space_preview_elements(renderer, space, output, overview));
+        elements.extend(preview_highlight_element(space, output, overview).map(OutputRenderElements::Highlight));
+
+        (elements, CLEAR_COLOR)
+    } else if let Some(window) = output
         .user_data()
         .get::<FullscreenSurface>()
         .and_then(|f| f.get())
     {
         // Handle fullscreen window rendering
-        let scale = output.current_scale().fractional_scale().into();
-        let window_render_elements: Vec<WindowRenderElement<R>> =
-            AsRenderElements::<R>::render_elements(&window, renderer, (0, 0).into(), scale, 1.0);
+        let scale: Scale<f64> = output.current_scale().fractional_scale().into();
+        let mut window_render_elements: Vec<WindowRenderElement<R>> = Vec::new();
+
+        // A picture-in-picture tile stays on top, even of a fullscreen app.
+        let output_location = space.output_geometry(output).map(|geo| geo.loc).unwrap_or_default();
+        for pip in space.elements_for_output(output).filter(|w| w.is_pip()) {
+            let Some(location) = space.element_location(pip) else {
+                continue;
+            };
+            let location = (location - pip.geometry().loc - output_location).to_physical_precise_round(scale);
+            window_render_elements.extend(AsRenderElements::<R>::render_elements::<WindowRenderElement<R>>(
+                pip, renderer, location, scale, 1.0,
+            ));
+        }
+        window_render_elements.extend(AsRenderElements::<R>::render_elements::<WindowRenderElement<R>>(
+            &window,
+            renderer,
+            (0, 0).into(),
+            scale,
+            1.0,
+        ));
 
         let elements = custom_elements
             .into_iter()
@@ -203,16 +279,12 @@ where
         
         (elements, CLEAR_COLOR_FULLSCREEN)
     } else {
-        // Handle standard rendering with space and optional window previews
+        // Handle standard rendering with space
         let mut output_render_elements = custom_elements
             .into_iter()
             .map(OutputRenderElements::from)
             .collect::<Vec<_>>();
 
-        if show_window_preview && space.elements_for_output(output).count() > 0 {
-            output_render_elements.extend(space_preview_elements(renderer, space, output));
-        }
-
         let space_elements = smithay::desktop::space::space_render_elements::<_, WindowElement, _>(
             renderer,
             [space],
@@ -237,7 +309,7 @@ Renders the elements for an output using the damage tracker to optimize renderin
 - `renderer`: The renderer used to render the elements.
 - `damage_tracker`: Tracks damage to the output, allowing for optimized partial rendering.
 - `age`: The "age" of the damage, used to determine which areas to re-render.
-- `show_window_preview`: A flag indicating whether to render window previews.
+- `overview`: The overview state, if it is open.
 
 # Returns
 - A `RenderOutputResult`, containing information about the rendering result.
@@ -249,7 +321,7 @@ pub fn render_output<'a, 'd, R>(
     renderer: &'a mut R,
     damage_tracker: &'d mut OutputDamageTracker,
     age: usize,
-    show_window_preview: bool,
+    overview: Option<&Overview>,
 ) -> Result<RenderOutputResult<'d>, OutputDamageTrackerError<R>>
 where
     R: Renderer + ImportAll + ImportMem,
@@ -257,7 +329,7 @@ where
 {
     // Generate elements to be rendered and background clear color
     // Calls `output_elements` to gather all the elements that should be rendered on the output.
-    let (elements, clear_color) = output_elements(output, space, custom_elements, renderer, show_window_preview);
+    let (elements, clear_color) = output_elements(output, space, custom_elements, renderer, overview);
     
     // Render the output using the damage tracker, optimizing for only changed areas
     damage_tracker.render_output(renderer, age, &elements, clear_color)
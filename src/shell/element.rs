@@ -1,4 +1,9 @@
-use std::{borrow::Cow, time::Duration};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use smithay::{
     backend::renderer::{
@@ -6,7 +11,9 @@ use smithay::{
         ImportAll, ImportMem, Renderer, Texture,
     },
     desktop::{
-        space::SpaceElement, utils::OutputPresentationFeedback, Window, WindowSurface, WindowSurfaceType,
+        space::{RenderZindex, SpaceElement},
+        utils::OutputPresentationFeedback,
+        Window, WindowSurface, WindowSurfaceType,
     },
     output::Output,
     reexports::{
@@ -17,11 +24,31 @@ use smithay::{
     utils::{user_data::UserDataMap, IsAlive, Logical, Physical, Point, Scale},
     wayland::{compositor::SurfaceData as WlSurfaceData, dmabuf::DmabufFeedback, seat::WaylandFocus},
 };
-use crate::focus::PointerFocusTarget;
+use crate::{config::WindowRule, focus::PointerFocusTarget};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct WindowElement(pub Window);
 
+/*
+Identifier of a window that stays the same for its whole life, unlike its position in
+the window manager, and is never reused. Handy for app switchers and embedders.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WindowId(u64);
+
+impl WindowId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        WindowId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl std::fmt::Display for WindowId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl IsAlive for WindowElement {
     #[inline]
     fn alive(&self) -> bool {
@@ -45,7 +72,12 @@ impl SpaceElement for WindowElement {
     }
 
     fn z_index(&self) -> u8 {
-        SpaceElement::z_index(&self.0)
+        // Picture-in-picture tiles stay above every other window, but below panels.
+        if self.is_pip() {
+            RenderZindex::Shell as u8 + 1
+        } else {
+            SpaceElement::z_index(&self.0)
+        }
     }
 
     fn set_activate(&self, activated: bool) {
@@ -65,7 +97,65 @@ impl SpaceElement for WindowElement {
     }
 }
 
+/// Marks a window shown as a picture-in-picture tile
+#[derive(Default)]
+struct PictureInPicture(Cell<bool>);
+
+/// The window rules applied to a window, merged into one
+#[derive(Default)]
+struct AppliedRule(RefCell<WindowRule>);
+
 impl WindowElement {
+    /*
+    Returns the id of the window, it is assigned on the first call.
+    */
+    pub fn id(&self) -> WindowId {
+        self.user_data().insert_if_missing(WindowId::next);
+        *self.user_data().get::<WindowId>().unwrap()
+    }
+
+    /*
+    Whether the window is a picture-in-picture tile, drawn above the other windows
+    and even above a fullscreen app.
+    */
+    pub fn is_pip(&self) -> bool {
+        self.user_data()
+            .get::<PictureInPicture>()
+            .map(|pip| pip.0.get())
+            .unwrap_or(false)
+    }
+
+    pub fn set_pip(&self, pip: bool) {
+        self.user_data().insert_if_missing(PictureInPicture::default);
+        self.user_data().get::<PictureInPicture>().unwrap().0.set(pip);
+    }
+
+    /*
+    The window rule matching the app id and title of the window, as last applied by
+    `AuroraState::apply_window_rules`.
+    */
+    pub fn rule(&self) -> WindowRule {
+        self.user_data()
+            .get::<AppliedRule>()
+            .map(|rule| rule.0.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_rule(&self, rule: WindowRule) {
+        self.user_data().insert_if_missing(AppliedRule::default);
+        *self.user_data().get::<AppliedRule>().unwrap().0.borrow_mut() = rule;
+    }
+
+    /*
+    Opacity the window is drawn with, set by a window rule.
+    */
+    pub fn opacity(&self) -> f32 {
+        self.user_data()
+            .get::<AppliedRule>()
+            .and_then(|rule| rule.0.borrow().opacity)
+            .unwrap_or(1.0)
+    }
+
     /*
     **Finds the surface under a given point relative to the window.**
     
@@ -268,7 +358,7 @@ where
         scale: Scale<f64>,
         alpha: f32,
     ) -> Vec<C> {        
-        AsRenderElements::render_elements(&self.0, renderer, location, scale, alpha)
+        AsRenderElements::render_elements(&self.0, renderer, location, scale, alpha * self.opacity())
             .into_iter()
             .map(C::from)
             .collect()
@@ -0,0 +1,1230 @@
+use std::cell::RefCell;
+
+use smithay::{
+    desktop::space::SpaceElement,
+    input::{
+        pointer::{
+            AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
+            GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent,
+            GestureSwipeUpdateEvent, GrabStartData as PointerGrabStartData, MotionEvent, PointerGrab,
+            PointerInnerHandle, RelativeMotionEvent,
+        },
+        touch::{
+            DownEvent, GrabStartData as TouchGrabStartData, MotionEvent as TouchMotionEvent, OrientationEvent,
+            ShapeEvent, TouchGrab, TouchInnerHandle, UpEvent,
+        },
+    },
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{IsAlive, Logical, Point, Serial, Size},
+    wayland::{compositor::with_states, shell::xdg::SurfaceCachedState},
+};
+
+use crate::{
+    focus::PointerFocusTarget,
+    overview::TAP_SLOP,
+    shell::{FloatingGeometry, SurfaceData, WindowElement},
+    state::{AuroraState, Backend},
+};
+
+// ------------------- Move ------------------- //
+
+pub struct PointerMoveSurfaceGrab<BackendData: Backend + 'static> {
+    pub start_data: PointerGrabStartData<AuroraState<BackendData>>,
+    pub window: WindowElement,
+    pub initial_window_location: Point<i32, Logical>,
+}
+
+impl<BackendData: Backend> PointerGrab<AuroraState<BackendData>> for PointerMoveSurfaceGrab<BackendData> {
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // While the grab is active, no client has pointer focus
+        handle.motion(data, None, event);
+
+        let delta = event.location - self.start_data.location;
+        let new_location = (self.initial_window_location.to_f64() + delta).to_i32_round();
+        data.move_window(&self.window, new_location);
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+        if handle.current_pressed().is_empty() {
+            // No more buttons are pressed, release the grab.
+            handle.unset_grab(self, data, event.serial, event.time, true);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details)
+    }
+
+    fn frame(&mut self, data: &mut AuroraState<BackendData>, handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event)
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event)
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event)
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event)
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event)
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event)
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event)
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+pub struct TouchMoveSurfaceGrab<BackendData: Backend + 'static> {
+    pub start_data: TouchGrabStartData<AuroraState<BackendData>>,
+    pub window: WindowElement,
+    pub initial_window_location: Point<i32, Logical>,
+}
+
+impl<BackendData: Backend> TouchGrab<AuroraState<BackendData>> for TouchMoveSurfaceGrab<BackendData> {
+    fn down(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        _event: &DownEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn up(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &UpEvent,
+        seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        handle.up(data, event, seq);
+        handle.unset_grab(self, data);
+    }
+
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &TouchMotionEvent,
+        _seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        let delta = event.location - self.start_data.location;
+        let new_location = (self.initial_window_location.to_f64() + delta).to_i32_round();
+        data.move_window(&self.window, new_location);
+    }
+
+    fn frame(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _seq: Serial,
+    ) {
+    }
+
+    fn cancel(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        seq: Serial,
+    ) {
+        handle.cancel(data, seq);
+        handle.unset_grab(self, data);
+    }
+
+    fn shape(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ShapeEvent,
+        seq: Serial,
+    ) {
+        handle.shape(data, event, seq)
+    }
+
+    fn orientation(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &OrientationEvent,
+        seq: Serial,
+    ) {
+        handle.orientation(data, event, seq)
+    }
+
+    fn start_data(&self) -> &TouchGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+// ------------------- Resize ------------------- //
+
+bitflags::bitflags! {
+    /*
+    Edges of a window being resized, with the same values as `xdg_toplevel::ResizeEdge`.
+    */
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ResizeEdge: u32 {
+        const TOP = 0b0001;
+        const BOTTOM = 0b0010;
+        const LEFT = 0b0100;
+        const RIGHT = 0b1000;
+
+        const TOP_LEFT = Self::TOP.bits() | Self::LEFT.bits();
+        const BOTTOM_LEFT = Self::BOTTOM.bits() | Self::LEFT.bits();
+
+        const TOP_RIGHT = Self::TOP.bits() | Self::RIGHT.bits();
+        const BOTTOM_RIGHT = Self::BOTTOM.bits() | Self::RIGHT.bits();
+    }
+}
+
+impl From<xdg_toplevel::ResizeEdge> for ResizeEdge {
+    #[inline]
+    fn from(x: xdg_toplevel::ResizeEdge) -> Self {
+        Self::from_bits(x as u32).unwrap_or_else(Self::empty)
+    }
+}
+
+/*
+Information about the resize operation of a window.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeData {
+    /// The edges the surface is being resized from.
+    pub edges: ResizeEdge,
+    /// The initial window location.
+    pub initial_window_location: Point<i32, Logical>,
+    /// The initial window size (geometry width and height).
+    pub initial_window_size: Size<i32, Logical>,
+}
+
+/*
+State of the resize operation of a window, stored in its `SurfaceData`.
+
+The window has to stay anchored on the edges opposite to the dragged ones until the
+client committed a buffer for the last configure, so the resize state outlives the grab.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeState {
+    /// The surface is not being resized.
+    #[default]
+    NotResizing,
+    /// The surface is currently being resized.
+    Resizing(ResizeData),
+    /// The resize has finished, and the surface needs to ack the final configure.
+    WaitingForFinalAck(ResizeData, Serial),
+    /// The resize has finished, and the surface needs to commit its final state.
+    WaitingForCommit(ResizeData),
+}
+
+pub struct PointerResizeSurfaceGrab<BackendData: Backend + 'static> {
+    pub start_data: PointerGrabStartData<AuroraState<BackendData>>,
+    pub window: WindowElement,
+    pub edges: ResizeEdge,
+    pub initial_window_size: Size<i32, Logical>,
+    pub last_window_size: Size<i32, Logical>,
+}
+
+impl<BackendData: Backend> PointerGrab<AuroraState<BackendData>> for PointerResizeSurfaceGrab<BackendData> {
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // While the grab is active, no client has pointer focus
+        handle.motion(data, None, event);
+
+        // It is impossible to get `min_size` and `max_size` of dead toplevel, so we return early.
+        if !self.window.alive() {
+            handle.unset_grab(self, data, event.serial, event.time, true);
+            return;
+        }
+
+        let delta = event.location - self.start_data.location;
+        self.last_window_size = resize_window(&self.window, self.edges, self.initial_window_size, delta);
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+        if handle.current_pressed().is_empty() {
+            // No more buttons are pressed, release the grab.
+            handle.unset_grab(self, data, event.serial, event.time, true);
+
+            // If the window is dead, there is no need to finish the resize.
+            if self.window.alive() {
+                finish_resize(&self.window, self.last_window_size);
+            }
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details)
+    }
+
+    fn frame(&mut self, data: &mut AuroraState<BackendData>, handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event)
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event)
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event)
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event)
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event)
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event)
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event)
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+pub struct TouchResizeSurfaceGrab<BackendData: Backend + 'static> {
+    pub start_data: TouchGrabStartData<AuroraState<BackendData>>,
+    pub window: WindowElement,
+    pub edges: ResizeEdge,
+    pub initial_window_size: Size<i32, Logical>,
+    pub last_window_size: Size<i32, Logical>,
+}
+
+impl<BackendData: Backend> TouchGrab<AuroraState<BackendData>> for TouchResizeSurfaceGrab<BackendData> {
+    fn down(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        _event: &DownEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn up(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &UpEvent,
+        seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        handle.up(data, event, seq);
+        handle.unset_grab(self, data);
+
+        // If the window is dead, there is no need to finish the resize.
+        if self.window.alive() {
+            finish_resize(&self.window, self.last_window_size);
+        }
+    }
+
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &TouchMotionEvent,
+        _seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        // It is impossible to get `min_size` and `max_size` of dead toplevel, so we return early.
+        if !self.window.alive() {
+            handle.unset_grab(self, data);
+            return;
+        }
+
+        let delta = event.location - self.start_data.location;
+        self.last_window_size = resize_window(&self.window, self.edges, self.initial_window_size, delta);
+    }
+
+    fn frame(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _seq: Serial,
+    ) {
+    }
+
+    fn cancel(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        seq: Serial,
+    ) {
+        handle.cancel(data, seq);
+        handle.unset_grab(self, data);
+
+        if self.window.alive() {
+            finish_resize(&self.window, self.last_window_size);
+        }
+    }
+
+    fn shape(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ShapeEvent,
+        seq: Serial,
+    ) {
+        handle.shape(data, event, seq)
+    }
+
+    fn orientation(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &OrientationEvent,
+        seq: Serial,
+    ) {
+        handle.orientation(data, event, seq)
+    }
+
+    fn start_data(&self) -> &TouchGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+// ------------------- Divider ------------------- //
+
+/*
+Drags the divider between tiled windows, the window manager lays them out again on every motion.
+*/
+pub struct PointerDividerGrab<BackendData: Backend + 'static> {
+    pub start_data: PointerGrabStartData<AuroraState<BackendData>>,
+}
+
+impl<BackendData: Backend> PointerGrab<AuroraState<BackendData>> for PointerDividerGrab<BackendData> {
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // While the grab is active, no client has pointer focus
+        handle.motion(data, None, event);
+        data.window_manager.move_divider(event.location, &mut data.space);
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+        if handle.current_pressed().is_empty() {
+            // No more buttons are pressed, release the grab.
+            handle.unset_grab(self, data, event.serial, event.time, true);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details)
+    }
+
+    fn frame(&mut self, data: &mut AuroraState<BackendData>, handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event)
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event)
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event)
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event)
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event)
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event)
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event)
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+pub struct TouchDividerGrab<BackendData: Backend + 'static> {
+    pub start_data: TouchGrabStartData<AuroraState<BackendData>>,
+}
+
+impl<BackendData: Backend> TouchGrab<AuroraState<BackendData>> for TouchDividerGrab<BackendData> {
+    fn down(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        _event: &DownEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn up(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &UpEvent,
+        seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        handle.up(data, event, seq);
+        handle.unset_grab(self, data);
+    }
+
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &TouchMotionEvent,
+        _seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        data.window_manager.move_divider(event.location, &mut data.space);
+    }
+
+    fn frame(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _seq: Serial,
+    ) {
+    }
+
+    fn cancel(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        seq: Serial,
+    ) {
+        handle.cancel(data, seq);
+        handle.unset_grab(self, data);
+    }
+
+    fn shape(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ShapeEvent,
+        seq: Serial,
+    ) {
+        handle.shape(data, event, seq)
+    }
+
+    fn orientation(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &OrientationEvent,
+        seq: Serial,
+    ) {
+        handle.orientation(data, event, seq)
+    }
+
+    fn start_data(&self) -> &TouchGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+// ------------------- Picture-in-picture ------------------- //
+
+/*
+Drags the picture-in-picture tile around. Letting it go snaps it to a corner,
+unless it was merely tapped, which brings its app back fullscreen.
+*/
+pub struct PointerPipGrab<BackendData: Backend + 'static> {
+    pub start_data: PointerGrabStartData<AuroraState<BackendData>>,
+    pub window: WindowElement,
+    pub initial_window_location: Point<i32, Logical>,
+    pub last_location: Point<f64, Logical>,
+}
+
+impl<BackendData: Backend> PointerGrab<AuroraState<BackendData>> for PointerPipGrab<BackendData> {
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // While the grab is active, no client has pointer focus
+        handle.motion(data, None, event);
+
+        self.last_location = event.location;
+        data.drag_pip(&self.window, self.initial_window_location, event.location - self.start_data.location);
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+        if handle.current_pressed().is_empty() {
+            // No more buttons are pressed, release the grab.
+            handle.unset_grab(self, data, event.serial, event.time, true);
+            data.release_pip(&self.window, self.last_location - self.start_data.location);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details)
+    }
+
+    fn frame(&mut self, data: &mut AuroraState<BackendData>, handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event)
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event)
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event)
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event)
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event)
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event)
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event)
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut PointerInnerHandle<'_, AuroraState<BackendData>>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+pub struct TouchPipGrab<BackendData: Backend + 'static> {
+    pub start_data: TouchGrabStartData<AuroraState<BackendData>>,
+    pub window: WindowElement,
+    pub initial_window_location: Point<i32, Logical>,
+    pub last_location: Point<f64, Logical>,
+}
+
+impl<BackendData: Backend> TouchGrab<AuroraState<BackendData>> for TouchPipGrab<BackendData> {
+    fn down(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        _event: &DownEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn up(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &UpEvent,
+        seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        handle.up(data, event, seq);
+        handle.unset_grab(self, data);
+        data.release_pip(&self.window, self.last_location - self.start_data.location);
+    }
+
+    fn motion(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &TouchMotionEvent,
+        _seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+
+        self.last_location = event.location;
+        data.drag_pip(&self.window, self.initial_window_location, event.location - self.start_data.location);
+    }
+
+    fn frame(
+        &mut self,
+        _data: &mut AuroraState<BackendData>,
+        _handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        _seq: Serial,
+    ) {
+    }
+
+    fn cancel(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        seq: Serial,
+    ) {
+        handle.cancel(data, seq);
+        handle.unset_grab(self, data);
+        data.window_manager.pip_moved(&mut data.space);
+    }
+
+    fn shape(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &ShapeEvent,
+        seq: Serial,
+    ) {
+        handle.shape(data, event, seq)
+    }
+
+    fn orientation(
+        &mut self,
+        data: &mut AuroraState<BackendData>,
+        handle: &mut TouchInnerHandle<'_, AuroraState<BackendData>>,
+        event: &OrientationEvent,
+        seq: Serial,
+    ) {
+        handle.orientation(data, event, seq)
+    }
+
+    fn start_data(&self) -> &TouchGrabStartData<AuroraState<BackendData>> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut AuroraState<BackendData>) {}
+}
+
+/*
+Sends the window a configure for its new size while being dragged by the given edges,
+respecting the size limits of the client. Returns the requested size.
+*/
+fn resize_window(
+    window: &WindowElement,
+    edges: ResizeEdge,
+    initial_window_size: Size<i32, Logical>,
+    delta: Point<f64, Logical>,
+) -> Size<i32, Logical> {
+    let (mut dx, mut dy) = delta.into();
+
+    let mut new_window_width = initial_window_size.w;
+    let mut new_window_height = initial_window_size.h;
+
+    if edges.intersects(ResizeEdge::LEFT | ResizeEdge::RIGHT) {
+        if edges.intersects(ResizeEdge::LEFT) {
+            dx = -dx;
+        }
+        new_window_width = (initial_window_size.w as f64 + dx) as i32;
+    }
+
+    if edges.intersects(ResizeEdge::TOP | ResizeEdge::BOTTOM) {
+        if edges.intersects(ResizeEdge::TOP) {
+            dy = -dy;
+        }
+        new_window_height = (initial_window_size.h as f64 + dy) as i32;
+    }
+
+    let Some(toplevel) = window.0.toplevel() else {
+        return initial_window_size;
+    };
+
+    let (min_size, max_size) = with_states(toplevel.wl_surface(), |states| {
+        let mut guard = states.cached_state.get::<SurfaceCachedState>();
+        let data = guard.current();
+        (data.min_size, data.max_size)
+    });
+
+    let min_width = min_size.w.max(1);
+    let min_height = min_size.h.max(1);
+    let max_width = if max_size.w == 0 { i32::MAX } else { max_size.w };
+    let max_height = if max_size.h == 0 { i32::MAX } else { max_size.h };
+
+    let new_window_size = Size::from((
+        new_window_width.clamp(min_width, max_width.max(min_width)),
+        new_window_height.clamp(min_height, max_height.max(min_height)),
+    ));
+
+    toplevel.with_pending_state(|state| {
+        state.states.set(xdg_toplevel::State::Resizing);
+        state.size = Some(new_window_size);
+    });
+    toplevel.send_pending_configure();
+
+    new_window_size
+}
+
+/*
+Ends the resize of a window: the resizing state is removed and the window waits for the
+client to ack the final configure before it stops being anchored.
+*/
+fn finish_resize(window: &WindowElement, last_window_size: Size<i32, Logical>) {
+    let Some(toplevel) = window.0.toplevel() else {
+        return;
+    };
+
+    toplevel.with_pending_state(|state| {
+        state.states.unset(xdg_toplevel::State::Resizing);
+        state.size = Some(last_window_size);
+    });
+    let serial = toplevel.send_pending_configure();
+
+    with_states(toplevel.wl_surface(), |states| {
+        let mut data = states
+            .data_map
+            .get::<RefCell<SurfaceData>>()
+            .unwrap()
+            .borrow_mut();
+        if let ResizeState::Resizing(resize_data) = data.resize_state {
+            data.resize_state = match serial {
+                Some(serial) => ResizeState::WaitingForFinalAck(resize_data, serial),
+                None => ResizeState::WaitingForCommit(resize_data),
+            };
+        }
+    });
+}
+
+impl<BackendData: Backend> AuroraState<BackendData> {
+    /*
+    Follows the pointer or finger with the picture-in-picture tile.
+    */
+    fn drag_pip(&mut self, window: &WindowElement, initial_location: Point<i32, Logical>, delta: Point<f64, Logical>) {
+        if !window.alive() {
+            return;
+        }
+        let location = (initial_location.to_f64() + delta).to_i32_round();
+        self.space.map_element(window.clone(), location, false);
+    }
+
+    /*
+    The picture-in-picture tile was let go: a tap brings its app back fullscreen,
+    after a drag the window manager snaps it into place.
+    */
+    fn release_pip(&mut self, window: &WindowElement, delta: Point<f64, Logical>) {
+        let distance = (delta.x * delta.x + delta.y * delta.y).sqrt();
+        if distance <= TAP_SLOP {
+            self.window_manager.activate(window.id(), &mut self.space);
+            self.refocus_active_window();
+        } else {
+            self.window_manager.pip_moved(&mut self.space);
+        }
+    }
+
+    /*
+    Moves a floating window during a grab, remembering the location for the window manager.
+    */
+    fn move_window(&mut self, window: &WindowElement, location: Point<i32, Logical>) {
+        self.space.map_element(window.clone(), location, true);
+        if let Some(geometry) = self.space.element_geometry(window) {
+            FloatingGeometry::save(window, geometry);
+        }
+    }
+}
+
+/*
+Keeps a window that is being resized anchored on the edges opposite to the dragged ones,
+called on every commit of the window.
+*/
+pub fn handle_resize_commit<BackendData: Backend>(state: &mut AuroraState<BackendData>, window: &WindowElement) {
+    let Some(mut window_loc) = state.space.element_location(window) else {
+        return;
+    };
+    let Some(toplevel) = window.0.toplevel() else {
+        return;
+    };
+    let geometry = window.geometry();
+
+    let resize: Option<(Option<i32>, Option<i32>)> = with_states(toplevel.wl_surface(), |states| {
+        let data = states.data_map.get::<RefCell<SurfaceData>>()?;
+        let mut data = data.borrow_mut();
+
+        let new_loc = match data.resize_state {
+            ResizeState::Resizing(resize_data)
+            | ResizeState::WaitingForFinalAck(resize_data, _)
+            | ResizeState::WaitingForCommit(resize_data) => {
+                let ResizeData {
+                    edges,
+                    initial_window_location,
+                    initial_window_size,
+                } = resize_data;
+
+                let new_x = edges
+                    .intersects(ResizeEdge::LEFT)
+                    .then(|| initial_window_location.x + (initial_window_size.w - geometry.size.w));
+                let new_y = edges
+                    .intersects(ResizeEdge::TOP)
+                    .then(|| initial_window_location.y + (initial_window_size.h - geometry.size.h));
+                Some((new_x, new_y))
+            }
+            ResizeState::NotResizing => None,
+        };
+
+        // The final buffer has been committed, the resize is over.
+        if let ResizeState::WaitingForCommit(_) = data.resize_state {
+            data.resize_state = ResizeState::NotResizing;
+        }
+
+        new_loc
+    });
+
+    let Some((new_x, new_y)) = resize else {
+        return;
+    };
+    if let Some(new_x) = new_x {
+        window_loc.x = new_x;
+    }
+    if let Some(new_y) = new_y {
+        window_loc.y = new_y;
+    }
+    // Also remembers the new size, so the window manager does not undo the resize.
+    state.move_window(window, window_loc);
+}
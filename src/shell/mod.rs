@@ -25,24 +25,23 @@ use smithay::{
             CompositorState, SurfaceAttributes, TraversalAction,
         },
         dmabuf::get_dmabuf,
-        shell::{
-            wlr_layer::{
-                Layer, LayerSurface as WlrLayerSurface, LayerSurfaceData, WlrLayerShellHandler,
-                WlrLayerShellState,
-            },
-            xdg::XdgToplevelSurfaceData,
+        shell::wlr_layer::{
+            Layer, LayerSurface as WlrLayerSurface, LayerSurfaceData, WlrLayerShellHandler,
+            WlrLayerShellState,
         },
     },
 };
 
 
-use crate::window_manager::WindowManager;
+use crate::config::Config;
 use crate::ClientState;
 use crate::{state::Backend, AuroraState};
 
 pub use self::element::*;
+pub use self::grabs::*;
 
 mod element;
+mod grabs;
 mod xdg;
 
 /* 
@@ -51,12 +50,15 @@ Structure to store data about a surface, such as geometry.
 #[derive(Default)]
 pub struct SurfaceData {
     pub geometry: Option<Rectangle<i32, Logical>>,
+    pub resize_state: ResizeState,
 }
 
 impl<BackendData: Backend> AuroraState<BackendData> {
     pub fn window_for_surface(&self, surface: &WlSurface) -> Option<WindowElement> {
+        // Background windows are not mapped, so the window manager is searched as well.
         self.space
             .elements()
+            .chain(self.window_manager.windows())
             .find(|window| window.wl_surface().map(|s| &*s == surface).unwrap_or(false))
             .cloned()
     }
@@ -83,6 +85,27 @@ impl FullscreenSurface {
     }
 }
 
+/*
+Last geometry of a window while it was neither maximized nor fullscreen,
+stored in the window's user data and restored when it leaves those states.
+*/
+#[derive(Default)]
+pub struct FloatingGeometry(RefCell<Option<Rectangle<i32, Logical>>>);
+
+impl FloatingGeometry {
+    pub fn save(window: &WindowElement, geometry: Rectangle<i32, Logical>) {
+        window.user_data().insert_if_missing(FloatingGeometry::default);
+        *window.user_data().get::<FloatingGeometry>().unwrap().0.borrow_mut() = Some(geometry);
+    }
+
+    pub fn get(window: &WindowElement) -> Option<Rectangle<i32, Logical>> {
+        window
+            .user_data()
+            .get::<FloatingGeometry>()
+            .and_then(|geometry| *geometry.0.borrow())
+    }
+}
+
 /*
 Implementation of the `CompositorHandler` for the `AuroraState` struct.
 The CompositorHandler defines how the compositor state, client compositor state,
@@ -196,9 +219,21 @@ impl<BackendData: Backend> CompositorHandler for AuroraState<BackendData> {
                             .take()
                     });
 
+                    // Background windows are not in the space, so they have no location to offset.
                     if let Some(buffer_offset) = buffer_offset {
-                        let current_loc = self.space.element_location(&window).unwrap();
-                        self.space.map_element(window, current_loc + buffer_offset, false);
+                        if let Some(current_loc) = self.space.element_location(&window) {
+                            self.space.map_element(window.clone(), current_loc + buffer_offset, false);
+                        }
+                    }
+
+                    handle_resize_commit(self, &window);
+
+                    // Clients set their app id and title before the first commit, so the window
+                    // rules are applied right before the initial configure. It is sent from here,
+                    // so windows a rule put in the background get it as well.
+                    if let Some(toplevel) = window.0.toplevel().filter(|t| !t.is_initial_configure_sent()) {
+                        self.apply_window_rules(&window);
+                        toplevel.send_configure();
                     }
                 }
             }
@@ -297,27 +332,6 @@ fn ensure_initial_configure(surface: &WlSurface, space: &Space<WindowElement>, p
         |_, _, _| true,
     );
 
-    if let Some(window) = space
-        .elements()
-        .find(|window| window.wl_surface().map(|s| &*s == surface).unwrap_or(false))
-        .cloned()
-    {
-        if let Some(toplevel) = window.0.toplevel() {
-            let initial_configure_sent = with_states(surface, |states| {
-                states
-                    .data_map
-                    .get::<XdgToplevelSurfaceData>()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .initial_configure_sent
-            });
-            if !initial_configure_sent {
-                toplevel.send_configure();
-            }
-        }
-    }
-
     if let Some(popup) = popups.find_popup(surface) {
         let popup = match popup {
             PopupKind::Xdg(ref popup) => popup,
@@ -359,6 +373,17 @@ fn ensure_initial_configure(surface: &WlSurface, space: &Space<WindowElement>, p
     };
 }
 
+/*
+The part of an output not covered by exclusive layer surfaces (eg. panels), in global
+coordinates. Windows are placed and laid out in there.
+*/
+pub fn non_exclusive_zone(space: &Space<WindowElement>, output: &Output) -> Option<Rectangle<i32, Logical>> {
+    let geo = space.output_geometry(output)?;
+    let map = layer_map_for_output(output);
+    let zone = map.non_exclusive_zone();
+    Some(Rectangle::from_loc_and_size(geo.loc + zone.loc, zone.size))
+}
+
 fn place_new_window(
     space: &mut Space<WindowElement>,
     pointer_location: Point<f64, Logical>,
@@ -375,12 +400,7 @@ fn place_new_window(
         .or_else(|| space.outputs().next())
         .cloned();
     let output_geometry = output
-        .and_then(|o| {
-            let geo = space.output_geometry(&o)?;
-            let map = layer_map_for_output(&o);
-            let zone = map.non_exclusive_zone();
-            Some(Rectangle::from_loc_and_size(geo.loc + zone.loc, zone.size))
-        })
+        .and_then(|o| non_exclusive_zone(space, &o))
         .unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), (800, 800)));
 
     // set the initial toplevel bounds
@@ -402,29 +422,37 @@ fn place_new_window(
     space.map_element(window.clone(), (x, y), activate);
 }
 
-pub fn fixup_positions(space: &mut Space<WindowElement>, window_manager: &mut WindowManager, pointer_location: Point<f64, Logical>) {
-    // fixup outputs
+/*
+Lines up the outputs again and brings back windows that ended up outside of every output.
+Laying out the windows is left to the window manager, called by the caller with the right hook.
+*/
+pub fn fixup_positions(
+    space: &mut Space<WindowElement>,
+    config: &Config,
+    pointer_location: Point<f64, Logical>,
+) {
+    // fixup outputs, the ones without a configured position are lined up left to right
     let mut offset = Point::<i32, Logical>::from((0, 0));
     for output in space.outputs().cloned().collect::<Vec<_>>().into_iter() {
         let size = space
             .output_geometry(&output)
             .map(|geo| geo.size)
             .unwrap_or_else(|| Size::from((0, 0)));
-        space.map_output(&output, offset);
+        match config.output(&output.name()).and_then(|c| c.position()) {
+            Some(position) => space.map_output(&output, position),
+            None => {
+                space.map_output(&output, offset);
+                offset.x += size.w;
+            }
+        }
         layer_map_for_output(&output).arrange();
-        offset.x += size.w;
     }
 
     // fixup windows
     let mut orphaned_windows = Vec::new();
     let outputs = space
         .outputs()
-        .flat_map(|o| {
-            let geo = space.output_geometry(o)?;
-            let map = layer_map_for_output(o);
-            let zone = map.non_exclusive_zone();
-            Some(Rectangle::from_loc_and_size(geo.loc + zone.loc, zone.size))
-        })
+        .flat_map(|o| non_exclusive_zone(space, o))
         .collect::<Vec<_>>();
 
     for window in space.elements() {
@@ -442,8 +470,4 @@ pub fn fixup_positions(space: &mut Space<WindowElement>, window_manager: &mut Wi
     for window in orphaned_windows.into_iter() {
         place_new_window(space, pointer_location, &window, false);
     }
-
-    // Fixup apps???
-    window_manager.refresh_geometry(space);
-    
 }
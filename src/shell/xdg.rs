@@ -1,14 +1,31 @@
+use std::{cell::RefCell, time::Duration};
+
 use smithay::{
 
-    desktop::Window, input::Seat, reexports::{
+    desktop::{
+        find_popup_root_surface, get_popup_toplevel_coords, layer_map_for_output, space::SpaceElement,
+        PopupKeyboardGrab, PopupKind, PopupPointerGrab, PopupUngrabStrategy, Window, WindowSurfaceType,
+    }, input::{pointer::Focus, Seat}, output::Output, reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel,
-        wayland_server:: protocol::{wl_output, wl_seat, wl_surface::WlSurface},
-    }, utils::Serial, wayland::shell::xdg::{Configure, PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState}
+        calloop::timer::{TimeoutAction, Timer},
+        wayland_server::{backend::DisconnectReason, protocol::{wl_output, wl_seat, wl_surface::WlSurface}, Resource},
+    }, utils::{IsAlive, Logical, Point, Rectangle, Serial}, wayland::{compositor::with_states, seat::WaylandFocus, shell::xdg::{Configure, PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState, XdgToplevelSurfaceData}}
+};
+
+use crate::{
+    config::WindowLayoutRule,
+    focus::{KeyboardFocusTarget, PointerFocusTarget},
+    state::{AuroraState, Backend},
+    window_manager::is_floating,
 };
 
-use crate::state::{AuroraState, Backend};
+use super::{
+    FloatingGeometry, PointerMoveSurfaceGrab, PointerResizeSurfaceGrab, ResizeData, ResizeEdge, ResizeState,
+    SurfaceData, TouchMoveSurfaceGrab, TouchResizeSurfaceGrab, WindowElement, WindowId,
+};
 
-use super::WindowElement;
+/// How long a client gets to close a window before it is killed
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /* 
 Implements the **XDG Shell protocol** for the Wayland compositor. 
@@ -34,45 +51,557 @@ impl<BackendData: Backend> XdgShellHandler for AuroraState<BackendData> {
 
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
         let window = WindowElement(Window::new_wayland_window(surface.clone()));
-        self.window_manager.insert_window(window.clone());
-        self.space.map_element(window, (0, 0), false);
+        self.window_manager.new_toplevel(window, &mut self.space);
+    }
+
+    fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        self.window_manager.toplevel_destroyed(&surface, &mut self.space);
+    }
+
+    fn app_id_changed(&mut self, surface: ToplevelSurface) {
+        self.window_rules_changed(&surface);
+    }
+
+    fn title_changed(&mut self, surface: ToplevelSurface) {
+        self.window_rules_changed(&surface);
+    }
+
+    fn new_popup(&mut self, surface: PopupSurface, positioner: PositionerState) {
+        surface.with_pending_state(|state| {
+            state.geometry = positioner.get_geometry();
+        });
+        self.unconstrain_popup(&surface);
 
-        self.window_manager.refresh_geometry(&mut self.space);
+        if let Err(err) = self.popups.track_popup(PopupKind::from(surface)) {
+            tracing::warn!("Failed to track popup: {}", err);
+        }
     }
 
-    fn toplevel_destroyed(&mut self, _surface: ToplevelSurface) {
-        self.window_manager.refresh_geometry(&mut self.space);
+    fn reposition_request(&mut self, surface: PopupSurface, positioner: PositionerState, token: u32) {
+        surface.with_pending_state(|state| {
+            state.geometry = positioner.get_geometry();
+            state.positioner = positioner;
+        });
+        self.unconstrain_popup(&surface);
+        surface.send_repositioned(token);
     }
 
-    fn new_popup(&mut self, _surface: PopupSurface, _positioner: PositionerState) {
+    fn move_request(&mut self, surface: ToplevelSurface, seat: wl_seat::WlSeat, serial: Serial) {
+        let Some(seat) = Seat::from_resource(&seat) else {
+            return;
+        };
+        self.move_request_xdg(&surface, &seat, serial)
     }
 
-    fn reposition_request(&mut self, _surface: PopupSurface, _positioner: PositionerState, _token: u32) {
+    fn resize_request(
+        &mut self,
+        surface: ToplevelSurface,
+        seat: wl_seat::WlSeat,
+        serial: Serial,
+        edges: xdg_toplevel::ResizeEdge,
+    ) {
+        let Some(seat) = Seat::from_resource(&seat) else {
+            return;
+        };
+        let Some(window) = self.floating_window_for_grab(&surface) else {
+            return;
+        };
+
+        let geometry = window.geometry();
+        let Some(loc) = self.space.element_location(&window) else {
+            return;
+        };
+        let edges = ResizeEdge::from(edges);
+
+        // The window stays anchored on the opposite edges until the client committed the final size.
+        let resize_data = ResizeData {
+            edges,
+            initial_window_location: loc,
+            initial_window_size: geometry.size,
+        };
+        let start_resize = || {
+            with_states(surface.wl_surface(), |states| {
+                states
+                    .data_map
+                    .get::<RefCell<SurfaceData>>()
+                    .unwrap()
+                    .borrow_mut()
+                    .resize_state = ResizeState::Resizing(resize_data);
+            });
+            surface.with_pending_state(|state| {
+                state.states.set(xdg_toplevel::State::Resizing);
+            });
+            surface.send_pending_configure();
+        };
+
+        let touch = seat.get_touch().unwrap();
+        if touch.has_grab(serial) {
+            let Some(start_data) = touch.grab_start_data() else {
+                return;
+            };
+            if !grab_started_on(&start_data.focus, &surface) {
+                return;
+            }
+
+            start_resize();
+            let grab = TouchResizeSurfaceGrab {
+                start_data,
+                window,
+                edges,
+                initial_window_size: geometry.size,
+                last_window_size: geometry.size,
+            };
+            touch.set_grab(self, grab, serial);
+            return;
+        }
 
+        let pointer = seat.get_pointer().unwrap();
+        if !pointer.has_grab(serial) {
+            return;
+        }
+        let Some(start_data) = pointer.grab_start_data() else {
+            return;
+        };
+        if !grab_started_on(&start_data.focus, &surface) {
+            return;
+        }
+
+        start_resize();
+        let grab = PointerResizeSurfaceGrab {
+            start_data,
+            window,
+            edges,
+            initial_window_size: geometry.size,
+            last_window_size: geometry.size,
+        };
+        pointer.set_grab(self, grab, serial, Focus::Clear);
     }
 
-    fn move_request(&mut self, _surface: ToplevelSurface, _seat: wl_seat::WlSeat, _serial: Serial) {
+    fn ack_configure(&mut self, surface: WlSurface, configure: Configure) {
+        let Configure::Toplevel(configure) = configure else {
+            return;
+        };
+
+        // Once the final configure of a resize is acked, the next commit ends the resize.
+        with_states(&surface, |states| {
+            let Some(data) = states.data_map.get::<RefCell<SurfaceData>>() else {
+                return;
+            };
+            let mut data = data.borrow_mut();
+            if let ResizeState::WaitingForFinalAck(resize_data, serial) = data.resize_state {
+                if configure.serial >= serial {
+                    data.resize_state = ResizeState::WaitingForCommit(resize_data);
+                }
+            }
+        });
+    }
 
+    fn fullscreen_request(&mut self, surface: ToplevelSurface, wl_output: Option<wl_output::WlOutput>) {
+        // The window goes to the requested output first, the policy lays it out fullscreen there.
+        let output = wl_output.as_ref().and_then(Output::from_resource);
+        let window = self.window_for_surface(surface.wl_surface());
+        if let (Some(output), Some(window)) = (output, window) {
+            self.window_manager.move_to_output(&window, &output, &mut self.space);
+        }
+        self.set_toplevel_state(&surface, xdg_toplevel::State::Fullscreen, true);
     }
 
-    fn resize_request(&mut self, _surface: ToplevelSurface, _seat: wl_seat::WlSeat, _serial: Serial, _edges: xdg_toplevel::ResizeEdge) {}
+    fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
+        self.set_toplevel_state(&surface, xdg_toplevel::State::Fullscreen, false);
+    }
 
-    fn ack_configure(&mut self, _surface: WlSurface, _configure: Configure) {
+    fn maximize_request(&mut self, surface: ToplevelSurface) {
+        self.set_toplevel_state(&surface, xdg_toplevel::State::Maximized, true);
+    }
 
+    fn unmaximize_request(&mut self, surface: ToplevelSurface) {
+        self.set_toplevel_state(&surface, xdg_toplevel::State::Maximized, false);
     }
 
-    fn fullscreen_request(&mut self, _surface: ToplevelSurface, mut _wl_output: Option<wl_output::WlOutput>) { }
+    fn grab(&mut self, surface: PopupSurface, seat: wl_seat::WlSeat, serial: Serial) {
+        let Some(seat) = Seat::from_resource(&seat) else {
+            return;
+        };
+        let kind = PopupKind::Xdg(surface);
+        let Some(root) = find_popup_root_surface(&kind)
+            .ok()
+            .and_then(|root| self.keyboard_target_for_root(&root))
+        else {
+            return;
+        };
 
-    fn unfullscreen_request(&mut self, _surface: ToplevelSurface) { }
+        let Ok(mut grab) = self.popups.grab_popup(root, kind, &seat, serial) else {
+            return;
+        };
 
-    fn maximize_request(&mut self, _surface: ToplevelSurface) { }
+        // The grab has to be started from an input event the client received, like a click or tap.
+        if let Some(keyboard) = seat.get_keyboard() {
+            if keyboard.is_grabbed()
+                && !(keyboard.has_grab(serial) || keyboard.has_grab(grab.previous_serial().unwrap_or(serial)))
+            {
+                grab.ungrab(PopupUngrabStrategy::All);
+                return;
+            }
+            keyboard.set_focus(self, grab.current_grab(), serial);
+            keyboard.set_grab(self, PopupKeyboardGrab::new(&grab), serial);
+        }
 
-    fn unmaximize_request(&mut self, _surface: ToplevelSurface) {}
+        if let Some(pointer) = seat.get_pointer() {
+            if pointer.is_grabbed()
+                && !(pointer.has_grab(serial) || pointer.has_grab(grab.previous_serial().unwrap_or_else(|| grab.serial())))
+            {
+                grab.ungrab(PopupUngrabStrategy::All);
+                return;
+            }
+            pointer.set_grab(self, PopupPointerGrab::new(&grab), serial, Focus::Keep);
+        }
 
-    fn grab(&mut self, _surface: PopupSurface, _seat: wl_seat::WlSeat, _serial: Serial) { }
+        self.popup_grab = Some(grab);
+    }
 }
 
 impl<BackendData: Backend> AuroraState<BackendData> {
-    pub fn move_request_xdg(&mut self, _surface: &ToplevelSurface, _seat: &Seat<Self>, _serial: Serial) {
+    /*
+    Sets or unsets the fullscreen or maximized state of a window and lets the window manager
+    lay it out again. The geometry the window had before is kept, so it can be restored.
+    */
+    fn set_toplevel_state(&mut self, surface: &ToplevelSurface, state: xdg_toplevel::State, enabled: bool) {
+        let Some(window) = self.window_for_surface(surface.wl_surface()) else {
+            return;
+        };
+
+        let (changed, floating) = surface.with_pending_state(|pending| {
+            (
+                pending.states.contains(state) != enabled,
+                !pending.states.contains(xdg_toplevel::State::Fullscreen)
+                    && !pending.states.contains(xdg_toplevel::State::Maximized),
+            )
+        });
+        if !changed {
+            // The protocol demands a configure in response, even if nothing changed.
+            if surface.is_initial_configure_sent() {
+                surface.send_configure();
+            }
+            return;
+        }
+
+        if enabled && floating {
+            if let Some(geometry) = self.space.element_geometry(&window) {
+                FloatingGeometry::save(&window, geometry);
+            }
+        }
+
+        surface.with_pending_state(|pending| {
+            if enabled {
+                pending.states.set(state);
+            } else {
+                pending.states.unset(state);
+            }
+        });
+        self.window_manager.toplevel_state_changed(&window, &mut self.space);
+
+        // Background windows are not laid out by the window manager, send them their new state right away.
+        if surface.is_initial_configure_sent() {
+            surface.send_pending_configure();
+        }
+    }
+
+    /*
+    Applies the window rules again after the app id or title of a mapped window changed,
+    eg. a browser that puts the page title into its own.
+    Before the first configure there is nothing to do, the rules are applied on the first commit.
+    */
+    fn window_rules_changed(&mut self, surface: &ToplevelSurface) {
+        if !surface.is_initial_configure_sent() {
+            return;
+        }
+        if let Some(window) = self.window_for_surface(surface.wl_surface()) {
+            self.apply_window_rules(&window);
+        }
     }
+
+    /*
+    Resolves the window rules matching the app id and title of a window and applies them.
+
+    Called right before the initial configure, with the app id and title the client set up,
+    and whenever they change. The size is only set before the initial configure, the output
+    and workspace only when the window opens or a rule changed them, so the user can still
+    move the window around.
+    A window opened with `no_focus` starts behind the active app.
+    */
+    pub fn apply_window_rules(&mut self, window: &WindowElement) {
+        let Some(toplevel) = window.0.toplevel() else {
+            return;
+        };
+        let (app_id, title) = with_states(toplevel.wl_surface(), |states| {
+            let data = states.data_map.get::<XdgToplevelSurfaceData>().unwrap().lock().unwrap();
+            (data.app_id.clone(), data.title.clone())
+        });
+        let rule = self.config.window.rule_for(app_id.as_deref(), title.as_deref());
+        let initial = !toplevel.is_initial_configure_sent();
+        let previous = window.rule();
+        if rule == previous && !initial {
+            return;
+        }
+        window.set_rule(rule.clone());
+
+        if rule.layout != previous.layout || initial {
+            if let Some(layout) = rule.layout {
+                if layout != WindowLayoutRule::Floating && !initial && is_floating(window) {
+                    if let Some(geometry) = self.space.element_geometry(window) {
+                        FloatingGeometry::save(window, geometry);
+                    }
+                }
+                toplevel.with_pending_state(|state| {
+                    state.states.unset(xdg_toplevel::State::Fullscreen);
+                    state.states.unset(xdg_toplevel::State::Maximized);
+                    match layout {
+                        WindowLayoutRule::Fullscreen => {
+                            state.states.set(xdg_toplevel::State::Fullscreen);
+                        }
+                        WindowLayoutRule::Maximized => {
+                            state.states.set(xdg_toplevel::State::Maximized);
+                        }
+                        WindowLayoutRule::Floating => {}
+                    }
+                });
+            }
+        }
+        if let Some(mode) = rule.decoration_mode {
+            toplevel.with_pending_state(|state| {
+                state.decoration_mode = Some(mode.into());
+            });
+        }
+        if let Some(size) = rule.size.filter(|_| initial) {
+            toplevel.with_pending_state(|state| {
+                state.size = Some(size.into());
+            });
+        }
+
+        let output = rule
+            .output
+            .as_ref()
+            .filter(|_| initial || rule.output != previous.output)
+            .and_then(|name| self.space.outputs().find(|output| output.name() == **name).cloned());
+        if let Some(output) = output {
+            self.window_manager.move_to_output(window, &output, &mut self.space);
+        }
+        if let Some(workspace) = rule.workspace.filter(|_| initial || rule.workspace != previous.workspace) {
+            self.window_manager.move_to_workspace(window, workspace, &mut self.space);
+        }
+        self.window_manager.toplevel_state_changed(window, &mut self.space);
+
+        // The policy made the new window active, the app the user was on gets the focus back.
+        if initial && rule.no_focus() {
+            self.window_manager.activate_previous(window, &mut self.space);
+            self.refocus_active_window();
+        }
+
+        if !initial {
+            toplevel.send_pending_configure();
+        }
+    }
+
+    /*
+    Asks the client of a window to close it. Clients that do not comply within
+    `CLOSE_TIMEOUT` (eg. because they hang) are disconnected.
+    Returns false when there is no window with the given id.
+    */
+    pub fn close_window(&mut self, id: WindowId) -> bool {
+        let Some(window) = self.window_manager.window(id).cloned() else {
+            return false;
+        };
+        let Some(toplevel) = window.0.toplevel() else {
+            return false;
+        };
+        tracing::info!(%id, "Closing window");
+        toplevel.send_close();
+
+        let Some(client) = toplevel.wl_surface().client() else {
+            return true;
+        };
+        let result = self
+            .handle
+            .insert_source(Timer::from_duration(CLOSE_TIMEOUT), move |_, _, state| {
+                if window.alive() {
+                    tracing::warn!(%id, "Window did not close in time, killing its client");
+                    state
+                        .display_handle
+                        .backend_handle()
+                        .kill_client(client.id(), DisconnectReason::ConnectionClosed);
+                }
+                TimeoutAction::Drop
+            });
+        if let Err(err) = result {
+            tracing::warn!("Failed to schedule the close timeout: {}", err);
+        }
+        true
+    }
+
+    /*
+    Tells apps whether they are shown. Windows the window manager did not map are in the
+    background: they lose the activated state and are suspended, so they can stop animating.
+    Apps listed in `window.run_in_background` (eg. music or navigation) are never suspended.
+
+    Only changed states are sent, so this is cheap enough to run on every loop iteration.
+    */
+    pub fn update_background_states(&mut self) {
+        for window in self.window_manager.windows() {
+            let Some(toplevel) = window.0.toplevel() else {
+                continue;
+            };
+            let background = self.space.element_location(window).is_none();
+            let app_id = with_states(toplevel.wl_surface(), |states| {
+                states
+                    .data_map
+                    .get::<XdgToplevelSurfaceData>()
+                    .and_then(|data| data.lock().unwrap().app_id.clone())
+            });
+            let suspend = background
+                && toplevel.xdg_toplevel().version() >= xdg_toplevel::STATE_SUSPENDED_SINCE
+                && !app_id.is_some_and(|app_id| self.config.window.runs_in_background(&app_id));
+
+            let changed = toplevel.with_pending_state(|state| {
+                let mut changed = if suspend {
+                    state.states.set(xdg_toplevel::State::Suspended)
+                } else {
+                    state.states.unset(xdg_toplevel::State::Suspended)
+                };
+                if background {
+                    changed |= state.states.unset(xdg_toplevel::State::Activated);
+                }
+                changed
+            });
+            if changed && toplevel.is_initial_configure_sent() {
+                toplevel.send_pending_configure();
+            }
+        }
+    }
+
+    pub fn move_request_xdg(&mut self, surface: &ToplevelSurface, seat: &Seat<Self>, serial: Serial) {
+        let Some(window) = self.floating_window_for_grab(surface) else {
+            return;
+        };
+        let Some(initial_window_location) = self.space.element_location(&window) else {
+            return;
+        };
+
+        let touch = seat.get_touch().unwrap();
+        if touch.has_grab(serial) {
+            let Some(start_data) = touch.grab_start_data() else {
+                return;
+            };
+            if !grab_started_on(&start_data.focus, surface) {
+                return;
+            }
+
+            let grab = TouchMoveSurfaceGrab {
+                start_data,
+                window,
+                initial_window_location,
+            };
+            touch.set_grab(self, grab, serial);
+            return;
+        }
+
+        let pointer = seat.get_pointer().unwrap();
+        if !pointer.has_grab(serial) {
+            return;
+        }
+        let Some(start_data) = pointer.grab_start_data() else {
+            return;
+        };
+        if !grab_started_on(&start_data.focus, surface) {
+            return;
+        }
+
+        let grab = PointerMoveSurfaceGrab {
+            start_data,
+            window,
+            initial_window_location,
+        };
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+    }
+
+    /*
+    Adjusts the popup geometry (flipping, sliding or resizing it, as the positioner allows)
+    so it stays on the output of its parent.
+    */
+    fn unconstrain_popup(&self, popup: &PopupSurface) {
+        let kind = PopupKind::Xdg(popup.clone());
+        let Ok(root) = find_popup_root_surface(&kind) else {
+            return;
+        };
+        let Some((root_loc, output_geo)) = self.popup_root_placement(&root) else {
+            return;
+        };
+
+        // The positioner works relative to the parent, so the output is moved into its coordinates.
+        let mut target = output_geo;
+        target.loc -= get_popup_toplevel_coords(&kind);
+        target.loc -= root_loc;
+
+        popup.with_pending_state(|state| {
+            state.geometry = state.positioner.get_unconstrained_geometry(target);
+        });
+    }
+
+    /*
+    Returns the global location of the surface at the root of a popup chain, a window or
+    a layer surface, along with the geometry of the output it is shown on.
+    */
+    fn popup_root_placement(&self, root: &WlSurface) -> Option<(Point<i32, Logical>, Rectangle<i32, Logical>)> {
+        if let Some(window) = self.window_for_surface(root) {
+            let window_geo = self.space.element_geometry(&window)?;
+            let output = self
+                .space
+                .outputs_for_element(&window)
+                .into_iter()
+                .next()
+                .or_else(|| self.space.outputs().next().cloned())?;
+            return Some((window_geo.loc, self.space.output_geometry(&output)?));
+        }
+
+        self.space.outputs().find_map(|output| {
+            let map = layer_map_for_output(output);
+            let layer = map.layer_for_surface(root, WindowSurfaceType::TOPLEVEL)?;
+            let output_geo = self.space.output_geometry(output)?;
+            let layer_geo = map.layer_geometry(layer)?;
+            Some((output_geo.loc + layer_geo.loc, output_geo))
+        })
+    }
+
+    fn keyboard_target_for_root(&self, root: &WlSurface) -> Option<KeyboardFocusTarget> {
+        if let Some(window) = self.window_for_surface(root) {
+            return Some(window.into());
+        }
+
+        self.space.outputs().find_map(|output| {
+            let map = layer_map_for_output(output);
+            map.layer_for_surface(root, WindowSurfaceType::TOPLEVEL)
+                .cloned()
+                .map(KeyboardFocusTarget::LayerSurface)
+        })
+    }
+
+    /*
+    Returns the mapped window of a toplevel that may be moved or resized by the user.
+    Maximized, fullscreen and tiled windows are laid out by the window manager, so they are left alone.
+    */
+    fn floating_window_for_grab(&self, surface: &ToplevelSurface) -> Option<WindowElement> {
+        let window = self.window_for_surface(surface.wl_surface())?;
+        self.space.element_location(&window)?;
+        is_floating(&window).then_some(window)
+    }
+}
+
+/*
+Whether the implicit grab of a pointer button or touch point started on the surface,
+so clients can only start a move or resize with their own input.
+*/
+fn grab_started_on(focus: &Option<(PointerFocusTarget, Point<f64, Logical>)>, surface: &ToplevelSurface) -> bool {
+    focus
+        .as_ref()
+        .map(|(focus, _)| focus.same_client_as(&surface.wl_surface().id()))
+        .unwrap_or(false)
 }
\ No newline at end of file
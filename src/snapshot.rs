@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+
+use smithay::{
+    backend::renderer::{
+        element::{
+            texture::{TextureBuffer, TextureRenderElement},
+            utils::CropRenderElement,
+            Kind,
+        },
+        utils::{import_surface_tree, CommitCounter, RendererSurfaceStateUserData},
+        ImportAll, Renderer,
+    },
+    desktop::{space::SpaceElement, Space},
+    output::Output,
+    reexports::wayland_server::{backend::ObjectId, Resource},
+    utils::{Logical, Point, Rectangle, Size},
+    wayland::compositor::{with_surface_tree_downward, TraversalAction},
+};
+
+use crate::{shell::WindowElement, window_manager::WindowManager};
+
+/*
+The last frame of a window, used to draw its preview in the overview.
+
+Apps in the background are not mapped in the space, so nothing else renders them. The textures
+of their surfaces are kept here and refreshed on every repaint of their output, which also keeps
+the preview around when the client releases its buffers. A surface only gets a new texture
+when it committed since the last snapshot.
+*/
+pub struct WindowSnapshot<T> {
+    /// Window geometry at the time of the snapshot, relative to the toplevel surface
+    geometry: Rectangle<i32, Logical>,
+    /// The surfaces of the window, topmost first
+    surfaces: Vec<SnapshotSurface<T>>,
+}
+
+struct SnapshotSurface<T> {
+    surface: ObjectId,
+    commit: CommitCounter,
+    buffer: TextureBuffer<T>,
+    /// Location relative to the toplevel surface
+    location: Point<i32, Logical>,
+    /// Size the surface is drawn with
+    size: Size<i32, Logical>,
+    /// Part of the buffer shown, in surface coordinates (wp_viewporter)
+    src: Rectangle<f64, Logical>,
+}
+
+/*
+Cache of the snapshot in the user data of a window, one per texture type,
+so every renderer keeps its own textures.
+*/
+struct SnapshotCache<T>(RefCell<Option<WindowSnapshot<T>>>);
+
+/*
+Imports the current buffers of a window and refreshes its snapshot.
+Surfaces without a buffer are left out, a window without any keeps its previous snapshot.
+*/
+pub fn update_snapshot<R>(renderer: &mut R, window: &WindowElement)
+where
+    R: Renderer + ImportAll,
+    R::TextureId: Clone + 'static,
+{
+    let Some(surface) = window.wl_surface() else {
+        return;
+    };
+    if let Err(err) = import_surface_tree(renderer, &surface) {
+        tracing::debug!("Failed to import the surfaces of a background window: {:?}", err);
+    }
+
+    window
+        .user_data()
+        .insert_if_missing(|| SnapshotCache::<R::TextureId>(RefCell::new(None)));
+    let cache = window.user_data().get::<SnapshotCache<R::TextureId>>().unwrap();
+    let mut snapshot = cache.0.borrow_mut();
+    let previous = snapshot.as_ref().map(|s| s.surfaces.as_slice()).unwrap_or_default();
+
+    let renderer = &*renderer;
+    let mut surfaces = Vec::new();
+    with_surface_tree_downward(
+        &surface,
+        Point::<i32, Logical>::default(),
+        |_, states, location| {
+            let view = states
+                .data_map
+                .get::<RendererSurfaceStateUserData>()
+                .and_then(|data| data.lock().unwrap().view());
+            match view {
+                Some(view) => TraversalAction::DoChildren(*location + view.offset),
+                None => TraversalAction::SkipChildren,
+            }
+        },
+        |surface, states, location| {
+            let Some(data) = states.data_map.get::<RendererSurfaceStateUserData>() else {
+                return;
+            };
+            let data = data.lock().unwrap();
+            let Some(view) = data.view() else {
+                return;
+            };
+
+            // Nothing new was committed, keep the texture buffer so the preview is not damaged.
+            let commit = data.current_commit();
+            let buffer = match previous
+                .iter()
+                .find(|s| s.surface == surface.id() && s.commit == commit)
+            {
+                Some(previous) => previous.buffer.clone(),
+                None => {
+                    let Some(texture) = data.texture::<R>(renderer.id()) else {
+                        return;
+                    };
+                    TextureBuffer::from_texture(
+                        renderer,
+                        texture.clone(),
+                        data.buffer_scale(),
+                        data.buffer_transform(),
+                        None,
+                    )
+                }
+            };
+
+            surfaces.push(SnapshotSurface {
+                surface: surface.id(),
+                commit,
+                buffer,
+                location: *location + view.offset,
+                size: view.dst,
+                src: view.src,
+            });
+        },
+        |_, _, _| true,
+    );
+
+    if !surfaces.is_empty() {
+        *snapshot = Some(WindowSnapshot {
+            geometry: SpaceElement::geometry(window),
+            surfaces,
+        });
+    }
+}
+
+/*
+Refreshes the snapshots of the apps in the background shown on this output, so the overview
+opens on their last frame. Their once a second frame callbacks end up here.
+*/
+pub fn update_background_snapshots<R>(
+    renderer: &mut R,
+    space: &Space<WindowElement>,
+    window_manager: &dyn WindowManager,
+    output: &Output,
+) where
+    R: Renderer + ImportAll,
+    R::TextureId: Clone + 'static,
+{
+    for window in window_manager.windows() {
+        if space.element_location(window).is_some() {
+            continue;
+        }
+        // Apps the window manager did not assign to an output may be shown on any of them.
+        let shown_on = window_manager.window_output(window.id());
+        if shown_on.is_some_and(|shown_on| &shown_on != output) {
+            continue;
+        }
+        update_snapshot(renderer, window);
+    }
+}
+
+/*
+Renders the snapshot of a window scaled to fit inside `frame`, centered.
+`frame` is relative to the output, the elements are cropped to it.
+*/
+pub fn snapshot_elements<T>(
+    window: &WindowElement,
+    frame: Rectangle<i32, Logical>,
+    output_scale: f64,
+) -> Vec<CropRenderElement<TextureRenderElement<T>>>
+where
+    T: Clone + 'static,
+{
+    let Some(cache) = window.user_data().get::<SnapshotCache<T>>() else {
+        return Vec::new();
+    };
+    let snapshot = cache.0.borrow();
+    let Some(snapshot) = snapshot.as_ref() else {
+        return Vec::new();
+    };
+    let geometry = snapshot.geometry;
+    if geometry.is_empty() {
+        return Vec::new();
+    }
+
+    let scale = f64::min(
+        frame.size.w as f64 / geometry.size.w as f64,
+        frame.size.h as f64 / geometry.size.h as f64,
+    );
+    let origin = frame.loc.to_f64()
+        + Point::from((
+            (frame.size.w as f64 - geometry.size.w as f64 * scale) / 2.0,
+            (frame.size.h as f64 - geometry.size.h as f64 * scale) / 2.0,
+        ));
+    let crop = frame.to_physical_precise_round(output_scale);
+
+    snapshot
+        .surfaces
+        .iter()
+        .filter_map(|surface| {
+            let location = origin + (surface.location - geometry.loc).to_f64().upscale(scale);
+            let size = surface.size.to_f64().upscale(scale).to_i32_round();
+            let element = TextureRenderElement::from_texture_buffer(
+                location.to_physical(output_scale),
+                &surface.buffer,
+                None,
+                Some(surface.src),
+                Some(size),
+                Kind::Unspecified,
+            );
+            CropRenderElement::from_element(element, output_scale, crop)
+        })
+        .collect()
+}
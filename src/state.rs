@@ -6,7 +6,13 @@ use std::{
 };
 
 use smithay::{
-    backend::renderer::element::{default_primary_scanout_output_compare, utils::select_dmabuf_feedback, RenderElementStates},
+    backend::{
+        input::TouchSlot,
+        renderer::{
+            element::{default_primary_scanout_output_compare, utils::select_dmabuf_feedback, RenderElementStates},
+            DebugFlags,
+        },
+    },
     delegate_compositor, delegate_data_control, delegate_data_device, delegate_fractional_scale,
     delegate_input_method_manager, delegate_keyboard_shortcuts_inhibit, delegate_layer_shell,
     delegate_output, delegate_pointer_constraints, delegate_pointer_gestures, delegate_presentation,
@@ -19,11 +25,12 @@ use smithay::{
             surface_presentation_feedback_flags_from_states, surface_primary_scanout_output,
             update_surface_primary_scanout_output, OutputPresentationFeedback,
         },
-        PopupKind, PopupManager, Space,
+        PopupGrab, PopupKind, PopupManager, Space,
     },
     input::{
-        keyboard::{LedState, XkbConfig},
+        keyboard::{Keycode, LedState},
         pointer::PointerHandle,
+        touch::TouchHandle,
         Seat, SeatHandler, SeatState,
     },
     output::Output,
@@ -35,10 +42,10 @@ use smithay::{
         wayland_server::{
             backend::{ClientData, ClientId, DisconnectReason},
             protocol::{wl_data_source::WlDataSource, wl_surface::WlSurface},
-            Client, Display, DisplayHandle, Resource,
+            BindError, Client, Display, DisplayHandle, Resource,
         },
     },
-    utils::{Clock, Logical, Monotonic, Rectangle, Time},
+    utils::{Clock, IsAlive, Logical, Monotonic, Point, Rectangle, Time},
     wayland::{
         commit_timing::{CommitTimerBarrierStateUserData, CommitTimingManagerState},
         compositor::{get_parent, with_states, CompositorClientState, CompositorHandler, CompositorState},
@@ -85,7 +92,10 @@ use smithay::{
 };
 
 
-use crate::{focus::{KeyboardFocusTarget, PointerFocusTarget}, shell::WindowElement, window_manager::WindowManager};
+use crate::{config::Config, focus::{KeyboardFocusTarget, PointerFocusTarget}, gestures::GestureRecognizer, input_handler::KeyBindings, overview::Overview, shell::WindowElement, window_manager::WindowManager};
+
+/// How often apps in the background get a frame callback, to keep their previews current
+const BACKGROUND_FRAME_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Default)]
 pub struct ClientState {
@@ -100,6 +110,30 @@ impl ClientData for ClientState {
     fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}    
 }
 
+/*
+Where Aurora accepts new wayland clients.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListeningSocket {
+    /// Don't listen at all, clients have to be inserted into the display manually.
+    None,
+    /// Use the first free `wayland-N` socket name.
+    Auto,
+    /// Use the given socket name, failing if it is already taken.
+    Named(String),
+}
+
+/*
+Errors that keep Aurora from starting, eg. because of the command line it was given.
+*/
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error("failed to bind wayland socket `{name}`: {source}")]
+    Socket { name: String, source: BindError },
+    #[error("failed to find a free wayland socket: {0}")]
+    NoFreeSocket(BindError),
+}
+
 pub trait Backend {
     const HAS_RELATIVE_MOTION: bool = false;
     const HAS_GESTURES: bool = false;
@@ -107,6 +141,9 @@ pub trait Backend {
     fn reset_buffers(&mut self, output: &Output);
     fn early_import(&mut self, surface: &WlSurface);
     fn update_led_state(&mut self, led_state: LedState);
+    fn change_vt(&mut self, vt: i32);
+    fn debug_flags(&self) -> DebugFlags;
+    fn set_debug_flags(&mut self, flags: DebugFlags);
 }
 
 #[derive(Debug)]
@@ -117,10 +154,13 @@ pub struct AuroraState<BackendData: Backend + 'static> {
     pub handle: LoopHandle<'static, AuroraState<BackendData>>,
     pub running: Arc<AtomicBool>,
     pub clock: Clock<Monotonic>,
+    pub config: Config,
 
     // desktop
     pub space: Space<WindowElement>,
     pub popups: PopupManager,
+    /// Grab of the open xdg popup chain, touch input dismisses it by hand
+    pub popup_grab: Option<PopupGrab<AuroraState<BackendData>>>,
 
     // smithay state
     pub compositor_state: CompositorState,
@@ -144,15 +184,22 @@ pub struct AuroraState<BackendData: Backend + 'static> {
     pub commit_timing_manager_state: CommitTimingManagerState,
 
     // drawing logic???
-    pub show_window_preview: bool,
+    /// The overview of running apps, when it is open
+    pub overview: Option<Overview>,
 
     // input-related fields
     pub seat: Seat<AuroraState<BackendData>>,
     pub seat_name: String,
     pub pointer: PointerHandle<AuroraState<BackendData>>,
+    pub touch: TouchHandle<AuroraState<BackendData>>,
+    /// Surface (and its global origin) each active touch point started on
+    pub touch_slots: HashMap<TouchSlot, Option<(PointerFocusTarget, Point<f64, Logical>)>>,
+    pub gestures: GestureRecognizer,
+    pub key_bindings: KeyBindings,
+    pub suppressed_keys: Vec<Keycode>,
 
     // apps...
-    pub window_manager: WindowManager,
+    pub window_manager: Box<dyn WindowManager>,
 }
 /*
 Delegates the Wayland compositor role to the AuroraState.
@@ -226,7 +273,9 @@ impl <BackendData: Backend> SeatHandler for AuroraState<BackendData> {
 
     fn cursor_image(&mut self, _seat: &smithay::input::Seat<Self>, _image: smithay::input::pointer::CursorImageStatus) { }
     
-    fn led_state_changed(&mut self, _seat: &smithay::input::Seat<Self>, _led_state: LedState) { }
+    fn led_state_changed(&mut self, _seat: &smithay::input::Seat<Self>, led_state: LedState) {
+        self.backend_data.update_led_state(led_state)
+    }
 }
 /*
 Delegates the seat role to the AuroraState.
@@ -444,14 +493,14 @@ impl<BackendData: Backend> XdgActivationHandler for AuroraState<BackendData> {
         surface: WlSurface,
     ) {
         if token_data.timestamp.elapsed().as_secs() < 10 {
-            // Just grant the wish
-            let w = self
-                .space
-                .elements()
-                .find(|window| window.wl_surface().map(|s| *s == surface).unwrap_or(false))
-                .cloned();
-            if let Some(window) = w {
-                self.space.raise_element(&window, true);
+            // Just grant the wish, the window manager decides how the window is shown.
+            if let Some(window) = self.window_for_surface(&surface) {
+                // Apps a window rule keeps from taking the focus only get it from the user.
+                if window.rule().no_focus() {
+                    return;
+                }
+                self.window_manager.activation_request(&window, &mut self.space);
+                self.refocus_active_window();
             }
         }
     }
@@ -470,20 +519,24 @@ This is essential for standardize how app windows appear in your compositor.
 */
 impl<BackendData: Backend> XdgDecorationHandler for AuroraState<BackendData> {
     fn new_decoration(&mut self, toplevel: ToplevelSurface) {
-        use xdg_decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode;
-        // Set the default to client side
+        // Start with the configured default
+        let mode = self
+            .forced_decoration_mode(&toplevel)
+            .unwrap_or(self.config.window.decoration_mode.into());
         toplevel.with_pending_state(|state| {
-            state.decoration_mode = Some(Mode::ClientSide);
+            state.decoration_mode = Some(mode);
         });
     }
     fn request_mode(&mut self, toplevel: ToplevelSurface, mode: DecorationMode) {
         use xdg_decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode;
 
+        // A window rule wins over what the client asks for.
+        let forced = self.forced_decoration_mode(&toplevel);
         toplevel.with_pending_state(|state| {
-            state.decoration_mode = Some(match mode {
+            state.decoration_mode = Some(forced.unwrap_or(match mode {
                 DecorationMode::ServerSide => Mode::ServerSide,
                 _ => Mode::ClientSide,
-            });
+            }));
         });
 
         if toplevel.is_initial_configure_sent() {
@@ -491,9 +544,11 @@ impl<BackendData: Backend> XdgDecorationHandler for AuroraState<BackendData> {
         }
     }
     fn unset_mode(&mut self, toplevel: ToplevelSurface) {
-        use xdg_decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode;
+        let mode = self
+            .forced_decoration_mode(&toplevel)
+            .unwrap_or(self.config.window.decoration_mode.into());
         toplevel.with_pending_state(|state| {
-            state.decoration_mode = Some(Mode::ClientSide);
+            state.decoration_mode = Some(mode);
         });
 
         if toplevel.is_initial_configure_sent() {
@@ -502,6 +557,18 @@ impl<BackendData: Backend> XdgDecorationHandler for AuroraState<BackendData> {
     }
 }
 
+impl<BackendData: Backend> AuroraState<BackendData> {
+    /*
+    The decoration mode a window rule forces on a toplevel, if any.
+    */
+    fn forced_decoration_mode(&self, toplevel: &ToplevelSurface) -> Option<DecorationMode> {
+        self.window_for_surface(toplevel.wl_surface())?
+            .rule()
+            .decoration_mode
+            .map(DecorationMode::from)
+    }
+}
+
 /*
 Delegates the XDG decoration role to the AuroraState.
 Provides support for server-side decorations (like window borders, shadows, etc.) used in modern desktop environments.
@@ -696,12 +763,19 @@ impl <BackendData: Backend + 'static> AuroraState<BackendData> {
         display: Display<AuroraState<BackendData>>,
         handle: LoopHandle<'static, AuroraState<BackendData>>,
         backend_data: BackendData,
-        listen_on_socket: bool,
-    ) -> AuroraState<BackendData> {
+        listening_socket: ListeningSocket,
+        config: Config,
+    ) -> Result<AuroraState<BackendData>, InitError> {
         let dh = display.handle();
 
-        let socket_name = if listen_on_socket {
-            let source = ListeningSocketSource::new_auto().unwrap();
+        let source = match listening_socket {
+            ListeningSocket::None => None,
+            ListeningSocket::Auto => Some(ListeningSocketSource::new_auto().map_err(InitError::NoFreeSocket)?),
+            ListeningSocket::Named(name) => Some(
+                ListeningSocketSource::with_name(&name).map_err(|source| InitError::Socket { name, source })?,
+            ),
+        };
+        let socket_name = if let Some(source) = source {
             let socket_name = source.socket_name().to_string_lossy().into_owned();
             handle
                 .insert_source(source, |client_stream, _, data| {
@@ -774,20 +848,30 @@ impl <BackendData: Backend + 'static> AuroraState<BackendData> {
         let seat_name = backend_data.seat_name();
         let mut seat = seat_state.new_wl_seat(&dh, seat_name.clone());
         let pointer = seat.add_pointer();
-        seat.add_keyboard(XkbConfig::default(), 200, 25)
-            .expect("Failed to initialize the keyboard");
+        let touch = seat.add_touch();
+        let keyboard_config = &config.keyboard;
+        seat.add_keyboard(
+            keyboard_config.xkb_config(),
+            keyboard_config.repeat_delay,
+            keyboard_config.repeat_rate,
+        )
+        .expect("Failed to initialize the keyboard");
         let keyboard_shortcuts_inhibit_state = KeyboardShortcutsInhibitState::new::<Self>(&dh);
 
-        AuroraState {
+        let state = AuroraState {
             backend_data,
             socket_name,
             display_handle: dh,
             handle,
             running: Arc::new(AtomicBool::new(true)),
             clock,
+            key_bindings: config.key_bindings(),
+            window_manager: config.window.layout.window_manager(),
+            config,
 
             space: Space::default(),
             popups: PopupManager::default(),
+            popup_grab: None,
             
             compositor_state,
             data_device_state,
@@ -809,18 +893,85 @@ impl <BackendData: Backend + 'static> AuroraState<BackendData> {
             fifo_manager_state,
             commit_timing_manager_state,
 
-            show_window_preview: false,
+            overview: None,
 
             seat,
             seat_name,
             pointer,
+            touch,
+            touch_slots: HashMap::new(),
+            gestures: GestureRecognizer::new(),
+            suppressed_keys: Vec::new(),
+        };
+        state.watch_config();
+
+        Ok(state)
+    }
+
+    /*
+    Launches a client process connected to Aurora's wayland socket.
+    The child is reaped on a background thread so it does not linger as a zombie.
+    */
+    pub fn spawn(&self, command: &[String]) {
+        let Some((program, args)) = command.split_first() else {
+            return;
+        };
+
+        let mut cmd = std::process::Command::new(program);
+        cmd.args(args);
+        if let Some(socket_name) = &self.socket_name {
+            cmd.env("WAYLAND_DISPLAY", socket_name);
+        }
+
+        match cmd.spawn() {
+            Ok(mut child) => {
+                tracing::info!(?command, pid = child.id(), "Launched client");
+                std::thread::spawn(move || child.wait());
+            }
+            Err(err) => tracing::error!(?command, "Failed to launch client: {}", err),
+        }
+    }
+
+    /*
+    Replaces the window management policy. The running apps are handed over to the new
+    policy as if they were just opened, the old one is returned.
+    */
+    pub fn set_window_manager(&mut self, window_manager: Box<dyn WindowManager>) -> Box<dyn WindowManager> {
+        let old = std::mem::replace(&mut self.window_manager, window_manager);
+        for window in old.windows() {
+            self.space.unmap_elem(window);
+            // Only the old policy knew about its picture-in-picture tile.
+            window.set_pip(false);
+        }
+        // The new policy has not seen any output yet, without them it has nowhere to put the apps.
+        for output in self.space.outputs().cloned().collect::<Vec<_>>() {
+            self.window_manager.output_added(&output, &mut self.space);
+        }
+        for window in old.windows().filter(|w| w.alive()) {
+            self.window_manager.new_toplevel(window.clone(), &mut self.space);
+        }
+
+        self.refocus_active_window();
+        old
+    }
 
-            window_manager: WindowManager::new()
+    /*
+    Switches the window manager between tablet and desktop mode, eg. from the tablet mode
+    switch of a convertible.
+    */
+    pub fn set_desktop_mode(&mut self, enabled: bool) {
+        if self.window_manager.desktop_mode() == enabled {
+            return;
         }
+        tracing::info!(enabled, "Switching desktop mode");
+        self.window_manager.set_desktop_mode(enabled, &mut self.space);
+        self.refocus_active_window();
     }
 
     pub fn pre_repaint(&mut self, output: &Output, frame_target: impl Into<Time<Monotonic>>) {
         let frame_target = frame_target.into();
+        self.window_manager.animate(&mut self.space);
+        self.refresh_overview();
 
         #[allow(clippy::mutable_key_type)]
         let mut clients: HashMap<ClientId, Client> = HashMap::new();
@@ -934,6 +1085,15 @@ impl <BackendData: Backend + 'static> AuroraState<BackendData> {
                 }
             }
         });
+
+        // Apps in the background are not mapped, they still get a frame callback once a second
+        // so their snapshots in the overview stay current without drawing at full rate.
+        for window in self.window_manager.windows() {
+            if self.space.element_location(window).is_none() {
+                window.send_frame(output, time, Some(BACKGROUND_FRAME_INTERVAL), |_, _| None);
+            }
+        }
+
         let map = smithay::desktop::layer_map_for_output(output);
         for layer_surface in map.layers() {
             layer_surface.with_surfaces(|surface, states| {
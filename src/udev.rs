@@ -0,0 +1,1032 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use smithay::{
+    backend::{
+        allocator::{
+            dmabuf::Dmabuf,
+            gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
+            Fourcc,
+        },
+        drm::{
+            compositor::{DrmCompositor, FrameFlags, RenderFrameError},
+            gbm::GbmFramebufferExporter,
+            CreateDrmNodeError, DrmDevice, DrmDeviceFd, DrmError, DrmEvent, DrmEventMetadata, DrmEventTime,
+            DrmNode, NodeType,
+        },
+        egl::{EGLDevice, EGLDisplay},
+        libinput::{LibinputInputBackend, LibinputSessionInterface},
+        renderer::{
+            damage::Error as OutputDamageTrackerError,
+            element::RenderElementStates,
+            multigpu::{gbm::GbmGlesBackend, GpuManager, MultiRenderer},
+            pixman::PixmanRenderer,
+            gles::GlesRenderer,
+            Bind, DebugFlags, ImportAll, ImportDma, ImportMem, ImportMemWl, Renderer, Texture,
+        },
+        session::{libseat::LibSeatSession, Event as SessionEvent, Session},
+        udev::{all_gpus, primary_gpu, UdevBackend, UdevEvent},
+        SwapBuffersError,
+    },
+    delegate_dmabuf,
+    desktop::{space::Space, utils::OutputPresentationFeedback},
+    input::keyboard::LedState,
+    output::{Mode as WlMode, Output, PhysicalProperties},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            EventLoop, RegistrationToken,
+        },
+        drm::control::{connector, crtc, Device as ControlDevice, Mode as DrmMode, ModeTypeFlags},
+        input::{DeviceCapability, Libinput},
+        rustix::fs::OFlags,
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::{backend::GlobalId, protocol::wl_surface, Display},
+    },
+    utils::DeviceFd,
+    wayland::{
+        dmabuf::{DmabufFeedbackBuilder, DmabufGlobal, DmabufHandler, DmabufState, ImportNotifier},
+        presentation::Refresh,
+    },
+};
+use smithay_drm_extras::{
+    display_info,
+    drm_scanner::{DrmScanEvent, DrmScanner},
+};
+
+use crate::{
+    cli::CliArgs,
+    config::Config,
+    overview::Overview,
+    renderer::{output_elements, CustomRenderElements},
+    shell::WindowElement,
+    snapshot::update_background_snapshots,
+    state::{take_presentation_feedback, AuroraState, Backend, InitError},
+    window_manager::WindowManager,
+};
+
+// we cannot simply pick the first supported format of the intersection of *all* formats, because:
+// - we do not want something like Abgr4444, which looses color information, if something better is available
+// - some formats might perform terribly for scanout
+const SUPPORTED_FORMATS: &[Fourcc] = &[
+    Fourcc::Abgr2101010,
+    Fourcc::Argb2101010,
+    Fourcc::Abgr8888,
+    Fourcc::Argb8888,
+];
+// Pixman can only render into 8 bit per channel buffers.
+const SUPPORTED_FORMATS_PIXMAN: &[Fourcc] = &[Fourcc::Argb8888, Fourcc::Xrgb8888];
+
+type UdevRenderer<'a> = MultiRenderer<
+    'a,
+    'a,
+    GbmGlesBackend<GlesRenderer, DrmDeviceFd>,
+    GbmGlesBackend<GlesRenderer, DrmDeviceFd>,
+>;
+
+type GbmDrmCompositor = DrmCompositor<
+    GbmAllocator<DrmDeviceFd>,
+    GbmFramebufferExporter<DrmDeviceFd>,
+    Option<OutputPresentationFeedback>,
+    DrmDeviceFd,
+>;
+
+/*
+Identifies which device and CRTC drive an output.
+Stored in the user data of every output created by the udev backend.
+*/
+#[derive(Debug, PartialEq)]
+struct UdevOutputId {
+    device_id: DrmNode,
+    crtc: crtc::Handle,
+}
+
+pub struct UdevData {
+    pub session: LibSeatSession,
+    primary_gpu: DrmNode,
+    gpus: GpuManager<GbmGlesBackend<GlesRenderer, DrmDeviceFd>>,
+    devices: HashMap<DrmNode, DeviceData>,
+    dmabuf_state: Option<(DmabufState, DmabufGlobal)>,
+    keyboards: Vec<smithay::reexports::input::Device>,
+    debug_flags: DebugFlags,
+}
+
+/*
+How the surfaces of a DRM device are rendered.
+Devices whose render node can not be driven through EGL (eg. simple display controllers
+without a GPU, or broken drivers) fall back to the Pixman software renderer.
+*/
+enum DeviceRenderer {
+    Gles,
+    Pixman(PixmanRenderer),
+}
+
+struct DeviceData {
+    surfaces: HashMap<crtc::Handle, SurfaceData>,
+    gbm: GbmDevice<DrmDeviceFd>,
+    drm: DrmDevice,
+    drm_scanner: DrmScanner,
+    render_node: DrmNode,
+    renderer: DeviceRenderer,
+    registration_token: RegistrationToken,
+}
+
+struct SurfaceData {
+    output: Output,
+    global: Option<GlobalId>,
+    compositor: GbmDrmCompositor,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum DeviceAddError {
+    #[error("Failed to open device using libseat: {0}")]
+    DeviceOpen(smithay::backend::session::libseat::Error),
+    #[error("Failed to initialize drm device: {0}")]
+    DrmDevice(DrmError),
+    #[error("Failed to initialize gbm device: {0}")]
+    GbmDevice(std::io::Error),
+    #[error("Failed to access drm node: {0}")]
+    DrmNode(CreateDrmNodeError),
+}
+
+impl DmabufHandler for AuroraState<UdevData> {
+    fn dmabuf_state(&mut self) -> &mut DmabufState {
+        &mut self.backend_data.dmabuf_state.as_mut().unwrap().0
+    }
+
+    fn dmabuf_imported(&mut self, _global: &DmabufGlobal, dmabuf: Dmabuf, notifier: ImportNotifier) {
+        if self
+            .backend_data
+            .gpus
+            .single_renderer(&self.backend_data.primary_gpu)
+            .and_then(|mut renderer| renderer.import_dmabuf(&dmabuf, None))
+            .is_ok()
+        {
+            dmabuf.set_node(self.backend_data.primary_gpu);
+            let _ = notifier.successful::<AuroraState<UdevData>>();
+        } else {
+            notifier.failed();
+        }
+    }
+}
+delegate_dmabuf!(AuroraState<UdevData>);
+
+impl Backend for UdevData {
+    fn seat_name(&self) -> String {
+        self.session.seat()
+    }
+    fn reset_buffers(&mut self, output: &Output) {
+        if let Some(id) = output.user_data().get::<UdevOutputId>() {
+            if let Some(surface) = self
+                .devices
+                .get_mut(&id.device_id)
+                .and_then(|device| device.surfaces.get_mut(&id.crtc))
+            {
+                surface.compositor.reset_buffers();
+            }
+        }
+    }
+    fn early_import(&mut self, surface: &wl_surface::WlSurface) {
+        if let Err(err) = self.gpus.early_import(self.primary_gpu, surface) {
+            tracing::warn!("Early buffer import failed: {}", err);
+        }
+    }
+    fn update_led_state(&mut self, led_state: LedState) {
+        for keyboard in self.keyboards.iter_mut() {
+            keyboard.led_update(led_state.into());
+        }
+    }
+    fn change_vt(&mut self, vt: i32) {
+        if let Err(err) = self.session.change_vt(vt) {
+            tracing::error!(vt, "Error switching vt: {}", err);
+        }
+    }
+    fn debug_flags(&self) -> DebugFlags {
+        self.debug_flags
+    }
+    fn set_debug_flags(&mut self, flags: DebugFlags) {
+        if self.debug_flags != flags {
+            self.debug_flags = flags;
+            for device in self.devices.values_mut() {
+                for surface in device.surfaces.values_mut() {
+                    surface.compositor.reset_buffers();
+                }
+            }
+        }
+    }
+}
+
+/*
+The parts of a DRM connector its output is built from.
+
+`connector::Info` can only be queried from a real device, this one can also be filled in
+by hand, eg. next to a fake `ControlDevice` in tests.
+*/
+#[derive(Debug, Clone)]
+pub struct ConnectorDescription<'a> {
+    pub handle: connector::Handle,
+    /// Name of the output, eg. `eDP-1`
+    pub name: String,
+    pub modes: &'a [DrmMode],
+    /// Physical size in millimeters
+    pub size: Option<(u32, u32)>,
+    pub subpixel: connector::SubPixel,
+}
+
+impl<'a> From<&'a connector::Info> for ConnectorDescription<'a> {
+    fn from(connector: &'a connector::Info) -> Self {
+        ConnectorDescription {
+            handle: connector.handle(),
+            name: format!("{}-{}", connector.interface().as_str(), connector.interface_id()),
+            modes: connector.modes(),
+            size: connector.size(),
+            subpixel: connector.subpixel(),
+        }
+    }
+}
+
+/*
+Builds the wayland `Output` advertised for a DRM connector, together with the mode it will be driven at:
+the preferred one, or else the first one.
+
+This only queries the connector and its EDID, so it is generic over `ControlDevice` and can be used
+with a fake device (like the one in `smithay-drm-extras/src/docs/doctest_helpers.rs`) without real hardware.
+Returns `None` if the connector does not expose any mode.
+*/
+pub fn output_for_connector<'a>(
+    drm: &impl ControlDevice,
+    connector: impl Into<ConnectorDescription<'a>>,
+) -> Option<(Output, DrmMode)> {
+    let connector = connector.into();
+
+    let drm_mode = connector
+        .modes
+        .iter()
+        .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+        .or_else(|| connector.modes.first())
+        .copied()?;
+
+    let display_info = display_info::for_connector(drm, connector.handle);
+    let make = display_info
+        .as_ref()
+        .and_then(|info| info.make())
+        .unwrap_or_else(|| "Unknown".into());
+    let model = display_info
+        .as_ref()
+        .and_then(|info| info.model())
+        .unwrap_or_else(|| "Unknown".into());
+
+    let (phys_w, phys_h) = connector.size.unwrap_or((0, 0));
+    let output = Output::new(
+        connector.name,
+        PhysicalProperties {
+            size: (phys_w as i32, phys_h as i32).into(),
+            subpixel: connector.subpixel.into(),
+            make,
+            model,
+        },
+    );
+
+    Some((output, drm_mode))
+}
+
+pub fn run_udev(args: &CliArgs, config: Config) -> Result<(), InitError> {
+    tracing::info!("Running with udev backend");
+
+    let mut event_loop = EventLoop::try_new().unwrap();
+    let display = Display::new().unwrap();
+    let mut display_handle = display.handle();
+
+    // Initialize session
+    let (session, notifier) = match LibSeatSession::new() {
+        Ok(ret) => ret,
+        Err(err) => {
+            tracing::error!("Could not initialize a session: {}", err);
+            return Ok(());
+        }
+    };
+
+    // Initialize the compositor
+    let primary_gpu = primary_gpu(session.seat())
+        .unwrap()
+        .and_then(|x| DrmNode::from_path(x).ok()?.node_with_type(NodeType::Render)?.ok())
+        .or_else(|| {
+            all_gpus(session.seat())
+                .unwrap()
+                .into_iter()
+                .find_map(|x| DrmNode::from_path(x).ok())
+        });
+    let primary_gpu = match primary_gpu {
+        Some(node) => node,
+        None => {
+            tracing::error!("No GPU found");
+            return Ok(());
+        }
+    };
+    tracing::info!("Using {} as primary gpu.", primary_gpu);
+
+    let gpus = GpuManager::new(GbmGlesBackend::default()).unwrap();
+
+    let data = UdevData {
+        session,
+        primary_gpu,
+        gpus,
+        devices: HashMap::new(),
+        dmabuf_state: None,
+        keyboards: Vec::new(),
+        debug_flags: DebugFlags::empty(),
+    };
+    let listening_socket = args.listening_socket(&config);
+    let mut state = AuroraState::init(display, event_loop.handle(), data, listening_socket, config)?;
+
+    // Initialize the udev backend
+    let udev_backend = match UdevBackend::new(&state.seat_name) {
+        Ok(ret) => ret,
+        Err(err) => {
+            tracing::error!(?err, "Failed to initialize udev backend");
+            return Ok(());
+        }
+    };
+
+    // Initialize libinput backend
+    let mut libinput_context = Libinput::new_with_udev::<LibinputSessionInterface<LibSeatSession>>(
+        state.backend_data.session.clone().into(),
+    );
+    libinput_context.udev_assign_seat(&state.seat_name).unwrap();
+    let libinput_backend = LibinputInputBackend::new(libinput_context.clone());
+
+    // Feed libinput events into the input handler
+    event_loop
+        .handle()
+        .insert_source(libinput_backend, move |mut event, _, data| {
+            if let smithay::backend::input::InputEvent::DeviceAdded { device } = &mut event {
+                if device.has_capability(DeviceCapability::Keyboard) {
+                    if let Some(led_state) = data
+                        .seat
+                        .get_keyboard()
+                        .map(|keyboard| keyboard.led_state())
+                    {
+                        device.led_update(led_state.into());
+                    }
+                    data.backend_data.keyboards.push(device.clone());
+                }
+            } else if let smithay::backend::input::InputEvent::DeviceRemoved { ref device } = event {
+                if device.has_capability(DeviceCapability::Keyboard) {
+                    data.backend_data.keyboards.retain(|item| item != device);
+                }
+            }
+
+            data.process_input_event(event)
+        })
+        .unwrap();
+
+    // Pause and resume DRM devices and libinput when the session (eg. the VT) changes
+    event_loop
+        .handle()
+        .insert_source(notifier, move |event, &mut (), data| match event {
+            SessionEvent::PauseSession => {
+                libinput_context.suspend();
+                tracing::info!("pausing session");
+
+                for device in data.backend_data.devices.values_mut() {
+                    device.drm.pause();
+                }
+            }
+            SessionEvent::ActivateSession => {
+                tracing::info!("resuming session");
+
+                if let Err(err) = libinput_context.resume() {
+                    tracing::error!("Failed to resume libinput context: {:?}", err);
+                }
+                let mut crtcs = Vec::new();
+                for (node, device) in data.backend_data.devices.iter_mut() {
+                    if let Err(err) = device.drm.activate(false) {
+                        tracing::error!("Failed to activate drm device {}: {}", node, err);
+                    }
+                    for (crtc, surface) in device.surfaces.iter_mut() {
+                        surface.compositor.reset_state().ok();
+                        crtcs.push((*node, *crtc));
+                    }
+                }
+                for (node, crtc) in crtcs {
+                    data.handle.insert_idle(move |data| data.render(node, crtc));
+                }
+            }
+        })
+        .unwrap();
+
+    for (device_id, path) in udev_backend.device_list() {
+        if let Err(err) = DrmNode::from_dev_id(device_id)
+            .map_err(DeviceAddError::DrmNode)
+            .and_then(|node| state.device_added(node, path))
+        {
+            tracing::error!("Skipping device {device_id}: {err}");
+        }
+    }
+    state.shm_state.update_formats(
+        state
+            .backend_data
+            .gpus
+            .single_renderer(&primary_gpu)
+            .map(|renderer| renderer.shm_formats().collect::<Vec<_>>())
+            .unwrap_or_default(),
+    );
+
+    // Advertise dmabuf support if the primary gpu can be driven through GLES
+    if let Ok(mut renderer) = state.backend_data.gpus.single_renderer(&primary_gpu) {
+        let dmabuf_formats = renderer.dmabuf_formats();
+        let default_feedback = DmabufFeedbackBuilder::new(primary_gpu.dev_id(), dmabuf_formats)
+            .build()
+            .unwrap();
+        let mut dmabuf_state = DmabufState::new();
+        let global = dmabuf_state.create_global_with_default_feedback::<AuroraState<UdevData>>(
+            &state.display_handle,
+            &default_feedback,
+        );
+        state.backend_data.dmabuf_state = Some((dmabuf_state, global));
+    } else {
+        tracing::warn!("Primary gpu has no GLES renderer, dmabuf support is disabled");
+    }
+
+    event_loop
+        .handle()
+        .insert_source(udev_backend, move |event, _, data| match event {
+            UdevEvent::Added { device_id, path } => {
+                if let Err(err) = DrmNode::from_dev_id(device_id)
+                    .map_err(DeviceAddError::DrmNode)
+                    .and_then(|node| data.device_added(node, &path))
+                {
+                    tracing::error!("Skipping device {device_id}: {err}");
+                }
+            }
+            UdevEvent::Changed { device_id } => {
+                if let Ok(node) = DrmNode::from_dev_id(device_id) {
+                    data.device_changed(node)
+                }
+            }
+            UdevEvent::Removed { device_id } => {
+                if let Ok(node) = DrmNode::from_dev_id(device_id) {
+                    data.device_removed(node)
+                }
+            }
+        })
+        .unwrap();
+
+    // The socket is ready, launch the requested client.
+    state.spawn(&args.command);
+
+    tracing::info!("Initialization completed, starting the main loop.");
+
+    while state.running.load(Ordering::SeqCst) {
+        let result = event_loop.dispatch(Some(Duration::from_millis(16)), &mut state);
+        if result.is_err() {
+            state.running.store(false, Ordering::SeqCst);
+        } else {
+            state.space.refresh();
+            state.popups.cleanup();
+            state.update_background_states();
+            display_handle.flush_clients().unwrap();
+        }
+    }
+
+    Ok(())
+}
+
+impl AuroraState<UdevData> {
+    fn device_added(&mut self, node: DrmNode, path: &Path) -> Result<(), DeviceAddError> {
+        // Try to open the device
+        let fd = self
+            .backend_data
+            .session
+            .open(
+                path,
+                OFlags::RDWR | OFlags::CLOEXEC | OFlags::NOCTTY | OFlags::NONBLOCK,
+            )
+            .map_err(DeviceAddError::DeviceOpen)?;
+
+        let fd = DrmDeviceFd::new(DeviceFd::from(fd));
+
+        let (drm, notifier) = DrmDevice::new(fd.clone(), true).map_err(DeviceAddError::DrmDevice)?;
+        let gbm = GbmDevice::new(fd).map_err(DeviceAddError::GbmDevice)?;
+
+        let registration_token = self
+            .handle
+            .insert_source(notifier, move |event, metadata, data| match event {
+                DrmEvent::VBlank(crtc) => {
+                    data.frame_finish(node, crtc, metadata);
+                }
+                DrmEvent::Error(error) => {
+                    tracing::error!("{:?}", error);
+                }
+            })
+            .unwrap();
+
+        // Prefer GLES on the render node of the device, fall back to pixman if EGL is unusable.
+        let (render_node, renderer) = match unsafe { EGLDisplay::new(gbm.clone()) } {
+            Ok(display) => {
+                let render_node = EGLDevice::device_for_display(&display)
+                    .ok()
+                    .and_then(|x| x.try_get_render_node().ok().flatten())
+                    .unwrap_or(node);
+                match self.backend_data.gpus.as_mut().add_node(render_node, gbm.clone()) {
+                    Ok(_) => (render_node, None),
+                    Err(err) => (node, Some(err.to_string())),
+                }
+            }
+            Err(err) => (node, Some(err.to_string())),
+        };
+        let renderer = match renderer {
+            None => DeviceRenderer::Gles,
+            Some(reason) => {
+                tracing::warn!(%node, "GLES is unavailable ({}), falling back to pixman", reason);
+                match PixmanRenderer::new() {
+                    Ok(renderer) => DeviceRenderer::Pixman(renderer),
+                    Err(err) => {
+                        tracing::error!(%node, "Failed to initialize pixman renderer: {}", err);
+                        self.handle.remove(registration_token);
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        self.backend_data.devices.insert(
+            node,
+            DeviceData {
+                surfaces: HashMap::new(),
+                gbm,
+                drm,
+                drm_scanner: DrmScanner::new(),
+                render_node,
+                renderer,
+                registration_token,
+            },
+        );
+
+        self.device_changed(node);
+
+        Ok(())
+    }
+
+    fn connector_connected(&mut self, node: DrmNode, connector: connector::Info, crtc: crtc::Handle) {
+        let device = if let Some(device) = self.backend_data.devices.get_mut(&node) {
+            device
+        } else {
+            return;
+        };
+
+        let (output, drm_mode) = match output_for_connector(&device.drm, &connector) {
+            Some(ret) => ret,
+            None => {
+                tracing::warn!(?connector, "Connector has no modes, skipping");
+                return;
+            }
+        };
+
+        let surface = match device.drm.create_surface(crtc, drm_mode, &[connector.handle()]) {
+            Ok(surface) => surface,
+            Err(err) => {
+                tracing::warn!("Failed to create drm surface: {}", err);
+                return;
+            }
+        };
+
+        let output_config = self.config.output(&output.name()).cloned().unwrap_or_default();
+        let position = output_config.position().unwrap_or_else(|| {
+            let x = self
+                .space
+                .outputs()
+                .fold(0, |acc, o| acc + self.space.output_geometry(o).unwrap().size.w);
+            (x, 0).into()
+        });
+
+        let wl_mode = WlMode::from(drm_mode);
+        output.set_preferred(wl_mode);
+        output.change_current_state(Some(wl_mode), None, None, Some(position));
+        output_config.apply(&output);
+
+        let allocator = GbmAllocator::new(
+            device.gbm.clone(),
+            GbmBufferFlags::RENDERING | GbmBufferFlags::SCANOUT,
+        );
+        let exporter = GbmFramebufferExporter::new(device.gbm.clone(), device.render_node.into());
+        let planes = surface.planes().clone();
+
+        let compositor = match &mut device.renderer {
+            DeviceRenderer::Gles => {
+                let render_formats = match self.backend_data.gpus.single_renderer(&device.render_node) {
+                    Ok(mut renderer) => renderer.as_mut().egl_context().dmabuf_render_formats().clone(),
+                    Err(err) => {
+                        tracing::warn!("Failed to get renderer for {}: {}", device.render_node, err);
+                        return;
+                    }
+                };
+                DrmCompositor::new(
+                    &output,
+                    surface,
+                    Some(planes),
+                    allocator,
+                    exporter,
+                    SUPPORTED_FORMATS.iter().copied(),
+                    render_formats,
+                    device.drm.cursor_size(),
+                    Some(device.gbm.clone()),
+                )
+            }
+            DeviceRenderer::Pixman(renderer) => DrmCompositor::new(
+                &output,
+                surface,
+                Some(planes),
+                allocator,
+                exporter,
+                SUPPORTED_FORMATS_PIXMAN.iter().copied(),
+                renderer.dmabuf_formats(),
+                device.drm.cursor_size(),
+                Some(device.gbm.clone()),
+            ),
+        };
+        let compositor = match compositor {
+            Ok(compositor) => compositor,
+            Err(err) => {
+                tracing::warn!("Failed to create drm compositor: {}", err);
+                return;
+            }
+        };
+
+        // Only an output that can be rendered is advertised and gets apps laid out on it.
+        let global = output.create_global::<AuroraState<UdevData>>(&self.display_handle);
+        self.space.map_output(&output, position);
+        output.user_data().insert_if_missing(|| UdevOutputId {
+            crtc,
+            device_id: node,
+        });
+        self.window_manager.output_added(&output, &mut self.space);
+
+        tracing::info!(name = output.name(), ?wl_mode, "Connector connected");
+        device.surfaces.insert(
+            crtc,
+            SurfaceData {
+                output,
+                global: Some(global),
+                compositor,
+            },
+        );
+
+        self.handle.insert_idle(move |data| data.render(node, crtc));
+    }
+
+    fn connector_disconnected(&mut self, node: DrmNode, connector: connector::Info, crtc: crtc::Handle) {
+        let device = if let Some(device) = self.backend_data.devices.get_mut(&node) {
+            device
+        } else {
+            return;
+        };
+
+        if let Some(mut surface) = device.surfaces.remove(&crtc) {
+            tracing::info!(name = surface.output.name(), ?connector, "Connector disconnected");
+            if let Some(global) = surface.global.take() {
+                self.display_handle.remove_global::<AuroraState<UdevData>>(global);
+            }
+            self.space.unmap_output(&surface.output);
+            self.window_manager.output_removed(&surface.output, &mut self.space);
+        }
+    }
+
+    fn device_changed(&mut self, node: DrmNode) {
+        let device = if let Some(device) = self.backend_data.devices.get_mut(&node) {
+            device
+        } else {
+            return;
+        };
+
+        let scan_result = match device.drm_scanner.scan_connectors(&device.drm) {
+            Ok(scan_result) => scan_result,
+            Err(err) => {
+                tracing::warn!(?err, "Failed to scan connectors");
+                return;
+            }
+        };
+
+        for event in scan_result {
+            match event {
+                DrmScanEvent::Connected {
+                    connector,
+                    crtc: Some(crtc),
+                } => {
+                    self.connector_connected(node, connector, crtc);
+                }
+                DrmScanEvent::Disconnected {
+                    connector,
+                    crtc: Some(crtc),
+                } => {
+                    self.connector_disconnected(node, connector, crtc);
+                }
+                _ => {}
+            }
+        }
+
+        crate::shell::fixup_positions(&mut self.space, &self.config, self.pointer.current_location());
+        self.window_manager.refresh(&mut self.space);
+    }
+
+    fn device_removed(&mut self, node: DrmNode) {
+        let device = if let Some(device) = self.backend_data.devices.get_mut(&node) {
+            device
+        } else {
+            return;
+        };
+
+        let crtcs: Vec<_> = device
+            .drm_scanner
+            .crtcs()
+            .map(|(info, crtc)| (info.clone(), crtc))
+            .collect();
+
+        for (connector, crtc) in crtcs {
+            self.connector_disconnected(node, connector, crtc);
+        }
+
+        tracing::debug!("Surfaces dropped");
+
+        // drop the backends on this side
+        if let Some(device) = self.backend_data.devices.remove(&node) {
+            if let DeviceRenderer::Gles = device.renderer {
+                self.backend_data.gpus.as_mut().remove_node(&device.render_node);
+            }
+            self.handle.remove(device.registration_token);
+            tracing::debug!("Dropping device");
+        }
+
+        crate::shell::fixup_positions(&mut self.space, &self.config, self.pointer.current_location());
+        self.window_manager.refresh(&mut self.space);
+    }
+
+    fn frame_finish(&mut self, dev_id: DrmNode, crtc: crtc::Handle, metadata: &mut Option<DrmEventMetadata>) {
+        let device = match self.backend_data.devices.get_mut(&dev_id) {
+            Some(device) => device,
+            None => return,
+        };
+        let surface = match device.surfaces.get_mut(&crtc) {
+            Some(surface) => surface,
+            None => return,
+        };
+
+        let submit_result = surface
+            .compositor
+            .frame_submitted()
+            .map_err(Into::<SwapBuffersError>::into);
+
+        match submit_result {
+            Ok(user_data) => {
+                if let Some(mut feedback) = user_data.flatten() {
+                    let tp = metadata.as_ref().and_then(|metadata| match metadata.time {
+                        DrmEventTime::Monotonic(tp) => Some(tp),
+                        DrmEventTime::Realtime(_) => None,
+                    });
+                    let seq = metadata.as_ref().map(|metadata| metadata.sequence).unwrap_or(0);
+
+                    let (clock, flags) = if let Some(tp) = tp {
+                        (
+                            tp.into(),
+                            wp_presentation_feedback::Kind::Vsync
+                                | wp_presentation_feedback::Kind::HwClock
+                                | wp_presentation_feedback::Kind::HwCompletion,
+                        )
+                    } else {
+                        (self.clock.now(), wp_presentation_feedback::Kind::Vsync)
+                    };
+
+                    feedback.presented(
+                        clock,
+                        surface
+                            .output
+                            .current_mode()
+                            .map(|mode| Refresh::fixed(Duration::from_secs_f64(1_000f64 / mode.refresh as f64)))
+                            .unwrap_or(Refresh::Unknown),
+                        seq as u64,
+                        flags,
+                    );
+                }
+            }
+            Err(err) => {
+                tracing::warn!("Error during rendering: {:?}", err);
+                if let SwapBuffersError::ContextLost(err) = err {
+                    tracing::error!("Critical Rendering Error: {}", err);
+                    self.running.store(false, Ordering::SeqCst);
+                }
+                return;
+            }
+        };
+
+        self.render(dev_id, crtc);
+    }
+
+    fn render(&mut self, node: DrmNode, crtc: crtc::Handle) {
+        let output = match self
+            .backend_data
+            .devices
+            .get(&node)
+            .and_then(|device| device.surfaces.get(&crtc))
+        {
+            Some(surface) => surface.output.clone(),
+            None => return,
+        };
+
+        let frame_duration = output
+            .current_mode()
+            .map(|mode| Duration::from_secs_f64(1_000f64 / mode.refresh as f64))
+            .unwrap_or(Duration::from_millis(16));
+        let frame_target = self.clock.now() + frame_duration;
+        self.pre_repaint(&output, frame_target);
+
+        let device = self.backend_data.devices.get_mut(&node).unwrap();
+        let surface = device.surfaces.get_mut(&crtc).unwrap();
+
+        let debug_flags = self.backend_data.debug_flags;
+        let result = match &mut device.renderer {
+            DeviceRenderer::Gles => match self.backend_data.gpus.single_renderer(&device.render_node) {
+                Ok(mut renderer) => {
+                    renderer.set_debug_flags(debug_flags);
+                    render_surface::<UdevRenderer<'_>>(
+                        surface,
+                        &mut renderer,
+                        &self.space,
+                        &*self.window_manager,
+                        &output,
+                        self.overview.as_ref(),
+                    )
+                }
+                Err(err) => {
+                    tracing::warn!("Failed to get renderer for {}: {}", device.render_node, err);
+                    return;
+                }
+            },
+            DeviceRenderer::Pixman(renderer) => {
+                renderer.set_debug_flags(debug_flags);
+                render_surface(
+                    surface,
+                    renderer,
+                    &self.space,
+                    &*self.window_manager,
+                    &output,
+                    self.overview.as_ref(),
+                )
+            }
+        };
+
+        match result {
+            Ok((rendered, states)) => {
+                if !rendered {
+                    // Nothing was queued, so there will be no vblank to drive the next frame.
+                    self.handle
+                        .insert_source(Timer::from_duration(frame_duration), move |_, _, data| {
+                            data.render(node, crtc);
+                            TimeoutAction::Drop
+                        })
+                        .expect("failed to schedule frame timer");
+                }
+                // Send frame events so that client start drawing their next frame
+                self.post_repaint(&output, frame_target, None, &states);
+            }
+            Err(SwapBuffersError::ContextLost(err)) => {
+                tracing::error!("Critical Rendering Error: {}", err);
+                self.running.store(false, Ordering::SeqCst);
+            }
+            Err(err) => tracing::warn!("Rendering error: {}", err),
+        }
+    }
+}
+
+/*
+Renders one frame of an output into the `DrmCompositor` of its surface and queues it for scanout.
+Generic over the renderer, so the same path is used for GLES and the pixman fallback.
+Returns whether a frame was queued, together with the render element states.
+*/
+fn render_surface<R>(
+    surface: &mut SurfaceData,
+    renderer: &mut R,
+    space: &Space<WindowElement>,
+    window_manager: &dyn WindowManager,
+    output: &Output,
+    overview: Option<&Overview>,
+) -> Result<(bool, RenderElementStates), SwapBuffersError>
+where
+    R: Renderer + ImportAll + ImportMem + Bind<Dmabuf>,
+    R::TextureId: Clone + Texture + 'static,
+    R::Error: Into<SwapBuffersError> + Send + Sync + 'static,
+{
+    update_background_snapshots(renderer, space, window_manager, output);
+
+    let custom_elements: Vec<CustomRenderElements<R>> = Vec::new();
+    let (elements, clear_color) = output_elements(output, space, custom_elements, renderer, overview);
+
+    let res = surface
+        .compositor
+        .render_frame::<_, _>(renderer, &elements, clear_color, FrameFlags::DEFAULT)
+        .map_err(|err| match err {
+            RenderFrameError::PrepareFrame(err) => SwapBuffersError::from(err),
+            RenderFrameError::RenderFrame(OutputDamageTrackerError::Rendering(err)) => err.into(),
+            _ => unreachable!(),
+        })?;
+
+    let rendered = !res.is_empty;
+    let states = res.states;
+    if rendered {
+        let output_presentation_feedback = take_presentation_feedback(output, space, &states);
+        surface
+            .compositor
+            .queue_frame(Some(output_presentation_feedback))
+            .map_err(Into::<SwapBuffersError>::into)?;
+    }
+
+    Ok((rendered, states))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        os::unix::io::{AsFd, BorrowedFd},
+    };
+
+    use smithay::reexports::drm::{
+        self,
+        control::{self, connector, Mode as DrmMode},
+    };
+
+    use super::*;
+
+    /*
+    A `ControlDevice` without a GPU behind it, like `FakeDevice` in the doctest helpers of
+    smithay-drm-extras. Its fd is `/dev/null`, so every ioctl (eg. reading the EDID) fails.
+    */
+    struct FakeDevice(File);
+
+    impl FakeDevice {
+        fn new() -> Self {
+            FakeDevice(File::open("/dev/null").unwrap())
+        }
+    }
+
+    impl AsFd for FakeDevice {
+        fn as_fd(&self) -> BorrowedFd<'_> {
+            self.0.as_fd()
+        }
+    }
+
+    impl drm::Device for FakeDevice {}
+    impl control::Device for FakeDevice {}
+
+    fn mode(width: u16, height: u16, preferred: bool) -> DrmMode {
+        // SAFETY: `drm_mode_modeinfo` is plain old data, zero is a valid value for every field.
+        let mut info: drm_ffi::drm_mode_modeinfo = unsafe { std::mem::zeroed() };
+        info.hdisplay = width;
+        info.vdisplay = height;
+        info.vrefresh = 60;
+        info.clock = width as u32 * height as u32 * 60 / 1000;
+        if preferred {
+            info.type_ = drm_ffi::DRM_MODE_TYPE_PREFERRED;
+        }
+        DrmMode::from(info)
+    }
+
+    fn connector(modes: &[DrmMode]) -> ConnectorDescription<'_> {
+        ConnectorDescription {
+            handle: control::from_u32::<connector::Handle>(42).unwrap(),
+            name: "eDP-1".into(),
+            modes,
+            size: Some((310, 170)),
+            subpixel: connector::SubPixel::HorizontalRgb,
+        }
+    }
+
+    #[test]
+    fn picks_the_preferred_mode() {
+        let modes = [mode(1280, 720, false), mode(1920, 1080, true), mode(800, 600, false)];
+        let (output, drm_mode) = output_for_connector(&FakeDevice::new(), connector(&modes)).unwrap();
+
+        assert_eq!(drm_mode.size(), (1920, 1080));
+        assert_eq!(output.name(), "eDP-1");
+        let properties = output.physical_properties();
+        assert_eq!(properties.size, (310, 170).into());
+        // There is no EDID to read on the fake device.
+        assert_eq!(properties.make, "Unknown");
+        assert_eq!(properties.model, "Unknown");
+    }
+
+    #[test]
+    fn falls_back_to_the_first_mode() {
+        let modes = [mode(1280, 720, false), mode(1920, 1080, false)];
+        let (_, drm_mode) = output_for_connector(&FakeDevice::new(), connector(&modes)).unwrap();
+        assert_eq!(drm_mode.size(), (1280, 720));
+    }
+
+    #[test]
+    fn skips_connectors_without_modes() {
+        assert!(output_for_connector(&FakeDevice::new(), connector(&[])).is_none());
+    }
+}
@@ -0,0 +1,251 @@
+use smithay::{
+    desktop::Space,
+    output::Output,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{Logical, Point, Size},
+    wayland::shell::xdg::ToplevelSurface,
+};
+
+use crate::{
+    gestures::Gesture,
+    input_handler::KeyAction,
+    shell::{WindowElement, WindowId},
+};
+
+pub use self::{
+    stack::FullscreenStack,
+    tiling::MasterStack,
+    workspaces::{Workspaces, MAX_WORKSPACES},
+};
+
+mod stack;
+mod tiling;
+mod workspaces;
+
+/*
+Direction to cycle through running apps in.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchDirection {
+    /// The app used before the active one
+    Next,
+    /// Back to the app the last `Next` switched away from
+    Previous,
+}
+
+/*
+Window management policy of the compositor.
+
+The policy owns the list of windows and decides which ones are mapped in the `Space`, where,
+and with which xdg states. Aurora calls the hooks below when something happens that may change
+the layout, everything else (input routing, rendering, protocol handling) stays in the compositor.
+
+`Workspaces` is the policy used by default, it runs a `FullscreenStack` per workspace.
+`MasterStack` tiles every app instead, it is picked with `layout = "tiling"` in the config.
+An embedder can swap the policy at runtime with `AuroraState::set_window_manager`.
+*/
+pub trait WindowManager: std::fmt::Debug {
+    /// Every window managed by the policy, including the ones that are not mapped.
+    fn windows(&self) -> Box<dyn Iterator<Item = &WindowElement> + '_>;
+
+    /// The window that should have the keyboard focus, if any.
+    fn get_active_window(&self) -> Option<&WindowElement>;
+
+    /// Finds a managed window by its id.
+    fn window(&self, id: WindowId) -> Option<&WindowElement> {
+        self.windows().find(|window| window.id() == id)
+    }
+
+    /// The output a window belongs to, even while it is not mapped, if the policy assigns outputs.
+    fn window_output(&self, id: WindowId) -> Option<Output> {
+        let _ = id;
+        None
+    }
+
+    /// Ids of the managed windows, the most recently used first.
+    fn mru(&self) -> Vec<WindowId>;
+
+    /// Switches to the next app, in the order of the app switcher.
+    fn focus_next(&mut self, space: &mut Space<WindowElement>);
+
+    /// Switches back to the previous app, undoing `focus_next`.
+    fn focus_prev(&mut self, space: &mut Space<WindowElement>);
+
+    /// Brings the window with the given id to the front, returns false when there is no such window.
+    fn activate(&mut self, id: WindowId, space: &mut Space<WindowElement>) -> bool;
+
+    /// A client created a new toplevel.
+    fn new_toplevel(&mut self, window: WindowElement, space: &mut Space<WindowElement>);
+
+    /// A toplevel was destroyed by its client.
+    fn toplevel_destroyed(&mut self, surface: &ToplevelSurface, space: &mut Space<WindowElement>);
+
+    /// A toplevel asked to be fullscreen, maximized or to leave those states.
+    /// The pending state already holds the request.
+    fn toplevel_state_changed(&mut self, window: &WindowElement, space: &mut Space<WindowElement>) {
+        let _ = window;
+        self.refresh(space);
+    }
+
+    /// The window was opened without taking the focus, the app used before it where it is
+    /// shown (the same output and workspace) gets the focus back. Nothing happens when the
+    /// window is not the active one.
+    fn activate_previous(&mut self, window: &WindowElement, space: &mut Space<WindowElement>) {
+        let _ = (window, space);
+    }
+
+    /// A client asked for a window to be activated (xdg-activation).
+    fn activation_request(&mut self, window: &WindowElement, space: &mut Space<WindowElement>) {
+        self.activate(window.id(), space);
+    }
+
+    /// An output was mapped in the space.
+    fn output_added(&mut self, output: &Output, space: &mut Space<WindowElement>) {
+        let _ = output;
+        self.refresh(space);
+    }
+
+    /// An output was unmapped from the space, the windows that were on it have to go somewhere else.
+    fn output_removed(&mut self, output: &Output, space: &mut Space<WindowElement>) {
+        let _ = output;
+        self.refresh(space);
+    }
+
+    /// The pointer or a finger went to an output, new windows should open there.
+    fn output_focused(&mut self, output: &Output) {
+        let _ = output;
+    }
+
+    /// A client asked for its window to be shown on a given output, eg. to go fullscreen there.
+    fn move_to_output(&mut self, window: &WindowElement, output: &Output, space: &mut Space<WindowElement>) {
+        let _ = (window, output);
+        self.refresh(space);
+    }
+
+    /// A window rule wants a window on a workspace of its output, counted from 0.
+    fn move_to_workspace(&mut self, window: &WindowElement, workspace: usize, space: &mut Space<WindowElement>) {
+        let _ = (window, workspace);
+        self.refresh(space);
+    }
+
+    /// The mode, scale, transform or position of an output changed.
+    fn output_resized(&mut self, output: &Output, space: &mut Space<WindowElement>) {
+        let _ = output;
+        self.refresh(space);
+    }
+
+    /// A system navigation gesture was recognised.
+    fn gesture(&mut self, gesture: Gesture, space: &mut Space<WindowElement>);
+
+    /// A key binding was triggered, returns whether the policy handled the action.
+    /// Actions that are not handled here are executed by the compositor.
+    fn key_action(&mut self, action: &KeyAction, space: &mut Space<WindowElement>) -> bool {
+        let _ = (action, space);
+        false
+    }
+
+    /// Whether a divider between tiled windows is under the point. Pressing there starts
+    /// dragging it instead of going to a client.
+    fn divider_under(&self, location: Point<f64, Logical>, space: &Space<WindowElement>) -> bool {
+        let _ = (location, space);
+        false
+    }
+
+    /// The divider is being dragged to the point.
+    fn move_divider(&mut self, location: Point<f64, Logical>, space: &mut Space<WindowElement>) {
+        let _ = (location, space);
+    }
+
+    /// The window shown as a picture-in-picture tile, if any. It can be dragged around,
+    /// a tap on it activates it.
+    fn pip_window(&self) -> Option<&WindowElement> {
+        None
+    }
+
+    /// The picture-in-picture tile was dragged and let go, eg. to snap it into a corner.
+    fn pip_moved(&mut self, space: &mut Space<WindowElement>) {
+        self.refresh(space);
+    }
+
+    /// Whether windows float freely (desktop mode) instead of being laid out for touch (tablet mode).
+    fn desktop_mode(&self) -> bool {
+        false
+    }
+
+    /// Switches between tablet and desktop mode, eg. when a convertible is folded.
+    fn set_desktop_mode(&mut self, enabled: bool, space: &mut Space<WindowElement>) {
+        let _ = (enabled, space);
+    }
+
+    /// Called before every repaint, to move windows around while an animation runs.
+    fn animate(&mut self, space: &mut Space<WindowElement>) {
+        let _ = space;
+    }
+
+    /// Lays out every window again, eg. after outputs moved around.
+    fn refresh(&mut self, space: &mut Space<WindowElement>);
+}
+
+/// The xdg states of a window tiled on every side
+pub(crate) const TILED_STATES: [xdg_toplevel::State; 4] = [
+    xdg_toplevel::State::TiledLeft,
+    xdg_toplevel::State::TiledRight,
+    xdg_toplevel::State::TiledTop,
+    xdg_toplevel::State::TiledBottom,
+];
+
+pub(crate) fn is_fullscreen(window: &WindowElement) -> bool {
+    window
+        .0
+        .toplevel()
+        .map(|toplevel| toplevel.with_pending_state(|state| state.states.contains(xdg_toplevel::State::Fullscreen)))
+        .unwrap_or(false)
+}
+
+/*
+Whether the window is placed freely, and not fullscreen, maximized, tiled or a
+picture-in-picture tile placed by the policy.
+Only floating windows can be moved and resized by the user.
+*/
+pub(crate) fn is_floating(window: &WindowElement) -> bool {
+    if window.is_pip() {
+        return false;
+    }
+    window
+        .0
+        .toplevel()
+        .map(|toplevel| {
+            toplevel.with_pending_state(|state| {
+                !state.states.contains(xdg_toplevel::State::Fullscreen)
+                    && !state.states.contains(xdg_toplevel::State::Maximized)
+                    && !TILED_STATES.iter().any(|tiled| state.states.contains(*tiled))
+            })
+        })
+        .unwrap_or(true)
+}
+
+/*
+Maximizes a window like every app laid out by the policy, unless a window rule makes it float.
+*/
+pub(crate) fn maximize(window: &WindowElement) {
+    if window.rule().floating() {
+        return;
+    }
+    if let Some(toplevel) = window.0.toplevel() {
+        toplevel.with_pending_state(|state| {
+            state.states.set(xdg_toplevel::State::Maximized);
+        });
+    }
+}
+
+/*
+The size a floating window is configured with before its first commit, set by a window rule.
+Afterwards the app keeps whatever size it has.
+*/
+pub(crate) fn initial_size(window: &WindowElement) -> Option<Size<i32, Logical>> {
+    let toplevel = window.0.toplevel()?;
+    if toplevel.is_initial_configure_sent() {
+        return None;
+    }
+    window.rule().size.map(Size::from)
+}
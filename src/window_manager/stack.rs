@@ -0,0 +1,891 @@
+use smithay::{
+    desktop::{space::SpaceElement, Space},
+    output::Output,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{IsAlive, Logical, Point, Rectangle, Size},
+    wayland::shell::xdg::ToplevelSurface,
+};
+
+use crate::{
+    gestures::Gesture,
+    input_handler::KeyAction,
+    shell::{non_exclusive_zone, FloatingGeometry, FullscreenSurface, WindowElement, WindowId},
+};
+
+use super::{initial_size, is_floating, is_fullscreen, maximize, SwitchDirection, WindowManager, TILED_STATES};
+
+/// Width of the divider between the two apps of a split, in logical pixels
+const DIVIDER_WIDTH: i32 = 12;
+/// Smallest share of the zone the divider leaves to either app
+const MIN_SPLIT_RATIO: f64 = 0.25;
+/// Largest share of the zone's width and height a picture-in-picture tile takes
+const PIP_SIZE: f64 = 0.3;
+/// Space between a picture-in-picture tile and the edges of the zone
+const PIP_MARGIN: i32 = 16;
+/// Offset between the windows of a cascade in desktop mode, in logical pixels
+const CASCADE_STEP: i32 = 32;
+
+/*
+Two apps shown side by side, or above each other on portrait outputs, with a divider between them.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Split {
+    /// The app on the left, or on top
+    first: WindowId,
+    /// The app on the right, or at the bottom
+    second: WindowId,
+    /// Share of the zone taken by the first app
+    ratio: f64,
+}
+
+impl Split {
+    fn contains(&self, id: WindowId) -> bool {
+        self.first == id || self.second == id
+    }
+
+    /*
+    Cuts the zone into the boxes of the first app, the divider and the second app.
+    */
+    fn layout(&self, zone: Rectangle<i32, Logical>) -> [Rectangle<i32, Logical>; 3] {
+        if zone.size.w >= zone.size.h {
+            let first_width = ((zone.size.w - DIVIDER_WIDTH) as f64 * self.ratio).round() as i32;
+            let divider_x = zone.loc.x + first_width;
+            [
+                Rectangle::from_loc_and_size(zone.loc, (first_width, zone.size.h)),
+                Rectangle::from_loc_and_size((divider_x, zone.loc.y), (DIVIDER_WIDTH, zone.size.h)),
+                Rectangle::from_loc_and_size(
+                    (divider_x + DIVIDER_WIDTH, zone.loc.y),
+                    (zone.size.w - first_width - DIVIDER_WIDTH, zone.size.h),
+                ),
+            ]
+        } else {
+            let first_height = ((zone.size.h - DIVIDER_WIDTH) as f64 * self.ratio).round() as i32;
+            let divider_y = zone.loc.y + first_height;
+            [
+                Rectangle::from_loc_and_size(zone.loc, (zone.size.w, first_height)),
+                Rectangle::from_loc_and_size((zone.loc.x, divider_y), (zone.size.w, DIVIDER_WIDTH)),
+                Rectangle::from_loc_and_size(
+                    (zone.loc.x, divider_y + DIVIDER_WIDTH),
+                    (zone.size.w, zone.size.h - first_height - DIVIDER_WIDTH),
+                ),
+            ]
+        }
+    }
+
+    /*
+    Centers the divider on a point, as far as both apps keep their minimal share.
+    */
+    fn move_divider(&mut self, location: Point<f64, Logical>, zone: Rectangle<i32, Logical>) {
+        let half_divider = DIVIDER_WIDTH as f64 / 2.0;
+        let ratio = if zone.size.w >= zone.size.h {
+            (location.x - zone.loc.x as f64 - half_divider) / (zone.size.w - DIVIDER_WIDTH) as f64
+        } else {
+            (location.y - zone.loc.y as f64 - half_divider) / (zone.size.h - DIVIDER_WIDTH) as f64
+        };
+        self.ratio = ratio.clamp(MIN_SPLIT_RATIO, 1.0 - MIN_SPLIT_RATIO);
+    }
+}
+
+/*
+Corner of the zone a picture-in-picture tile snaps to.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /*
+    The corner of the quarter of the zone the point is in.
+    */
+    fn nearest(point: Point<i32, Logical>, zone: Rectangle<i32, Logical>) -> Self {
+        let left = point.x < zone.loc.x + zone.size.w / 2;
+        let top = point.y < zone.loc.y + zone.size.h / 2;
+        match (left, top) {
+            (true, true) => Corner::TopLeft,
+            (false, true) => Corner::TopRight,
+            (true, false) => Corner::BottomLeft,
+            (false, false) => Corner::BottomRight,
+        }
+    }
+
+    /*
+    Location of a tile of the given size in this corner of the zone.
+    */
+    fn place(self, size: Size<i32, Logical>, zone: Rectangle<i32, Logical>) -> Point<i32, Logical> {
+        let left = zone.loc.x + PIP_MARGIN;
+        let right = zone.loc.x + zone.size.w - size.w - PIP_MARGIN;
+        let top = zone.loc.y + PIP_MARGIN;
+        let bottom = zone.loc.y + zone.size.h - size.h - PIP_MARGIN;
+        match self {
+            Corner::TopLeft => (left, top),
+            Corner::TopRight => (right, top),
+            Corner::BottomLeft => (left, bottom),
+            Corner::BottomRight => (right, bottom),
+        }
+        .into()
+    }
+}
+
+/*
+A window demoted to a small tile floating above the app in use, eg. a video call.
+*/
+#[derive(Debug)]
+struct Pip {
+    window: WindowElement,
+    corner: Corner,
+    /// Size of the window before it was demoted, the tile keeps its aspect ratio
+    natural_size: Size<i32, Logical>,
+}
+
+impl Pip {
+    fn size(&self, zone: Rectangle<i32, Logical>) -> Size<i32, Logical> {
+        let max_w = zone.size.w as f64 * PIP_SIZE;
+        let max_h = zone.size.h as f64 * PIP_SIZE;
+        if self.natural_size.w <= 0 || self.natural_size.h <= 0 {
+            return Size::from((max_w.round() as i32, max_h.round() as i32));
+        }
+        let scale = f64::min(max_w / self.natural_size.w as f64, max_h / self.natural_size.h as f64);
+        Size::from((
+            (self.natural_size.w as f64 * scale).round() as i32,
+            (self.natural_size.h as f64 * scale).round() as i32,
+        ))
+    }
+}
+
+/*
+The default window management policy, made for phones: apps are stacked on top of each
+other, and only the one on top is shown, maximized on the first output (or the one set
+with `set_output`).
+
+On tablets, two apps can be paired in a split and shown side by side, with a divider
+that can be dragged to share the output between them. One app can also be demoted to a
+picture-in-picture tile that stays on top of everything else.
+
+In desktop mode (eg. a convertible with its keyboard, or an external monitor) every app
+floats instead, stacked in switching order: the active one is raised on top of the others.
+*/
+#[derive(Debug, Default)]
+pub struct FullscreenStack {
+    /// Windows in app switching order, the active one is last
+    windows: Vec<WindowElement>,
+    /// Window ids, the most recently active first
+    mru: Vec<WindowId>,
+    /// Every app is hidden, the home screen is shown
+    showing_home: bool,
+    /// Two apps paired in a split, shown together whenever one of them is active
+    split: Option<Split>,
+    /// The picture-in-picture tile, its window is not part of `windows`
+    pip: Option<Pip>,
+    /// Output the apps are laid out on, the first one when not set
+    output: Option<Output>,
+    /// Apps float instead of being maximized
+    desktop: bool,
+}
+
+impl FullscreenStack {
+    pub fn new() -> Self {
+        Self {
+            windows: Vec::new(),
+            mru: Vec::new(),
+            showing_home: false,
+            split: None,
+            pip: None,
+            output: None,
+            desktop: false,
+        }
+    }
+
+    /*
+    Lays the apps out on the given output, or on the first one with `None`.
+    Nothing is shown while that output is not in the space.
+    */
+    pub fn set_output(&mut self, output: Option<Output>) {
+        self.output = output;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /*
+    Switches between the fullscreen stack and desktop mode, the caller lays the apps out again.
+    Apps leave and get back their maximized state, the floating geometry of each one is kept
+    in the meantime.
+    */
+    pub fn set_desktop(&mut self, enabled: bool, space: &Space<WindowElement>) {
+        if self.desktop == enabled {
+            return;
+        }
+        self.desktop = enabled;
+        for window in self.windows.iter() {
+            if !enabled && is_floating(window) {
+                if let Some(geometry) = space.element_geometry(window) {
+                    FloatingGeometry::save(window, geometry);
+                }
+            }
+            let Some(toplevel) = window.0.toplevel() else {
+                continue;
+            };
+            toplevel.with_pending_state(|state| {
+                for tiled in TILED_STATES {
+                    state.states.unset(tiled);
+                }
+                if enabled {
+                    state.states.unset(xdg_toplevel::State::Maximized);
+                }
+            });
+            if !enabled {
+                maximize(window);
+            }
+        }
+    }
+
+    pub fn insert_window(&mut self, window: WindowElement) {
+        // Apps are shown maximized, unless they ask for something else or float on a desktop.
+        if !self.desktop {
+            maximize(&window);
+        }
+        self.mru.insert(0, window.id());
+        self.windows.push(window.clone());
+        self.showing_home = false;
+    }
+
+    /*
+    Hides every app, they keep running in the background.
+    */
+    pub fn show_home(&mut self, space: &mut Space<WindowElement>) {
+        self.showing_home = true;
+        self.refresh_geometry(space);
+    }
+
+    /*
+    Returns to the previously used app, or to the home screen when there is none.
+    */
+    pub fn back(&mut self, space: &mut Space<WindowElement>) {
+        if self.showing_home || self.windows.len() < 2 {
+            self.show_home(space);
+        } else {
+            self.switch_app(SwitchDirection::Next, space);
+        }
+    }
+
+    /*
+    Cycles the active app through the running ones.
+    Coming from the home screen, the last active app is shown again.
+    */
+    pub fn switch_app(&mut self, direction: SwitchDirection, space: &mut Space<WindowElement>) {
+        if self.windows.is_empty() {
+            return;
+        }
+
+        if !self.showing_home {
+            match direction {
+                SwitchDirection::Next => self.windows.rotate_right(1),
+                SwitchDirection::Previous => self.windows.rotate_left(1),
+            }
+        }
+        self.showing_home = false;
+        self.refresh_geometry(space);
+    }
+
+    /*
+    Pairs the active app with the one used before it in a split, or breaks up the split
+    that is shown.
+    */
+    pub fn toggle_split(&mut self, space: &mut Space<WindowElement>) {
+        if self.shown_split().is_some() {
+            self.split = None;
+        } else if let Some(active) = self.get_active_window().map(|w| w.id()) {
+            let Some(previous) = self.mru.iter().copied().find(|id| *id != active) else {
+                return;
+            };
+            self.split = Some(Split {
+                first: previous,
+                second: active,
+                ratio: 0.5,
+            });
+        }
+        self.refresh_geometry(space);
+    }
+
+    /*
+    Demotes the active app to a picture-in-picture tile over the next one,
+    or brings the tile back to fullscreen.
+    */
+    pub fn toggle_pip(&mut self, space: &mut Space<WindowElement>) {
+        if self.pip.is_some() {
+            self.restore_pip();
+        } else if !self.showing_home {
+            let Some(window) = self.windows.pop() else {
+                return;
+            };
+            window.set_pip(true);
+            self.pip = Some(Pip {
+                natural_size: window.geometry().size,
+                window,
+                corner: Corner::BottomRight,
+            });
+        }
+        self.refresh_geometry(space);
+    }
+
+    /*
+    Puts the window of the picture-in-picture tile back on top of the stack, fullscreen.
+    */
+    fn restore_pip(&mut self) {
+        let Some(pip) = self.pip.take() else {
+            return;
+        };
+        pip.window.set_pip(false);
+        if !self.desktop {
+            maximize(&pip.window);
+        }
+        self.windows.push(pip.window);
+        self.showing_home = false;
+    }
+
+    /*
+    The split, if the active app is part of it.
+    */
+    fn shown_split(&self) -> Option<Split> {
+        let active = self.get_active_window()?.id();
+        self.split.filter(|split| split.contains(active))
+    }
+
+    /*
+    Unmaps every app, eg. when another workspace is shown.
+    */
+    pub(super) fn hide(&self, space: &mut Space<WindowElement>) {
+        for window in self.windows() {
+            space.unmap_elem(window);
+        }
+    }
+
+    /*
+    Takes a window out of the stack, eg. to move it to another workspace.
+    The split it was part of is broken up.
+    */
+    pub(super) fn remove_window(&mut self, id: WindowId) -> Option<WindowElement> {
+        if self.split.is_some_and(|split| split.contains(id)) {
+            self.split = None;
+        }
+        self.mru.retain(|other| *other != id);
+        if self.pip.as_ref().is_some_and(|pip| pip.window.id() == id) {
+            let pip = self.pip.take()?;
+            pip.window.set_pip(false);
+            return Some(pip.window);
+        }
+        let index = self.windows.iter().position(|w| w.id() == id)?;
+        Some(self.windows.remove(index))
+    }
+
+    /*
+    Empties the stack, returning the windows in app switching order.
+    */
+    pub(super) fn take_windows(&mut self) -> Vec<WindowElement> {
+        self.mru.clear();
+        self.showing_home = false;
+        self.split = None;
+        let mut windows = std::mem::take(&mut self.windows);
+        if let Some(pip) = self.pip.take() {
+            pip.window.set_pip(false);
+            windows.push(pip.window);
+        }
+        windows
+    }
+
+    pub(super) fn remove_dead_window(&mut self) {
+        self.windows.retain(|w| w.alive());
+        if self.pip.as_ref().is_some_and(|pip| !pip.window.alive()) {
+            self.pip = None;
+        }
+        let windows = &self.windows;
+        let pip = self.pip.as_ref().map(|pip| pip.window.id());
+        self.mru.retain(|id| pip == Some(*id) || windows.iter().any(|w| w.id() == *id));
+    }
+
+    pub fn refresh_geometry(&mut self, space: &mut Space<WindowElement>) {
+        space.refresh();
+
+        // Remove dead windows/closed apps.
+        self.remove_dead_window();
+
+        // Without an output there is nothing to lay out.
+        let Some((output, output_geometry, zone)) = usable_zone(space, self.output.as_ref()) else {
+            return;
+        };
+        if self.desktop {
+            self.layout_desktop(&output, output_geometry, zone, space);
+        } else {
+            self.layout_stack(&output, output_geometry, zone, space);
+        }
+        self.layout_pip(zone, space);
+    }
+
+    /*
+    Shows the active app, or both apps of its split, and hides the others.
+    */
+    fn layout_stack(
+        &mut self,
+        output: &Output,
+        output_geometry: Rectangle<i32, Logical>,
+        zone: Rectangle<i32, Logical>,
+        space: &mut Space<WindowElement>,
+    ) {
+        output.user_data().insert_if_missing(FullscreenSurface::default);
+        let fullscreen_surface = output.user_data().get::<FullscreenSurface>().unwrap();
+
+        // A split collapses back to a single app when one side closed, went fullscreen
+        // or became the picture-in-picture tile.
+        if let Some(split) = self.split {
+            let pip = self.pip.as_ref().map(|pip| pip.window.id());
+            let broken = [split.first, split.second]
+                .into_iter()
+                .any(|id| pip == Some(id) || self.window(id).map(is_fullscreen).unwrap_or(true));
+            if broken {
+                self.split = None;
+            }
+        }
+        let split = self.shown_split();
+
+        // Only display the window at the last index of windows::Vec, or both apps of its split
+        let active_window = self.get_active_window().cloned();
+        for window in self.windows.iter() {
+            let shown = match split {
+                Some(split) => split.contains(window.id()),
+                None => Some(window) == active_window.as_ref(),
+            };
+            if !shown {
+                if is_floating(window) {
+                    if let Some(geometry) = space.element_geometry(window) {
+                        FloatingGeometry::save(window, geometry);
+                    }
+                }
+                space.unmap_elem(window);
+            }
+        }
+
+        let Some(window) = active_window else {
+            fullscreen_surface.clear();
+            return;
+        };
+        let id = window.id();
+        self.mru.retain(|other| *other != id);
+        self.mru.insert(0, id);
+
+        if let Some(split) = split {
+            fullscreen_surface.clear();
+            self.layout_split(split, &window, zone, space);
+            return;
+        }
+
+        untile(&window);
+        let Some(toplevel) = window.0.toplevel() else {
+            return;
+        };
+
+        let (fullscreen, maximized) = toplevel.with_pending_state(|state| {
+            (
+                state.states.contains(xdg_toplevel::State::Fullscreen),
+                state.states.contains(xdg_toplevel::State::Maximized),
+            )
+        });
+
+        // Floating windows go back to their last geometry, or get centered in the zone.
+        let floating_geometry = FloatingGeometry::get(&window);
+        let geometry = if fullscreen {
+            output_geometry
+        } else if maximized {
+            zone
+        } else {
+            floating_geometry.unwrap_or_else(|| {
+                let size = initial_size(&window).unwrap_or_else(|| window.geometry().size);
+                let loc = zone.loc + Point::from(((zone.size.w - size.w) / 2, (zone.size.h - size.h) / 2));
+                Rectangle::from_loc_and_size(loc, size)
+            })
+        };
+
+        if fullscreen {
+            fullscreen_surface.set(window.clone());
+        } else {
+            fullscreen_surface.clear();
+        }
+
+        toplevel.with_pending_state(|state| {
+            state.size = if fullscreen || maximized {
+                Some(geometry.size)
+            } else {
+                floating_geometry.map(|geo| geo.size).or_else(|| initial_size(&window))
+            };
+        });
+        if toplevel.is_initial_configure_sent() {
+            toplevel.send_pending_configure();
+        }
+
+        space.map_element(window.clone(), geometry.loc, true);
+    }
+
+    /*
+    Maps every app where it floats, from the bottom of the stack to the active one on top.
+    Apps that never floated are cascaded from the top left corner of the zone.
+    */
+    fn layout_desktop(
+        &mut self,
+        output: &Output,
+        output_geometry: Rectangle<i32, Logical>,
+        zone: Rectangle<i32, Logical>,
+        space: &mut Space<WindowElement>,
+    ) {
+        output.user_data().insert_if_missing(FullscreenSurface::default);
+        let fullscreen_surface = output.user_data().get::<FullscreenSurface>().unwrap();
+
+        if self.showing_home {
+            fullscreen_surface.clear();
+            for window in self.windows.iter() {
+                if let Some(geometry) = space.element_geometry(window).filter(|_| is_floating(window)) {
+                    FloatingGeometry::save(window, geometry);
+                }
+                space.unmap_elem(window);
+            }
+            return;
+        }
+
+        let active_window = self.get_active_window().cloned();
+        match active_window.as_ref() {
+            Some(window) if is_fullscreen(window) => fullscreen_surface.set(window.clone()),
+            _ => {
+                fullscreen_surface.clear();
+            }
+        }
+        if let Some(id) = active_window.as_ref().map(|w| w.id()) {
+            self.mru.retain(|other| *other != id);
+            self.mru.insert(0, id);
+        }
+
+        for window in self.windows.iter() {
+            let Some(toplevel) = window.0.toplevel() else {
+                continue;
+            };
+            let (fullscreen, maximized) = toplevel.with_pending_state(|state| {
+                (
+                    state.states.contains(xdg_toplevel::State::Fullscreen),
+                    state.states.contains(xdg_toplevel::State::Maximized),
+                )
+            });
+
+            let floating_geometry = FloatingGeometry::get(window);
+            let geometry = if fullscreen {
+                output_geometry
+            } else if maximized {
+                zone
+            } else {
+                let location = floating_geometry
+                    .map(|geometry| geometry.loc)
+                    .unwrap_or_else(|| self.cascade_location(window, zone));
+                let size = initial_size(window).unwrap_or_else(|| window.geometry().size);
+                let geometry = Rectangle::from_loc_and_size(location, size);
+                if floating_geometry.is_none() {
+                    FloatingGeometry::save(window, geometry);
+                }
+                geometry
+            };
+
+            toplevel.with_pending_state(|state| {
+                for tiled in TILED_STATES {
+                    state.states.unset(tiled);
+                }
+                state.bounds = Some(zone.size);
+                state.size = if fullscreen || maximized {
+                    Some(geometry.size)
+                } else {
+                    // Before its first commit, the app picks its own size.
+                    floating_geometry
+                        .map(|geo| geo.size)
+                        .filter(|size| !size.is_empty())
+                        .or_else(|| initial_size(window))
+                };
+            });
+            if toplevel.is_initial_configure_sent() {
+                toplevel.send_pending_configure();
+            }
+
+            space.map_element(window.clone(), geometry.loc, Some(window) == active_window.as_ref());
+        }
+    }
+
+    /*
+    The first step of the cascade no other floating app sits on, going back to the first one
+    when the window would not fit into the zone anymore.
+    */
+    fn cascade_location(&self, window: &WindowElement, zone: Rectangle<i32, Logical>) -> Point<i32, Logical> {
+        let size = initial_size(window).unwrap_or_else(|| window.geometry().size);
+        let taken: Vec<Point<i32, Logical>> = self
+            .windows
+            .iter()
+            .filter(|other| *other != window)
+            .filter_map(FloatingGeometry::get)
+            .map(|geometry| geometry.loc)
+            .collect();
+
+        let first = zone.loc + Point::from((CASCADE_STEP, CASCADE_STEP));
+        let mut location = first;
+        while taken.contains(&location) {
+            location += Point::from((CASCADE_STEP, CASCADE_STEP));
+            let end = location + Point::from((size.w, size.h));
+            if end.x > zone.loc.x + zone.size.w || end.y > zone.loc.y + zone.size.h {
+                return first;
+            }
+        }
+        location
+    }
+
+    /*
+    Maps the picture-in-picture tile into its corner, without taking the focus from the app below.
+    */
+    fn layout_pip(&self, zone: Rectangle<i32, Logical>, space: &mut Space<WindowElement>) {
+        let Some(pip) = self.pip.as_ref() else {
+            return;
+        };
+        let window = &pip.window;
+        let size = pip.size(zone);
+
+        if let Some(toplevel) = window.0.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Maximized);
+                state.states.unset(xdg_toplevel::State::Fullscreen);
+                state.states.unset(xdg_toplevel::State::Activated);
+                for tiled in TILED_STATES {
+                    state.states.unset(tiled);
+                }
+                state.size = Some(size);
+            });
+            if toplevel.is_initial_configure_sent() {
+                toplevel.send_pending_configure();
+            }
+        }
+
+        let location = pip.corner.place(size, zone);
+        space.map_element(window.clone(), location, false);
+    }
+
+    /*
+    Maps both apps of a split into their side of the zone, tiled on every edge.
+    */
+    fn layout_split(
+        &self,
+        split: Split,
+        active: &WindowElement,
+        zone: Rectangle<i32, Logical>,
+        space: &mut Space<WindowElement>,
+    ) {
+        let [first_geometry, _, second_geometry] = split.layout(zone);
+        let mut tiles = [(split.first, first_geometry), (split.second, second_geometry)];
+        // The active app is mapped last, so it is the one activated.
+        tiles.sort_by_key(|(id, _)| *id == active.id());
+
+        for (id, geometry) in tiles {
+            let Some(window) = self.window(id).cloned() else {
+                continue;
+            };
+            if let Some(toplevel) = window.0.toplevel() {
+                toplevel.with_pending_state(|state| {
+                    state.states.unset(xdg_toplevel::State::Maximized);
+                    for tiled in TILED_STATES {
+                        state.states.set(tiled);
+                    }
+                    state.size = Some(geometry.size);
+                });
+                if toplevel.is_initial_configure_sent() {
+                    toplevel.send_pending_configure();
+                }
+            }
+            space.map_element(window.clone(), geometry.loc, id == active.id());
+        }
+    }
+}
+
+/*
+The output (the first one when `None`) with its geometry, and the part of it not covered
+by exclusive layer surfaces (eg. panels), where apps are laid out.
+*/
+fn usable_zone(
+    space: &Space<WindowElement>,
+    output: Option<&Output>,
+) -> Option<(Output, Rectangle<i32, Logical>, Rectangle<i32, Logical>)> {
+    let output = match output {
+        Some(output) => space.outputs().find(|o| *o == output)?.clone(),
+        None => space.outputs().next()?.clone(),
+    };
+    let output_geometry = space.output_geometry(&output)?;
+    let zone = non_exclusive_zone(space, &output)?;
+    Some((output, output_geometry, zone))
+}
+
+/*
+Takes a window out of a split, it is maximized again like every app shown alone.
+*/
+fn untile(window: &WindowElement) {
+    let Some(toplevel) = window.0.toplevel() else {
+        return;
+    };
+    let was_tiled = toplevel.with_pending_state(|state| {
+        let mut was_tiled = false;
+        for tiled in TILED_STATES {
+            was_tiled |= state.states.unset(tiled);
+        }
+        was_tiled
+    });
+    if was_tiled {
+        maximize(window);
+    }
+}
+
+impl WindowManager for FullscreenStack {
+    fn windows(&self) -> Box<dyn Iterator<Item = &WindowElement> + '_> {
+        Box::new(self.windows.iter().chain(self.pip.as_ref().map(|pip| &pip.window)))
+    }
+
+    fn get_active_window(&self) -> Option<&WindowElement> {
+        if self.showing_home {
+            return None;
+        }
+        self.windows.last()
+    }
+
+    fn mru(&self) -> Vec<WindowId> {
+        self.mru.clone()
+    }
+
+    fn focus_next(&mut self, space: &mut Space<WindowElement>) {
+        self.switch_app(SwitchDirection::Next, space);
+    }
+
+    fn focus_prev(&mut self, space: &mut Space<WindowElement>) {
+        self.switch_app(SwitchDirection::Previous, space);
+    }
+
+    fn activate(&mut self, id: WindowId, space: &mut Space<WindowElement>) -> bool {
+        // Activating the picture-in-picture tile brings it back fullscreen.
+        if self.pip.as_ref().is_some_and(|pip| pip.window.id() == id) {
+            self.restore_pip();
+            self.refresh_geometry(space);
+            return true;
+        }
+
+        // The activated app goes on top of the stack.
+        let Some(index) = self.windows.iter().position(|w| w.id() == id) else {
+            return false;
+        };
+        let window = self.windows.remove(index);
+        self.windows.push(window);
+        self.showing_home = false;
+        self.refresh_geometry(space);
+        true
+    }
+
+    fn activate_previous(&mut self, window: &WindowElement, space: &mut Space<WindowElement>) {
+        let id = window.id();
+        if self.get_active_window().map(|w| w.id()) != Some(id) {
+            return;
+        }
+        let previous = self
+            .mru
+            .iter()
+            .copied()
+            .find(|other| *other != id && self.windows.iter().any(|w| w.id() == *other));
+        if let Some(previous) = previous {
+            self.activate(previous, space);
+        }
+    }
+
+    fn new_toplevel(&mut self, window: WindowElement, space: &mut Space<WindowElement>) {
+        self.insert_window(window.clone());
+        space.map_element(window, (0, 0), false);
+        self.refresh_geometry(space);
+    }
+
+    fn toplevel_destroyed(&mut self, _surface: &ToplevelSurface, space: &mut Space<WindowElement>) {
+        self.refresh_geometry(space);
+    }
+
+    fn gesture(&mut self, gesture: Gesture, space: &mut Space<WindowElement>) {
+        match gesture {
+            Gesture::Home => self.show_home(space),
+            Gesture::Back => self.back(space),
+            Gesture::SwitchApp(SwitchDirection::Next) => self.focus_next(space),
+            Gesture::SwitchApp(SwitchDirection::Previous) => self.focus_prev(space),
+            // A single stack has no other workspace to go to.
+            Gesture::SwitchWorkspace(_) => {}
+        }
+    }
+
+    fn key_action(&mut self, action: &KeyAction, space: &mut Space<WindowElement>) -> bool {
+        match action {
+            KeyAction::ToggleSplit => {
+                self.toggle_split(space);
+                true
+            }
+            KeyAction::TogglePip => {
+                self.toggle_pip(space);
+                true
+            }
+            KeyAction::ToggleDesktopMode => {
+                self.set_desktop(!self.desktop, space);
+                self.refresh_geometry(space);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn divider_under(&self, location: Point<f64, Logical>, space: &Space<WindowElement>) -> bool {
+        let (Some(split), Some((_, _, zone))) = (self.shown_split(), usable_zone(space, self.output.as_ref())) else {
+            return false;
+        };
+        split.layout(zone)[1].to_f64().contains(location)
+    }
+
+    fn move_divider(&mut self, location: Point<f64, Logical>, space: &mut Space<WindowElement>) {
+        if self.shown_split().is_none() {
+            return;
+        }
+        let Some((_, _, zone)) = usable_zone(space, self.output.as_ref()) else {
+            return;
+        };
+        if let Some(split) = self.split.as_mut() {
+            split.move_divider(location, zone);
+        }
+        self.refresh_geometry(space);
+    }
+
+    fn pip_window(&self) -> Option<&WindowElement> {
+        self.pip.as_ref().map(|pip| &pip.window)
+    }
+
+    fn pip_moved(&mut self, space: &mut Space<WindowElement>) {
+        if let (Some(pip), Some((_, _, zone))) = (self.pip.as_mut(), usable_zone(space, self.output.as_ref())) {
+            if let Some(geometry) = space.element_geometry(&pip.window) {
+                let center = geometry.loc + Point::from((geometry.size.w / 2, geometry.size.h / 2));
+                pip.corner = Corner::nearest(center, zone);
+            }
+        }
+        self.refresh_geometry(space);
+    }
+
+    fn desktop_mode(&self) -> bool {
+        self.desktop
+    }
+
+    fn set_desktop_mode(&mut self, enabled: bool, space: &mut Space<WindowElement>) {
+        self.set_desktop(enabled, space);
+        self.refresh_geometry(space);
+    }
+
+    fn refresh(&mut self, space: &mut Space<WindowElement>) {
+        self.refresh_geometry(space);
+    }
+}
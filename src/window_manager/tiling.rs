@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+
+use smithay::{
+    desktop::{space::SpaceElement, Space},
+    output::Output,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{IsAlive, Logical, Point, Rectangle},
+    wayland::shell::xdg::ToplevelSurface,
+};
+
+use crate::{
+    gestures::Gesture,
+    input_handler::KeyAction,
+    shell::{non_exclusive_zone, FloatingGeometry, FullscreenSurface, WindowElement, WindowId},
+};
+
+use super::{initial_size, is_fullscreen, SwitchDirection, WindowManager, TILED_STATES};
+
+/// Space between tiles, in logical pixels. The one next to the master tile is its divider.
+const TILE_GAP: i32 = 8;
+/// How much the master tile grows or shrinks per key press, as a share of the zone
+const RATIO_STEP: f64 = 0.05;
+/// Smallest share of the zone the master tile or the stack keeps
+const MIN_RATIO: f64 = 0.2;
+
+/*
+A tiling window management policy for large displays, selected with `layout = "tiling"`.
+
+Every app is shown: the master tile takes the left part of the zone (the top part on portrait
+outputs), the other apps share the rest in a stack. New apps become the master. The share of
+the master tile is changed with the keyboard or by dragging the gap next to it.
+
+Every output tiles its own apps. New apps open on the output in use, the apps of an unplugged
+output move there. The share of the master tile is the same on every output. Apps a window rule
+makes float are not tiled, they are shown above the tiles.
+*/
+#[derive(Debug)]
+pub struct MasterStack {
+    /// Windows in tiling order, the first one is the master
+    windows: Vec<WindowElement>,
+    /// Window ids, the most recently active first
+    mru: Vec<WindowId>,
+    /// The tile with the keyboard focus
+    active: Option<WindowId>,
+    /// Share of the zone taken by the master tile
+    ratio: f64,
+    /// Output every window is tiled on
+    placement: HashMap<WindowId, Output>,
+    /// The output in use, new apps open there
+    focused: Option<Output>,
+}
+
+impl Default for MasterStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MasterStack {
+    pub fn new() -> Self {
+        Self {
+            windows: Vec::new(),
+            mru: Vec::new(),
+            active: None,
+            ratio: 0.55,
+            placement: HashMap::new(),
+            focused: None,
+        }
+    }
+
+    fn active_index(&self) -> Option<usize> {
+        let active = self.get_active_window()?.id();
+        self.windows.iter().position(|w| w.id() == active)
+    }
+
+    /*
+    Moves the focus to the tile after or before the active one, in tiling order.
+    */
+    fn cycle_focus(&mut self, direction: SwitchDirection, space: &mut Space<WindowElement>) {
+        let Some(index) = self.active_index() else {
+            return;
+        };
+        let len = self.windows.len();
+        let next = match direction {
+            SwitchDirection::Next => (index + 1) % len,
+            SwitchDirection::Previous => (index + len - 1) % len,
+        };
+        self.active = Some(self.windows[next].id());
+        self.refresh_layout(space);
+    }
+
+    /*
+    Swaps the active app with the tile after or before it, the focus stays on the app.
+    */
+    fn move_tile(&mut self, direction: SwitchDirection, space: &mut Space<WindowElement>) {
+        let Some(index) = self.active_index() else {
+            return;
+        };
+        let len = self.windows.len();
+        let other = match direction {
+            SwitchDirection::Next => (index + 1) % len,
+            SwitchDirection::Previous => (index + len - 1) % len,
+        };
+        self.windows.swap(index, other);
+        self.refresh_layout(space);
+    }
+
+    /*
+    Makes the active app the master, or swaps the master with the first app of the stack.
+    */
+    fn promote(&mut self, space: &mut Space<WindowElement>) {
+        match self.active_index() {
+            Some(0) if self.windows.len() > 1 => self.windows.swap(0, 1),
+            Some(index) => {
+                let window = self.windows.remove(index);
+                self.windows.insert(0, window);
+            }
+            None => return,
+        }
+        self.refresh_layout(space);
+    }
+
+    fn resize_master(&mut self, delta: f64, space: &mut Space<WindowElement>) {
+        self.ratio = (self.ratio + delta).clamp(MIN_RATIO, 1.0 - MIN_RATIO);
+        self.refresh_layout(space);
+    }
+
+    /*
+    The tiled windows of an output, in tiling order.
+    */
+    fn tiled_windows<'a>(&'a self, output: &'a Output) -> impl Iterator<Item = &'a WindowElement> + 'a {
+        self.windows
+            .iter()
+            .filter(move |window| !window.rule().floating() && self.placement.get(&window.id()) == Some(output))
+    }
+
+    /*
+    The output in use while it is mapped, or else the first one.
+    */
+    fn focused_output(&self, space: &Space<WindowElement>) -> Option<Output> {
+        self.focused
+            .clone()
+            .filter(|focused| space.outputs().any(|output| output == focused))
+            .or_else(|| space.outputs().next().cloned())
+    }
+
+    /*
+    Puts the new apps and the ones of unplugged outputs on the output in use.
+    */
+    fn place_windows(&mut self, space: &Space<WindowElement>) {
+        let Some(focused) = self.focused_output(space) else {
+            return;
+        };
+        for window in self.windows.iter() {
+            let placed = self
+                .placement
+                .get(&window.id())
+                .is_some_and(|placed| space.outputs().any(|output| output == placed));
+            if !placed {
+                self.placement.insert(window.id(), focused.clone());
+            }
+        }
+    }
+
+    /*
+    Cuts the zone into `count` tiles.
+    */
+    fn tiles(&self, count: usize, zone: Rectangle<i32, Logical>) -> Vec<Rectangle<i32, Logical>> {
+        if count <= 1 {
+            return vec![zone; count];
+        }
+
+        let landscape = zone.size.w >= zone.size.h;
+        let (master, stack) = self.split_zone(zone);
+        let stacked = (count - 1) as i32;
+        let length = if landscape { stack.size.h } else { stack.size.w };
+        // With many apps on a small zone the tiles overflow it, but never get an empty size.
+        let tile_length = ((length - TILE_GAP * (stacked - 1)) / stacked).max(1);
+
+        let mut tiles = vec![master];
+        for i in 0..stacked {
+            let offset = i * (tile_length + TILE_GAP);
+            // The last tile takes what the rounding left over.
+            let this_length = if i == stacked - 1 { (length - offset).max(1) } else { tile_length };
+            tiles.push(if landscape {
+                Rectangle::from_loc_and_size((stack.loc.x, stack.loc.y + offset), (stack.size.w, this_length))
+            } else {
+                Rectangle::from_loc_and_size((stack.loc.x + offset, stack.loc.y), (this_length, stack.size.h))
+            });
+        }
+        tiles
+    }
+
+    /*
+    The master area and the stack area, with the gap between them.
+    Like the tiles, both keep a size of at least 1 on zones smaller than the gap.
+    */
+    fn split_zone(&self, zone: Rectangle<i32, Logical>) -> (Rectangle<i32, Logical>, Rectangle<i32, Logical>) {
+        if zone.size.w >= zone.size.h {
+            let master_width = (((zone.size.w - TILE_GAP) as f64 * self.ratio).round() as i32).max(1);
+            (
+                Rectangle::from_loc_and_size(zone.loc, (master_width, zone.size.h)),
+                Rectangle::from_loc_and_size(
+                    (zone.loc.x + master_width + TILE_GAP, zone.loc.y),
+                    ((zone.size.w - master_width - TILE_GAP).max(1), zone.size.h),
+                ),
+            )
+        } else {
+            let master_height = (((zone.size.h - TILE_GAP) as f64 * self.ratio).round() as i32).max(1);
+            (
+                Rectangle::from_loc_and_size(zone.loc, (zone.size.w, master_height)),
+                Rectangle::from_loc_and_size(
+                    (zone.loc.x, zone.loc.y + master_height + TILE_GAP),
+                    (zone.size.w, (zone.size.h - master_height - TILE_GAP).max(1)),
+                ),
+            )
+        }
+    }
+
+    /*
+    The gap between the master tile and the stack of an output, dragging it changes the ratio.
+    */
+    fn divider(&self, output: &Output, zone: Rectangle<i32, Logical>) -> Option<Rectangle<i32, Logical>> {
+        if self.tiled_windows(output).count() < 2 {
+            return None;
+        }
+        let (master, _) = self.split_zone(zone);
+        Some(if zone.size.w >= zone.size.h {
+            Rectangle::from_loc_and_size((master.loc.x + master.size.w, zone.loc.y), (TILE_GAP, zone.size.h))
+        } else {
+            Rectangle::from_loc_and_size((zone.loc.x, master.loc.y + master.size.h), (zone.size.w, TILE_GAP))
+        })
+    }
+
+    fn refresh_layout(&mut self, space: &mut Space<WindowElement>) {
+        space.refresh();
+
+        // Remove dead windows/closed apps, the focus goes back to the app used before.
+        self.windows.retain(|w| w.alive());
+        let windows = &self.windows;
+        self.mru.retain(|id| windows.iter().any(|w| w.id() == *id));
+        self.placement.retain(|id, _| windows.iter().any(|w| w.id() == *id));
+        if self.active.is_some_and(|id| !self.mru.contains(&id)) {
+            self.active = self.mru.first().copied();
+        }
+        self.place_windows(space);
+
+        let active_window = self.get_active_window().cloned();
+        if let Some(id) = active_window.as_ref().map(|w| w.id()) {
+            self.mru.retain(|other| *other != id);
+            self.mru.insert(0, id);
+        }
+
+        let outputs: Vec<Output> = space.outputs().cloned().collect();
+        for output in outputs.iter() {
+            self.layout_output(output, active_window.as_ref(), space);
+        }
+        if let Some(window) = active_window.as_ref() {
+            space.raise_element(window, true);
+        }
+
+        // Floating apps stay above the tiles, the active one on top.
+        let mut floating: Vec<&WindowElement> = self.windows.iter().filter(|w| w.rule().floating()).collect();
+        floating.sort_by_key(|window| Some(*window) == active_window.as_ref());
+        for window in floating {
+            let zone = self
+                .placement
+                .get(&window.id())
+                .and_then(|output| non_exclusive_zone(space, output));
+            if let Some(zone) = zone {
+                self.layout_floating(window, zone, space);
+            }
+            space.raise_element(window, Some(window) == active_window.as_ref());
+        }
+    }
+
+    /*
+    Tiles the apps of an output in its non exclusive zone.
+    */
+    fn layout_output(
+        &self,
+        output: &Output,
+        active_window: Option<&WindowElement>,
+        space: &mut Space<WindowElement>,
+    ) {
+        let (Some(output_geometry), Some(zone)) = (space.output_geometry(output), non_exclusive_zone(space, output))
+        else {
+            return;
+        };
+        output.user_data().insert_if_missing(FullscreenSurface::default);
+        let fullscreen_surface = output.user_data().get::<FullscreenSurface>().unwrap();
+
+        let tiles = self.tiles(self.tiled_windows(output).count(), zone);
+        for (window, tile) in self.tiled_windows(output).zip(tiles) {
+            let Some(toplevel) = window.0.toplevel() else {
+                continue;
+            };
+            // Only the active app can cover the output, the others stay in their tile.
+            let fullscreen = Some(window) == active_window
+                && toplevel.with_pending_state(|state| state.states.contains(xdg_toplevel::State::Fullscreen));
+            let geometry = if fullscreen { output_geometry } else { tile };
+
+            toplevel.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Maximized);
+                for tiled in TILED_STATES {
+                    if fullscreen {
+                        state.states.unset(tiled);
+                    } else {
+                        state.states.set(tiled);
+                    }
+                }
+                state.size = Some(geometry.size);
+            });
+            if toplevel.is_initial_configure_sent() {
+                toplevel.send_pending_configure();
+            }
+            space.map_element(window.clone(), geometry.loc, false);
+        }
+
+        match active_window.filter(|window| self.placement.get(&window.id()) == Some(output)) {
+            Some(window) if is_fullscreen(window) => fullscreen_surface.set(window.clone()),
+            _ => {
+                fullscreen_surface.clear();
+            }
+        }
+    }
+
+    /*
+    Maps a floating app where it was last, or centered in the zone.
+    */
+    fn layout_floating(&self, window: &WindowElement, zone: Rectangle<i32, Logical>, space: &mut Space<WindowElement>) {
+        let Some(toplevel) = window.0.toplevel() else {
+            return;
+        };
+        let floating_geometry = FloatingGeometry::get(window);
+        let geometry = floating_geometry.unwrap_or_else(|| {
+            let size = initial_size(window).unwrap_or_else(|| window.geometry().size);
+            let loc = zone.loc + Point::from(((zone.size.w - size.w) / 2, (zone.size.h - size.h) / 2));
+            Rectangle::from_loc_and_size(loc, size)
+        });
+
+        toplevel.with_pending_state(|state| {
+            state.states.unset(xdg_toplevel::State::Maximized);
+            for tiled in TILED_STATES {
+                state.states.unset(tiled);
+            }
+            state.size = floating_geometry.map(|geo| geo.size).or_else(|| initial_size(window));
+        });
+        if toplevel.is_initial_configure_sent() {
+            toplevel.send_pending_configure();
+        }
+        space.map_element(window.clone(), geometry.loc, false);
+    }
+}
+
+/*
+The output under a point with its non exclusive zone, where its tiles go.
+*/
+fn tiling_zone_at(
+    location: Point<f64, Logical>,
+    space: &Space<WindowElement>,
+) -> Option<(Output, Rectangle<i32, Logical>)> {
+    let output = space.output_under(location).next()?.clone();
+    let zone = non_exclusive_zone(space, &output)?;
+    Some((output, zone))
+}
+
+impl WindowManager for MasterStack {
+    fn windows(&self) -> Box<dyn Iterator<Item = &WindowElement> + '_> {
+        Box::new(self.windows.iter())
+    }
+
+    fn get_active_window(&self) -> Option<&WindowElement> {
+        self.active
+            .and_then(|id| self.windows.iter().find(|w| w.id() == id))
+            .or_else(|| self.windows.first())
+    }
+
+    fn window_output(&self, id: WindowId) -> Option<Output> {
+        self.placement.get(&id).cloned()
+    }
+
+    fn mru(&self) -> Vec<WindowId> {
+        self.mru.clone()
+    }
+
+    fn focus_next(&mut self, space: &mut Space<WindowElement>) {
+        self.cycle_focus(SwitchDirection::Next, space);
+    }
+
+    fn focus_prev(&mut self, space: &mut Space<WindowElement>) {
+        self.cycle_focus(SwitchDirection::Previous, space);
+    }
+
+    fn activate(&mut self, id: WindowId, space: &mut Space<WindowElement>) -> bool {
+        if !self.windows.iter().any(|w| w.id() == id) {
+            return false;
+        }
+        self.active = Some(id);
+        self.refresh_layout(space);
+        true
+    }
+
+    fn activate_previous(&mut self, window: &WindowElement, space: &mut Space<WindowElement>) {
+        let id = window.id();
+        if self.get_active_window().map(|w| w.id()) != Some(id) {
+            return;
+        }
+        let output = self.placement.get(&id);
+        let previous = self
+            .mru
+            .iter()
+            .copied()
+            .find(|other| *other != id && self.placement.get(other) == output);
+        if let Some(previous) = previous {
+            self.activate(previous, space);
+        }
+    }
+
+    fn new_toplevel(&mut self, window: WindowElement, space: &mut Space<WindowElement>) {
+        self.mru.insert(0, window.id());
+        self.active = Some(window.id());
+        self.windows.insert(0, window.clone());
+        space.map_element(window, (0, 0), false);
+        self.refresh_layout(space);
+    }
+
+    fn toplevel_destroyed(&mut self, _surface: &ToplevelSurface, space: &mut Space<WindowElement>) {
+        self.refresh_layout(space);
+    }
+
+    fn output_focused(&mut self, output: &Output) {
+        self.focused = Some(output.clone());
+    }
+
+    fn move_to_output(&mut self, window: &WindowElement, output: &Output, space: &mut Space<WindowElement>) {
+        if self.window(window.id()).is_some() {
+            self.placement.insert(window.id(), output.clone());
+        }
+        self.refresh_layout(space);
+    }
+
+    fn gesture(&mut self, gesture: Gesture, space: &mut Space<WindowElement>) {
+        // Every app is on screen, there is no home screen or history to go back in.
+        if let Gesture::SwitchApp(direction) = gesture {
+            self.cycle_focus(direction, space);
+        }
+    }
+
+    fn key_action(&mut self, action: &KeyAction, space: &mut Space<WindowElement>) -> bool {
+        match action {
+            KeyAction::MoveTileNext => self.move_tile(SwitchDirection::Next, space),
+            KeyAction::MoveTilePrevious => self.move_tile(SwitchDirection::Previous, space),
+            KeyAction::PromoteTile => self.promote(space),
+            KeyAction::GrowMaster => self.resize_master(RATIO_STEP, space),
+            KeyAction::ShrinkMaster => self.resize_master(-RATIO_STEP, space),
+            _ => return false,
+        }
+        true
+    }
+
+    fn divider_under(&self, location: Point<f64, Logical>, space: &Space<WindowElement>) -> bool {
+        tiling_zone_at(location, space)
+            .and_then(|(output, zone)| self.divider(&output, zone))
+            .is_some_and(|divider| divider.to_f64().contains(location))
+    }
+
+    fn move_divider(&mut self, location: Point<f64, Logical>, space: &mut Space<WindowElement>) {
+        // Dragged past the output, the ratio stays where it was.
+        let Some((_, zone)) = tiling_zone_at(location, space) else {
+            return;
+        };
+        let half_gap = TILE_GAP as f64 / 2.0;
+        let ratio = if zone.size.w >= zone.size.h {
+            (location.x - zone.loc.x as f64 - half_gap) / (zone.size.w - TILE_GAP) as f64
+        } else {
+            (location.y - zone.loc.y as f64 - half_gap) / (zone.size.h - TILE_GAP) as f64
+        };
+        self.ratio = ratio.clamp(MIN_RATIO, 1.0 - MIN_RATIO);
+        self.refresh_layout(space);
+    }
+
+    fn refresh(&mut self, space: &mut Space<WindowElement>) {
+        self.refresh_layout(space);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    /*
+    Checks that the stack tiles follow each other with a gap and end with the stack.
+    */
+    fn assert_stacked(tiles: &[Rectangle<i32, Logical>], stack: Rectangle<i32, Logical>, landscape: bool) {
+        for pair in tiles.windows(2) {
+            if landscape {
+                assert_eq!(pair[1].loc.y, pair[0].loc.y + pair[0].size.h + TILE_GAP);
+            } else {
+                assert_eq!(pair[1].loc.x, pair[0].loc.x + pair[0].size.w + TILE_GAP);
+            }
+        }
+        let (first, last) = (tiles[0], tiles[tiles.len() - 1]);
+        assert_eq!(first.loc, stack.loc);
+        assert_eq!(last.loc + last.size.to_point(), stack.loc + stack.size.to_point());
+    }
+
+    #[test]
+    fn one_app_takes_the_whole_zone() {
+        let layout = MasterStack::new();
+        let zone = zone(0, 30, 1920, 1050);
+        assert!(layout.tiles(0, zone).is_empty());
+        assert_eq!(layout.tiles(1, zone), [zone]);
+    }
+
+    #[test]
+    fn two_apps_share_the_zone_around_the_divider() {
+        let layout = MasterStack::new();
+        let zone = zone(0, 0, 1000, 600);
+        let (master, stack) = layout.split_zone(zone);
+        assert_eq!(master, Rectangle::from_loc_and_size((0, 0), (546, 600)));
+        assert_eq!(stack, Rectangle::from_loc_and_size((554, 0), (446, 600)));
+        assert_eq!(layout.tiles(2, zone), [master, stack]);
+    }
+
+    #[test]
+    fn many_apps_stack_next_to_the_master() {
+        let layout = MasterStack::new();
+
+        // Landscape: the master is on the left, the stack is cut in rows.
+        let landscape = zone(100, 0, 1000, 600);
+        let (master, stack) = layout.split_zone(landscape);
+        let tiles = layout.tiles(4, landscape);
+        assert_eq!(tiles.len(), 4);
+        assert_eq!(tiles[0], master);
+        assert!(tiles[1..].iter().all(|tile| tile.loc.x == stack.loc.x && tile.size.w == stack.size.w));
+        assert_stacked(&tiles[1..], stack, true);
+
+        // Portrait: the master is on top, the stack is cut in columns.
+        let portrait = zone(0, 50, 600, 1000);
+        let (master, stack) = layout.split_zone(portrait);
+        assert_eq!(master, Rectangle::from_loc_and_size((0, 50), (600, 546)));
+        let tiles = layout.tiles(3, portrait);
+        assert_eq!(tiles[0], master);
+        assert!(tiles[1..].iter().all(|tile| tile.loc.y == stack.loc.y && tile.size.h == stack.size.h));
+        assert_eq!(tiles[1].size.w, 296);
+        assert_stacked(&tiles[1..], stack, false);
+    }
+
+    #[test]
+    fn the_master_share_stays_in_bounds() {
+        let mut layout = MasterStack::new();
+        let mut space = Space::default();
+        for _ in 0..20 {
+            layout.resize_master(RATIO_STEP, &mut space);
+        }
+        assert_eq!(layout.ratio, 1.0 - MIN_RATIO);
+        let (master, stack) = layout.split_zone(zone(0, 0, 1008, 600));
+        assert_eq!((master.size.w, stack.size.w), (800, 200));
+
+        for _ in 0..20 {
+            layout.resize_master(-RATIO_STEP, &mut space);
+        }
+        assert_eq!(layout.ratio, MIN_RATIO);
+        let (master, stack) = layout.split_zone(zone(0, 0, 1008, 600));
+        assert_eq!((master.size.w, stack.size.w), (200, 800));
+    }
+
+    #[test]
+    fn tiles_of_tiny_zones_are_never_empty() {
+        let layout = MasterStack::new();
+        for zone in [zone(0, 0, 40, 30), zone(0, 0, 10, 10), zone(0, 0, 4, 4), zone(0, 0, 1, 1)] {
+            let (master, stack) = layout.split_zone(zone);
+            assert!(!master.is_empty() && !stack.is_empty(), "{:?}", zone);
+            for count in 1..8 {
+                let tiles = layout.tiles(count, zone);
+                assert_eq!(tiles.len(), count);
+                assert!(tiles.iter().all(|tile| !tile.is_empty()), "{} tiles in {:?}", count, zone);
+            }
+        }
+    }
+}
@@ -0,0 +1,653 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use smithay::{
+    desktop::Space,
+    output::Output,
+    utils::{IsAlive, Logical, Point},
+    wayland::shell::xdg::ToplevelSurface,
+};
+
+use crate::{
+    gestures::Gesture,
+    input_handler::KeyAction,
+    shell::{FullscreenSurface, WindowElement, WindowId},
+};
+
+use super::{FullscreenStack, SwitchDirection, WindowManager};
+
+/// Number of workspaces an output can have, one per `Logo+1..9` binding
+pub const MAX_WORKSPACES: usize = 9;
+/// How long switching workspaces slides for
+const SLIDE_DURATION: Duration = Duration::from_millis(250);
+
+/*
+The workspaces of one output. They are kept by output name, so an output that is
+plugged back in comes back on the workspace it showed.
+*/
+#[derive(Debug)]
+struct OutputWorkspaces {
+    name: String,
+    /// `None` while the output is disconnected
+    output: Option<Output>,
+    /// Numbered workspaces with their own stack of apps, there is always at least one
+    workspaces: Vec<FullscreenStack>,
+    /// Index of the workspace shown
+    active: usize,
+}
+
+impl OutputWorkspaces {
+    fn new(output: &Output, desktop: bool, space: &Space<WindowElement>) -> Self {
+        let mut workspace = FullscreenStack::new();
+        workspace.set_output(Some(output.clone()));
+        workspace.set_desktop(desktop, space);
+        Self {
+            name: output.name(),
+            output: Some(output.clone()),
+            workspaces: vec![workspace],
+            active: 0,
+        }
+    }
+
+    fn active(&self) -> &FullscreenStack {
+        &self.workspaces[self.active]
+    }
+
+    fn active_mut(&mut self) -> &mut FullscreenStack {
+        &mut self.workspaces[self.active]
+    }
+
+    /*
+    Returns the workspace with the given number, creating the missing ones up to it.
+    */
+    fn workspace_mut(&mut self, index: usize, space: &Space<WindowElement>) -> &mut FullscreenStack {
+        let desktop = self.active().desktop_mode();
+        while self.workspaces.len() <= index {
+            let mut workspace = FullscreenStack::new();
+            workspace.set_output(self.output.clone());
+            workspace.set_desktop(desktop, space);
+            self.workspaces.push(workspace);
+        }
+        &mut self.workspaces[index]
+    }
+}
+
+/*
+A workspace switch being animated: the apps of the workspace left slide out of the output
+while the ones of the new workspace slide in from the other side.
+*/
+#[derive(Debug)]
+struct Slide {
+    /// Index in `Workspaces::outputs`
+    output_index: usize,
+    output: Output,
+    /// Index of the workspace left
+    from: usize,
+    /// Mapped apps of both workspaces, with their resting location
+    outgoing: Vec<(WindowElement, Point<i32, Logical>)>,
+    incoming: Vec<(WindowElement, Point<i32, Logical>)>,
+    /// 1 when the new workspace comes in from the right, -1 from the left
+    direction: i32,
+    start: Instant,
+}
+
+/*
+The default window management policy: every output has numbered workspaces, each one
+a `FullscreenStack` with its own apps, split and picture-in-picture tile.
+
+`Logo+1..9` switch the output in use to a workspace, `Logo+Shift+1..9` move the active
+app there, and a four finger swipe goes to the workspace next to the shown one.
+
+The output in use is the one the pointer or the last touch is on, new apps open there.
+*/
+#[derive(Debug, Default)]
+pub struct Workspaces {
+    /// Every output seen so far, connected or not
+    outputs: Vec<OutputWorkspaces>,
+    /// Name of the output in use, new apps open on its active workspace
+    focused: Option<String>,
+    /// Apps opened before there was any output
+    pending: Vec<WindowElement>,
+    /// Every workspace is in desktop mode
+    desktop: bool,
+    /// Output name and workspace number of the apps moved away from an unplugged output,
+    /// they go back when it returns
+    homes: HashMap<WindowId, (String, usize)>,
+    slide: Option<Slide>,
+}
+
+impl Workspaces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+    Index of the output in use, or of the first connected one.
+    */
+    fn focused_index(&self) -> Option<usize> {
+        self.focused
+            .as_ref()
+            .and_then(|name| {
+                self.outputs
+                    .iter()
+                    .position(|entry| &entry.name == name && entry.output.is_some())
+            })
+            .or_else(|| self.outputs.iter().position(|entry| entry.output.is_some()))
+    }
+
+    fn index_of(&self, output: &Output) -> Option<usize> {
+        let name = output.name();
+        self.outputs.iter().position(|entry| entry.name == name)
+    }
+
+    /*
+    The output and workspace numbers of a window.
+    */
+    fn find(&self, id: WindowId) -> Option<(usize, usize)> {
+        self.outputs.iter().enumerate().find_map(|(index, entry)| {
+            entry
+                .workspaces
+                .iter()
+                .position(|workspace| workspace.window(id).is_some())
+                .map(|workspace| (index, workspace))
+        })
+    }
+
+    /*
+    The shown workspace of the connected output under the point.
+    */
+    fn active_under(&self, location: Point<f64, Logical>, space: &Space<WindowElement>) -> Option<usize> {
+        let output = space.output_under(location).next()?;
+        self.index_of(output)
+            .filter(|index| self.outputs[*index].output.is_some())
+    }
+
+    /*
+    Shows another workspace on an output, sliding it in.
+    */
+    fn switch_workspace(&mut self, index: usize, target: usize, space: &mut Space<WindowElement>) {
+        if target >= MAX_WORKSPACES {
+            return;
+        }
+        self.finish_slide(space);
+
+        let entry = &mut self.outputs[index];
+        self.focused = Some(entry.name.clone());
+        let Some(output) = entry.output.clone() else {
+            return;
+        };
+        if entry.active == target {
+            return;
+        }
+
+        let from = entry.active;
+        let outgoing = mapped_windows(entry.active(), space);
+        entry.workspace_mut(target, space);
+        entry.active = target;
+        entry.active_mut().refresh_geometry(space);
+        let incoming = mapped_windows(entry.active(), space);
+
+        self.slide = Some(Slide {
+            output_index: index,
+            output,
+            from,
+            outgoing,
+            incoming,
+            direction: if target > from { 1 } else { -1 },
+            start: Instant::now(),
+        });
+        self.animate(space);
+    }
+
+    /*
+    Ends the slide running, if any: the workspace left is hidden and the new one laid out.
+    */
+    fn finish_slide(&mut self, space: &mut Space<WindowElement>) {
+        let Some(slide) = self.slide.take() else {
+            return;
+        };
+        let entry = &mut self.outputs[slide.output_index];
+        if slide.from != entry.active {
+            entry.workspaces[slide.from].hide(space);
+        }
+        if entry.output.is_some() {
+            entry.active_mut().refresh_geometry(space);
+        }
+    }
+
+    /*
+    Moves the active app of the output in use to another of its workspaces, which stays hidden.
+    */
+    fn move_active_window(&mut self, target: usize, space: &mut Space<WindowElement>) {
+        let Some(index) = self.focused_index() else {
+            return;
+        };
+        self.finish_slide(space);
+
+        let entry = &mut self.outputs[index];
+        if target == entry.active || target >= MAX_WORKSPACES {
+            return;
+        }
+        let Some(id) = entry.active().get_active_window().map(|w| w.id()) else {
+            return;
+        };
+        let Some(window) = entry.active_mut().remove_window(id) else {
+            return;
+        };
+        space.unmap_elem(&window);
+        self.homes.remove(&id);
+        entry.workspace_mut(target, space).insert_window(window);
+        entry.active_mut().refresh_geometry(space);
+    }
+
+    /*
+    Moves every app of a disconnected output to another one, into the workspace with the
+    same number. They remember where they came from.
+    */
+    fn migrate(&mut self, from: usize, to: usize, space: &mut Space<WindowElement>) {
+        let moved: Vec<Vec<WindowElement>> = self.outputs[from]
+            .workspaces
+            .iter_mut()
+            .map(|workspace| workspace.take_windows())
+            .collect();
+        let name = self.outputs[from].name.clone();
+        let entry = &mut self.outputs[to];
+        for (number, windows) in moved.into_iter().enumerate() {
+            let workspace = entry.workspace_mut(number, space);
+            for window in windows {
+                space.unmap_elem(&window);
+                // An app that already had to move keeps its first home.
+                self.homes.entry(window.id()).or_insert_with(|| (name.clone(), number));
+                workspace.insert_window(window);
+            }
+        }
+    }
+
+    /*
+    Brings the apps that had to leave an output back to it, into the workspace they were on.
+    */
+    fn return_home(&mut self, index: usize, space: &mut Space<WindowElement>) {
+        let name = self.outputs[index].name.clone();
+        let returning: Vec<(WindowId, usize)> = self
+            .homes
+            .iter()
+            .filter(|(_, (home, _))| *home == name)
+            .map(|(id, (_, number))| (*id, *number))
+            .collect();
+
+        for (id, number) in returning {
+            self.homes.remove(&id);
+            let Some((from, workspace)) = self.find(id).filter(|(from, _)| *from != index) else {
+                continue;
+            };
+            let Some(window) = self.outputs[from].workspaces[workspace].remove_window(id) else {
+                continue;
+            };
+            space.unmap_elem(&window);
+            self.outputs[index].workspace_mut(number, space).insert_window(window);
+        }
+    }
+
+    /*
+    Goes to the workspace left or right of the shown one. Going right past the last
+    workspace opens an empty one, unless the last one is empty already.
+    */
+    fn switch_to_neighbour(&mut self, direction: SwitchDirection, space: &mut Space<WindowElement>) {
+        let Some(index) = self.focused_index() else {
+            return;
+        };
+        let entry = &self.outputs[index];
+        let target = match direction {
+            SwitchDirection::Next if entry.active + 1 < entry.workspaces.len() || !entry.active().is_empty() => {
+                entry.active + 1
+            }
+            SwitchDirection::Previous if entry.active > 0 => entry.active - 1,
+            _ => return,
+        };
+        self.switch_workspace(index, target, space);
+    }
+}
+
+fn mapped_windows(
+    workspace: &FullscreenStack,
+    space: &Space<WindowElement>,
+) -> Vec<(WindowElement, Point<i32, Logical>)> {
+    workspace
+        .windows()
+        .filter_map(|window| Some((window.clone(), space.element_location(window)?)))
+        .collect()
+}
+
+impl WindowManager for Workspaces {
+    fn windows(&self) -> Box<dyn Iterator<Item = &WindowElement> + '_> {
+        Box::new(
+            self.outputs
+                .iter()
+                .flat_map(|entry| entry.workspaces.iter())
+                .flat_map(|workspace| workspace.windows())
+                .chain(self.pending.iter()),
+        )
+    }
+
+    fn get_active_window(&self) -> Option<&WindowElement> {
+        let index = self.focused_index()?;
+        self.outputs[index].active().get_active_window()
+    }
+
+    fn window_output(&self, id: WindowId) -> Option<Output> {
+        let (index, _) = self.find(id)?;
+        self.outputs[index].output.clone()
+    }
+
+    fn mru(&self) -> Vec<WindowId> {
+        // Apps of the workspace in use come first.
+        let mut mru = self
+            .focused_index()
+            .map(|index| self.outputs[index].active().mru())
+            .unwrap_or_default();
+        for workspace in self.outputs.iter().flat_map(|entry| entry.workspaces.iter()) {
+            for id in workspace.mru() {
+                if !mru.contains(&id) {
+                    mru.push(id);
+                }
+            }
+        }
+        mru
+    }
+
+    fn focus_next(&mut self, space: &mut Space<WindowElement>) {
+        if let Some(index) = self.focused_index() {
+            self.outputs[index].active_mut().focus_next(space);
+        }
+    }
+
+    fn focus_prev(&mut self, space: &mut Space<WindowElement>) {
+        if let Some(index) = self.focused_index() {
+            self.outputs[index].active_mut().focus_prev(space);
+        }
+    }
+
+    fn activate(&mut self, id: WindowId, space: &mut Space<WindowElement>) -> bool {
+        // An app on another workspace brings its workspace along.
+        let Some((index, workspace)) = self.find(id) else {
+            return false;
+        };
+        if self.outputs[index].output.is_none() {
+            return false;
+        }
+        if self.outputs[index].active == workspace {
+            self.focused = Some(self.outputs[index].name.clone());
+            return self.outputs[index].active_mut().activate(id, space);
+        }
+
+        // The app goes on top of its workspace first, so the slide brings in the app asked for.
+        self.finish_slide(space);
+        let activated = self.outputs[index].workspaces[workspace].activate(id, space);
+        self.switch_workspace(index, workspace, space);
+        activated
+    }
+
+    fn activate_previous(&mut self, window: &WindowElement, space: &mut Space<WindowElement>) {
+        // Only a shown workspace has an active app, the others are left alone.
+        let Some((index, workspace)) = self.find(window.id()) else {
+            return;
+        };
+        if self.outputs[index].output.is_some() && self.outputs[index].active == workspace {
+            self.outputs[index].active_mut().activate_previous(window, space);
+        }
+    }
+
+    fn new_toplevel(&mut self, window: WindowElement, space: &mut Space<WindowElement>) {
+        match self.focused_index() {
+            Some(index) => self.outputs[index].active_mut().new_toplevel(window, space),
+            None => self.pending.push(window),
+        }
+    }
+
+    fn toplevel_destroyed(&mut self, surface: &ToplevelSurface, space: &mut Space<WindowElement>) {
+        self.pending.retain(|window| window.alive());
+        for entry in self.outputs.iter_mut() {
+            for workspace in entry.workspaces.iter_mut() {
+                workspace.remove_dead_window();
+            }
+            if entry.output.is_some() {
+                entry.active_mut().toplevel_destroyed(surface, space);
+            }
+        }
+        let outputs = &self.outputs;
+        self.homes.retain(|id, _| {
+            outputs
+                .iter()
+                .flat_map(|entry| entry.workspaces.iter())
+                .any(|workspace| workspace.window(*id).is_some())
+        });
+    }
+
+    fn toplevel_state_changed(&mut self, window: &WindowElement, space: &mut Space<WindowElement>) {
+        // Apps on hidden workspaces get their new state when the workspace is shown.
+        let Some((index, workspace)) = self.find(window.id()) else {
+            return;
+        };
+        let entry = &mut self.outputs[index];
+        if entry.output.is_some() && entry.active == workspace {
+            entry.active_mut().toplevel_state_changed(window, space);
+        }
+    }
+
+    fn output_added(&mut self, output: &Output, space: &mut Space<WindowElement>) {
+        let index = match self.index_of(output) {
+            Some(index) => {
+                let entry = &mut self.outputs[index];
+                entry.output = Some(output.clone());
+                for workspace in entry.workspaces.iter_mut() {
+                    workspace.set_output(Some(output.clone()));
+                }
+                index
+            }
+            None => {
+                self.outputs.push(OutputWorkspaces::new(output, self.desktop, space));
+                self.outputs.len() - 1
+            }
+        };
+        if self.focused.is_none() {
+            self.focused = Some(output.name());
+        }
+
+        let entry = &mut self.outputs[index];
+        for window in self.pending.drain(..) {
+            entry.active_mut().insert_window(window);
+        }
+        self.return_home(index, space);
+
+        // Apps left on outputs that were all unplugged come here, eg. when the laptop lid
+        // was closed before a monitor was plugged in.
+        let stranded: Vec<usize> = (0..self.outputs.len())
+            .filter(|other| self.outputs[*other].output.is_none())
+            .collect();
+        for other in stranded {
+            self.migrate(other, index, space);
+        }
+        self.refresh(space);
+    }
+
+    fn output_removed(&mut self, output: &Output, space: &mut Space<WindowElement>) {
+        self.finish_slide(space);
+        let Some(index) = self.index_of(output) else {
+            return;
+        };
+        self.outputs[index].output = None;
+
+        // The apps move to the first output left. The removed output keeps its active
+        // workspace for when it comes back.
+        let Some(target) = self.outputs.iter().position(|entry| entry.output.is_some()) else {
+            return;
+        };
+        self.migrate(index, target, space);
+        if self.focused.as_deref() == Some(output.name().as_str()) {
+            self.focused = Some(self.outputs[target].name.clone());
+        }
+        self.outputs[target].active_mut().refresh_geometry(space);
+    }
+
+    fn output_focused(&mut self, output: &Output) {
+        let name = output.name();
+        if self.focused.as_deref() != Some(name.as_str()) && self.index_of(output).is_some() {
+            self.focused = Some(name);
+        }
+    }
+
+    fn move_to_output(&mut self, window: &WindowElement, output: &Output, space: &mut Space<WindowElement>) {
+        let id = window.id();
+        let (Some((from, workspace)), Some(target)) = (self.find(id), self.index_of(output)) else {
+            return;
+        };
+        if from == target || self.outputs[target].output.is_none() {
+            return;
+        }
+        self.finish_slide(space);
+
+        let Some(window) = self.outputs[from].workspaces[workspace].remove_window(id) else {
+            return;
+        };
+        space.unmap_elem(&window);
+        self.homes.remove(&id);
+        self.outputs[target].active_mut().insert_window(window);
+        self.focused = Some(self.outputs[target].name.clone());
+        self.refresh(space);
+    }
+
+    fn move_to_workspace(&mut self, window: &WindowElement, workspace: usize, space: &mut Space<WindowElement>) {
+        let id = window.id();
+        let Some((index, from)) = self.find(id) else {
+            return;
+        };
+        if from == workspace || workspace >= MAX_WORKSPACES {
+            return;
+        }
+        self.finish_slide(space);
+
+        let Some(window) = self.outputs[index].workspaces[from].remove_window(id) else {
+            return;
+        };
+        space.unmap_elem(&window);
+        self.homes.remove(&id);
+        self.outputs[index].workspace_mut(workspace, space).insert_window(window);
+        self.refresh(space);
+    }
+
+    fn output_resized(&mut self, output: &Output, space: &mut Space<WindowElement>) {
+        self.finish_slide(space);
+        if let Some(index) = self.index_of(output) {
+            self.outputs[index].active_mut().refresh_geometry(space);
+        }
+    }
+
+    fn gesture(&mut self, gesture: Gesture, space: &mut Space<WindowElement>) {
+        match gesture {
+            Gesture::SwitchWorkspace(direction) => self.switch_to_neighbour(direction, space),
+            gesture => {
+                if let Some(index) = self.focused_index() {
+                    self.outputs[index].active_mut().gesture(gesture, space);
+                }
+            }
+        }
+    }
+
+    fn key_action(&mut self, action: &KeyAction, space: &mut Space<WindowElement>) -> bool {
+        match action {
+            KeyAction::Screen(number) => {
+                if let Some(index) = self.focused_index() {
+                    self.switch_workspace(index, *number, space);
+                }
+                true
+            }
+            KeyAction::MoveToScreen(number) => {
+                self.move_active_window(*number, space);
+                true
+            }
+            KeyAction::ToggleDesktopMode => {
+                self.set_desktop_mode(!self.desktop, space);
+                true
+            }
+            action => match self.focused_index() {
+                Some(index) => self.outputs[index].active_mut().key_action(action, space),
+                None => false,
+            },
+        }
+    }
+
+    fn divider_under(&self, location: Point<f64, Logical>, space: &Space<WindowElement>) -> bool {
+        self.active_under(location, space)
+            .map(|index| self.outputs[index].active().divider_under(location, space))
+            .unwrap_or(false)
+    }
+
+    fn move_divider(&mut self, location: Point<f64, Logical>, space: &mut Space<WindowElement>) {
+        if let Some(index) = self.active_under(location, space) {
+            self.outputs[index].active_mut().move_divider(location, space);
+        }
+    }
+
+    fn pip_window(&self) -> Option<&WindowElement> {
+        let index = self.focused_index()?;
+        self.outputs[index].active().pip_window()
+    }
+
+    fn pip_moved(&mut self, space: &mut Space<WindowElement>) {
+        if let Some(index) = self.focused_index() {
+            self.outputs[index].active_mut().pip_moved(space);
+        }
+    }
+
+    /*
+    Moves the apps of both workspaces while a switch slides, with an ease out.
+    */
+    fn animate(&mut self, space: &mut Space<WindowElement>) {
+        let Some(slide) = self.slide.as_ref() else {
+            return;
+        };
+        let progress = slide.start.elapsed().as_secs_f64() / SLIDE_DURATION.as_secs_f64();
+        let width = space.output_geometry(&slide.output).map(|geometry| geometry.size.w);
+        let Some(width) = width.filter(|_| progress < 1.0) else {
+            self.finish_slide(space);
+            return;
+        };
+
+        let eased = 1.0 - (1.0 - progress).powi(3);
+        let offset = (eased * width as f64).round() as i32;
+        let outgoing_shift = Point::from((-slide.direction * offset, 0));
+        let incoming_shift = Point::from((slide.direction * (width - offset), 0));
+        for (window, location) in slide.outgoing.iter().filter(|(window, _)| window.alive()) {
+            space.map_element(window.clone(), *location + outgoing_shift, false);
+        }
+        for (window, location) in slide.incoming.iter().filter(|(window, _)| window.alive()) {
+            space.map_element(window.clone(), *location + incoming_shift, false);
+        }
+
+        // A fullscreen app would be drawn alone, without the other workspace next to it.
+        if let Some(fullscreen_surface) = slide.output.user_data().get::<FullscreenSurface>() {
+            fullscreen_surface.clear();
+        }
+    }
+
+    fn desktop_mode(&self) -> bool {
+        self.desktop
+    }
+
+    fn set_desktop_mode(&mut self, enabled: bool, space: &mut Space<WindowElement>) {
+        self.finish_slide(space);
+        self.desktop = enabled;
+        for workspace in self.outputs.iter_mut().flat_map(|entry| entry.workspaces.iter_mut()) {
+            workspace.set_desktop(enabled, space);
+        }
+        self.refresh(space);
+    }
+
+    fn refresh(&mut self, space: &mut Space<WindowElement>) {
+        for entry in self.outputs.iter_mut().filter(|entry| entry.output.is_some()) {
+            entry.active_mut().refresh_geometry(space);
+        }
+    }
+}
@@ -5,9 +5,10 @@ use smithay::{
         allocator::dmabuf::Dmabuf,
         egl::EGLDevice,
         renderer::{
-            damage::{Error as OutputDamageTrackerError, OutputDamageTracker}, gles::GlesRenderer, ImportDma, ImportEgl, ImportMemWl
+            damage::{Error as OutputDamageTrackerError, OutputDamageTracker}, gles::GlesRenderer, DebugFlags, ImportDma, ImportEgl, ImportMemWl, Renderer
         },
-        winit::{self, WinitEvent, WinitGraphicsBackend},
+        input::{AbsolutePositionEvent, ButtonState, InputEvent, MouseButton, PointerButtonEvent},
+        winit::{self, WinitEvent, WinitGraphicsBackend, WinitInput},
         SwapBuffersError,
     }, delegate_dmabuf, input::keyboard::LedState,
         output::{Mode, Output, PhysicalProperties, Subpixel}, reexports::{
@@ -15,7 +16,7 @@ use smithay::{
         wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
         wayland_server::{protocol::wl_surface, Display},
         winit::platform::pump_events::PumpStatus,
-    }, utils::Transform, wayland::{
+    }, utils::{Logical, Point, Transform}, wayland::{
         dmabuf::{
             DmabufFeedback, DmabufFeedbackBuilder, DmabufGlobal, DmabufHandler, DmabufState, ImportNotifier,
         },
@@ -24,15 +25,34 @@ use smithay::{
 };
 
 
-use crate::{renderer::{render_output, CustomRenderElements}, state::{take_presentation_feedback, AuroraState, Backend}};
+use crate::{cli::CliArgs, config::Config, renderer::{render_output, CustomRenderElements}, snapshot::update_background_snapshots, state::{take_presentation_feedback, AuroraState, Backend, InitError}};
 
 pub const OUTPUT_NAME: &str = "winit";
 
+/// Touch slot ids of the emulated fingers
+const FIRST_FINGER: Option<u32> = Some(0);
+const SECOND_FINGER: Option<u32> = Some(1);
+
 pub struct WinitData {
     backend: WinitGraphicsBackend<GlesRenderer>,
     damage_tracker: OutputDamageTracker,
     dmabuf_state: (DmabufState, DmabufGlobal, Option<DmabufFeedback>),
-    full_redraw: u8
+    full_redraw: u8,
+    debug_flags: DebugFlags,
+    touch_emulation: TouchEmulation,
+}
+
+/*
+State of the emulated touchscreen, which turns mouse drags into touch points.
+*/
+#[derive(Debug, Default)]
+struct TouchEmulation {
+    /// Last known cursor location in global coordinates
+    cursor: Point<f64, Logical>,
+    /// Whether the left button is held, ie. the first finger touches
+    pressed: bool,
+    /// Whether a second finger mirrors the first around the output center
+    second_finger: bool,
 }
 
 impl DmabufHandler for AuroraState<WinitData> {
@@ -65,11 +85,97 @@ impl Backend for WinitData {
     }
     fn early_import(&mut self, _surface: &wl_surface::WlSurface) {}
     fn update_led_state(&mut self, _led_state: LedState) {}
+    fn change_vt(&mut self, _vt: i32) {
+        tracing::warn!("VT switching is not supported by the winit backend");
+    }
+    fn debug_flags(&self) -> DebugFlags {
+        self.debug_flags
+    }
+    fn set_debug_flags(&mut self, flags: DebugFlags) {
+        self.debug_flags = flags;
+        self.backend.renderer().set_debug_flags(flags);
+    }
 }
 
 
 
-pub fn run_winit() {
+impl AuroraState<WinitData> {
+    /*
+    Turns left button drags into touch events, for developing touch flows without a touchscreen.
+    Holding Ctrl when pressing the button adds a second finger mirrored around the center of
+    the output, so moving the mouse pinches and rotates.
+    Every other event is handled as usual.
+    */
+    fn process_input_event_emulated_touch(&mut self, event: InputEvent<WinitInput>) {
+        let Some(output_geo) = self
+            .space
+            .outputs()
+            .find(|o| o.name() == OUTPUT_NAME)
+            .and_then(|o| self.space.output_geometry(o))
+        else {
+            return;
+        };
+        let output_geo_f64 = output_geo.to_f64();
+        let center = output_geo_f64.loc + output_geo_f64.size.downscale(2.0).to_point();
+        let mirrored = |location: Point<f64, Logical>| center + center - location;
+
+        match event {
+            InputEvent::PointerMotionAbsolute { event } => {
+                let location = event.position_transformed(output_geo.size) + output_geo.loc.to_f64();
+                let emulation = &mut self.backend_data.touch_emulation;
+                emulation.cursor = location;
+                if !emulation.pressed {
+                    return;
+                }
+
+                let second_finger = emulation.second_finger;
+                let time = event.time_msec();
+                self.on_touch_motion(FIRST_FINGER.into(), location, time);
+                if second_finger {
+                    self.on_touch_motion(SECOND_FINGER.into(), mirrored(location), time);
+                }
+                self.on_touch_frame();
+            }
+            InputEvent::PointerButton { event } if event.button() == Some(MouseButton::Left) => {
+                let time = event.time_msec();
+                let emulation = &mut self.backend_data.touch_emulation;
+                let location = emulation.cursor;
+
+                match event.state() {
+                    ButtonState::Pressed if !emulation.pressed => {
+                        let ctrl = self.seat.get_keyboard().unwrap().modifier_state().ctrl;
+                        let emulation = &mut self.backend_data.touch_emulation;
+                        emulation.pressed = true;
+                        emulation.second_finger = ctrl;
+
+                        self.on_touch_down(FIRST_FINGER.into(), location, time);
+                        if ctrl {
+                            self.on_touch_down(SECOND_FINGER.into(), mirrored(location), time);
+                        }
+                        self.on_touch_frame();
+                    }
+                    ButtonState::Released if emulation.pressed => {
+                        let second_finger = emulation.second_finger;
+                        emulation.pressed = false;
+                        emulation.second_finger = false;
+
+                        self.on_touch_up(FIRST_FINGER.into(), time);
+                        if second_finger {
+                            self.on_touch_up(SECOND_FINGER.into(), time);
+                        }
+                        self.on_touch_frame();
+                    }
+                    _ => {}
+                }
+            }
+            // The cursor only drives the emulated fingers.
+            InputEvent::PointerButton { .. } | InputEvent::PointerAxis { .. } => {}
+            event => self.process_input_event_windowed(event, OUTPUT_NAME),
+        }
+    }
+}
+
+pub fn run_winit(args: &CliArgs, config: Config) -> Result<(), InitError> {
     tracing::info!("Running with winit backend");
     tracing::warn!("Only for debuging and development porpose");
     // Initialization
@@ -83,16 +189,15 @@ pub fn run_winit() {
         Ok(ret) => ret,
         Err(err) => {
             tracing::error!("Failed to initialize Winit backend: {}", err);
-            return;
+            return Ok(());
         }
     };
 
     // Output setup
+    let output_config = config.output(OUTPUT_NAME).cloned().unwrap_or_default();
+    let refresh = output_config.refresh_mhz().unwrap_or(60_000);
     let size = backend.window_size();
-    let mode = Mode {
-        size,
-        refresh: 60_000,
-    };
+    let mode = Mode { size, refresh };
     // output represents a Wayland output (eg: a monitor)
     let output = Output::new(
         OUTPUT_NAME.to_string(),
@@ -107,6 +212,7 @@ pub fn run_winit() {
     // Output configuration
     let _global = output.create_global::<AuroraState<WinitData>>(&display.handle());
     output.change_current_state(Some(mode), Some(Transform::Flipped180), None, Some((0, 0).into()));
+    output_config.apply(&output);
     output.set_preferred(mode);
 
 
@@ -165,17 +271,24 @@ pub fn run_winit() {
             damage_tracker,
             dmabuf_state,
             full_redraw: 0,
+            debug_flags: DebugFlags::empty(),
+            touch_emulation: TouchEmulation::default(),
         }
     };
 
     // Aurora state object managin compositor-specific data.(eg.surfaces, inputs).
-    let mut state = AuroraState::init(display, event_loop.handle(), data, true);
+    let listening_socket = args.listening_socket(&config);
+    let mut state = AuroraState::init(display, event_loop.handle(), data, listening_socket, config)?;
     
 
     state
         .shm_state
         .update_formats(state.backend_data.backend.renderer().shm_formats());
-    state.space.map_output(&output, (0, 0));
+    state.space.map_output(&output, output_config.position().unwrap_or_default());
+    state.window_manager.output_added(&output, &mut state.space);
+
+    // The socket is ready, launch the requested client.
+    state.spawn(&args.command);
 
     tracing::info!("Initialization completed, starting the main loop.");
 
@@ -187,18 +300,20 @@ pub fn run_winit() {
             // Updates output mode & repositions content when window is resized
             WinitEvent::Resized  { size, .. }  => {
                 let output = state.space.outputs().next().unwrap().clone();
-                state.space.map_output(&output, (0, 0));
-
-                let mode = Mode {
-                    size,
-                    refresh: 60_000,
-                };
+                let mode = Mode { size, refresh };
                 output.change_current_state(Some(mode), None, None, None);
                 output.set_preferred(mode);
-                crate::shell::fixup_positions(&mut state.space, &mut state.window_manager, state.pointer.current_location());
+                crate::shell::fixup_positions(&mut state.space, &state.config, state.pointer.current_location());
+                state.window_manager.output_resized(&output, &mut state.space);
             }
 
-            WinitEvent::Input(event) => state.process_input_event_windowed(event, OUTPUT_NAME),
+            WinitEvent::Input(event) => {
+                if state.config.input.touch_emulation {
+                    state.process_input_event_emulated_touch(event);
+                } else {
+                    state.process_input_event_windowed(event, OUTPUT_NAME);
+                }
+            }
             
             _ => (),
         });
@@ -225,7 +340,8 @@ pub fn run_winit() {
             
             let space = &mut state.space;
             let damage_tracker = &mut state.backend_data.damage_tracker;
-            let show_window_preview = state.show_window_preview;
+            let overview = state.overview.as_ref();
+            let window_manager = &*state.window_manager;
 
 
             // Binds the rendering backend to start a new frame. This prepares the rendering 
@@ -241,6 +357,7 @@ pub fn run_winit() {
                 };
 
                 let renderer = backend.renderer();
+                update_background_snapshots(renderer, space, window_manager, &output);
                 
                 // Creating a list of render elements.
                 let elements: Vec<CustomRenderElements<GlesRenderer>> = Vec::<CustomRenderElements<GlesRenderer>>::new();
@@ -253,7 +370,7 @@ pub fn run_winit() {
                     renderer,
                     damage_tracker,
                     age,
-                    show_window_preview,
+                    overview,
                 )
                 .map_err(|err| match err {
                     OutputDamageTrackerError::Rendering(err) => err.into(),
@@ -306,7 +423,10 @@ pub fn run_winit() {
         } else {
             state.space.refresh();
             state.popups.cleanup();
+            state.update_background_states();
             display_handle.flush_clients().unwrap();
         }
     }
+
+    Ok(())
 }
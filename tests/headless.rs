@@ -0,0 +1,396 @@
+#![cfg(feature = "headless")]
+
+use std::{
+    fs::File,
+    io::Write,
+    os::{fd::AsFd, unix::net::UnixStream},
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use aurora::{
+    config::{Config, LayoutConfig, WindowConfig},
+    headless::{HeadlessData, OUTPUT_NAME},
+    shell::WindowElement,
+    state::{AuroraState, ListeningSocket},
+    ClientState,
+};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{Bind, ExportMem, ImportMemWl},
+    },
+    reexports::{calloop::EventLoop, wayland_server::Display},
+    utils::Rectangle,
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+use wayland_client::{
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_buffer, wl_callback, wl_compositor, wl_registry, wl_shm, wl_shm_pool, wl_surface},
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols::xdg::shell::client::{xdg_surface, xdg_toplevel, xdg_wm_base};
+
+/// How long the compositor is driven before a test gives up waiting for its client
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Size of the virtual output of the tests
+const OUTPUT_SIZE: (i32, i32) = (1920, 1080);
+/// Width and height of the buffer the drawing client attaches
+const BUFFER_SIZE: i32 = 100;
+/// Opaque red, in the byte order of `Argb8888` in memory
+const RED: [u8; 4] = [0, 0, 255, 255];
+
+/*
+A wayland client opening toplevels. It runs on its own thread, so its blocking
+roundtrips are answered by the compositor dispatched on the test thread.
+*/
+#[derive(Default)]
+struct TestClient {
+    /// Frame callbacks the compositor sent so far
+    frames: usize,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for TestClient {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for TestClient {
+    fn event(
+        _: &mut Self,
+        wm_base: &xdg_wm_base::XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<xdg_surface::XdgSurface, ()> for TestClient {
+    fn event(
+        _: &mut Self,
+        xdg_surface: &xdg_surface::XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            xdg_surface.ack_configure(serial);
+        }
+    }
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for TestClient {
+    fn event(
+        client: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            client.frames += 1;
+        }
+    }
+}
+
+wayland_client::delegate_noop!(TestClient: ignore wl_compositor::WlCompositor);
+wayland_client::delegate_noop!(TestClient: ignore wl_shm::WlShm);
+wayland_client::delegate_noop!(TestClient: ignore wl_shm_pool::WlShmPool);
+wayland_client::delegate_noop!(TestClient: ignore wl_buffer::WlBuffer);
+wayland_client::delegate_noop!(TestClient: ignore wl_surface::WlSurface);
+wayland_client::delegate_noop!(TestClient: ignore xdg_toplevel::XdgToplevel);
+
+/*
+Opens one toplevel per app id, tells the test once they are configured and keeps them
+open until the test is done.
+*/
+fn run_client(stream: UnixStream, app_ids: &[&str], ready: mpsc::Sender<()>, done: mpsc::Receiver<()>) {
+    let connection = Connection::from_socket(stream).unwrap();
+    let (globals, mut queue) = registry_queue_init::<TestClient>(&connection).unwrap();
+    let qh = queue.handle();
+    let compositor: wl_compositor::WlCompositor = globals.bind(&qh, 1..=5, ()).unwrap();
+    let wm_base: xdg_wm_base::XdgWmBase = globals.bind(&qh, 1..=5, ()).unwrap();
+    let mut client = TestClient::default();
+
+    let mut windows = Vec::new();
+    for app_id in app_ids {
+        let surface = compositor.create_surface(&qh, ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &qh, ());
+        let toplevel = xdg_surface.get_toplevel(&qh, ());
+        toplevel.set_app_id(app_id.to_string());
+        surface.commit();
+        windows.push((surface, xdg_surface, toplevel));
+    }
+    queue.roundtrip(&mut client).unwrap();
+    queue.roundtrip(&mut client).unwrap();
+
+    ready.send(()).unwrap();
+    let _ = done.recv();
+}
+
+/*
+Opens a toplevel and, once it is configured, draws it with a red shm buffer and asks for a
+frame callback. Tells the test when the buffer is committed, and when the frame is done.
+*/
+fn run_drawing_client(stream: UnixStream, committed: mpsc::Sender<()>, presented: mpsc::Sender<()>) {
+    let connection = Connection::from_socket(stream).unwrap();
+    let (globals, mut queue) = registry_queue_init::<TestClient>(&connection).unwrap();
+    let qh = queue.handle();
+    let compositor: wl_compositor::WlCompositor = globals.bind(&qh, 1..=5, ()).unwrap();
+    let wm_base: xdg_wm_base::XdgWmBase = globals.bind(&qh, 1..=5, ()).unwrap();
+    let shm: wl_shm::WlShm = globals.bind(&qh, 1..=1, ()).unwrap();
+    let mut client = TestClient::default();
+
+    let surface = compositor.create_surface(&qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &qh, ());
+    let toplevel = xdg_surface.get_toplevel(&qh, ());
+    toplevel.set_app_id("painter".into());
+    surface.commit();
+    queue.roundtrip(&mut client).unwrap();
+
+    // The pool keeps the file, it is unlinked right away.
+    let path = std::env::temp_dir().join(format!("aurora-headless-{}-painter", std::process::id()));
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let pixels: Vec<u8> = RED
+        .iter()
+        .copied()
+        .cycle()
+        .take((BUFFER_SIZE * BUFFER_SIZE * 4) as usize)
+        .collect();
+    file.write_all(&pixels).unwrap();
+    let pool = shm.create_pool(file.as_fd(), pixels.len() as i32, &qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        BUFFER_SIZE * 4,
+        wl_shm::Format::Argb8888,
+        &qh,
+        (),
+    );
+
+    surface.attach(Some(&buffer), 0, 0);
+    surface.damage_buffer(0, 0, BUFFER_SIZE, BUFFER_SIZE);
+    surface.frame(&qh, ());
+    surface.commit();
+    queue.roundtrip(&mut client).unwrap();
+    committed.send(()).unwrap();
+
+    while client.frames == 0 {
+        queue.blocking_dispatch(&mut client).unwrap();
+    }
+    presented.send(()).unwrap();
+}
+
+/*
+A compositor with a single virtual output of `OUTPUT_SIZE`, driven from the test thread.
+*/
+fn start_compositor(config: Config) -> (EventLoop<'static, AuroraState<HeadlessData>>, AuroraState<HeadlessData>) {
+    let event_loop = EventLoop::try_new().unwrap();
+    let display = Display::new().unwrap();
+    let data = HeadlessData::new().unwrap();
+    let mut state = AuroraState::init(display, event_loop.handle(), data, ListeningSocket::None, config).unwrap();
+    let formats = state.backend_data.renderer().shm_formats().collect::<Vec<_>>();
+    state.shm_state.update_formats(formats);
+    state.add_virtual_output(OUTPUT_NAME, OUTPUT_SIZE.into(), 60_000).unwrap();
+    (event_loop, state)
+}
+
+/*
+A test client connected to the compositor, its apps stay open until it is stopped.
+*/
+struct RunningClient {
+    done: mpsc::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl RunningClient {
+    fn connect(
+        event_loop: &mut EventLoop<'static, AuroraState<HeadlessData>>,
+        state: &mut AuroraState<HeadlessData>,
+        app_ids: &'static [&'static str],
+    ) -> Self {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        state
+            .display_handle
+            .insert_client(server_stream, Arc::new(ClientState::default()))
+            .unwrap();
+        let (ready_sender, ready) = mpsc::channel();
+        let (done, done_receiver) = mpsc::channel();
+        let thread = thread::spawn(move || run_client(client_stream, app_ids, ready_sender, done_receiver));
+
+        dispatch_until(event_loop, state, || ready.try_recv().is_ok());
+        RunningClient { done, thread }
+    }
+
+    fn stop(self) {
+        self.done.send(()).unwrap();
+        self.thread.join().unwrap();
+    }
+}
+
+/*
+The window of the app with the given app id.
+*/
+fn window(state: &AuroraState<HeadlessData>, app_id: &str) -> WindowElement {
+    state
+        .window_manager
+        .windows()
+        .find(|window| {
+            let toplevel = window.0.toplevel().unwrap();
+            with_states(toplevel.wl_surface(), |states| {
+                let data = states.data_map.get::<XdgToplevelSurfaceData>().unwrap().lock().unwrap();
+                data.app_id.as_deref() == Some(app_id)
+            })
+        })
+        .cloned()
+        .unwrap_or_else(|| panic!("no window for {}", app_id))
+}
+
+/*
+Dispatches the compositor until `condition` holds.
+*/
+fn dispatch_until(
+    event_loop: &mut EventLoop<'static, AuroraState<HeadlessData>>,
+    state: &mut AuroraState<HeadlessData>,
+    mut condition: impl FnMut() -> bool,
+) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < TIMEOUT, "timed out waiting for the client");
+        event_loop.dispatch(Some(Duration::from_millis(5)), state).unwrap();
+        state.space.refresh();
+        state.display_handle.flush_clients().unwrap();
+    }
+}
+
+#[test]
+fn committed_buffers_are_rendered_offscreen() {
+    let (mut event_loop, mut state) = start_compositor(Config::default());
+    let output = state.space.outputs().next().cloned().unwrap();
+
+    let (server_stream, client_stream) = UnixStream::pair().unwrap();
+    state
+        .display_handle
+        .insert_client(server_stream, Arc::new(ClientState::default()))
+        .unwrap();
+    let (committed_sender, committed) = mpsc::channel();
+    let (presented_sender, presented) = mpsc::channel();
+    let client = thread::spawn(move || run_drawing_client(client_stream, committed_sender, presented_sender));
+    dispatch_until(&mut event_loop, &mut state, || committed.try_recv().is_ok());
+
+    // One pre_repaint, render and post_repaint cycle, like the repaint timer of the backend.
+    state.render_headless();
+    dispatch_until(&mut event_loop, &mut state, || presented.try_recv().is_ok());
+    client.join().unwrap();
+
+    let buffer = state.backend_data.output_buffer(&output).cloned().unwrap();
+    let renderer = state.backend_data.renderer();
+    renderer.bind(buffer).unwrap();
+    let region = Rectangle::from_loc_and_size((0, 0), OUTPUT_SIZE);
+    let mapping = renderer.copy_framebuffer(region, Fourcc::Argb8888).unwrap();
+    let pixels = renderer.map_texture(&mapping).unwrap();
+    let pixel = |x: i32, y: i32| {
+        let offset = ((y * OUTPUT_SIZE.0 + x) * 4) as usize;
+        &pixels[offset..offset + 4]
+    };
+
+    // The app is maximized in the top left corner, with its buffer drawn at its size.
+    assert_eq!(pixel(0, 0), RED);
+    assert_eq!(pixel(BUFFER_SIZE - 1, BUFFER_SIZE - 1), RED);
+    // Everything else is the opaque black clear color.
+    assert_eq!(pixel(BUFFER_SIZE, BUFFER_SIZE), [0, 0, 0, 255]);
+    assert_eq!(pixel(OUTPUT_SIZE.0 - 1, OUTPUT_SIZE.1 - 1), [0, 0, 0, 255]);
+}
+
+#[test]
+fn switching_the_layout_keeps_the_apps_shown() {
+    let config = Config {
+        window: WindowConfig {
+            layout: LayoutConfig::Tiling,
+            ..WindowConfig::default()
+        },
+        ..Config::default()
+    };
+    let (mut event_loop, mut state) = start_compositor(config.clone());
+    let client = RunningClient::connect(&mut event_loop, &mut state, &["one", "two"]);
+
+    assert_eq!(state.window_manager.windows().count(), 2);
+    for window in state.window_manager.windows() {
+        assert!(state.space.element_location(window).is_some(), "every app is tiled");
+    }
+
+    // Back to the default fullscreen stack, as if the config file was edited.
+    state.apply_config(Config {
+        window: WindowConfig {
+            layout: LayoutConfig::Stack,
+            ..config.window.clone()
+        },
+        ..config
+    });
+    assert_eq!(state.window_manager.windows().count(), 2);
+    let active = state
+        .window_manager
+        .get_active_window()
+        .cloned()
+        .expect("an app is active after the switch");
+    assert!(state.space.element_location(&active).is_some(), "the active app is shown");
+    assert!(state.window_manager.windows().all(|window| !window.is_pip()));
+
+    client.stop();
+}
+
+#[test]
+fn activating_an_app_on_a_hidden_workspace_slides_it_in() {
+    let (mut event_loop, mut state) = start_compositor(Config::default());
+    let client = RunningClient::connect(&mut event_loop, &mut state, &["one", "two", "three"]);
+    let (one, two, three) = (window(&state, "one"), window(&state, "two"), window(&state, "three"));
+
+    // The second workspace gets "two" and "three", with "three" on top.
+    state.window_manager.move_to_workspace(&two, 1, &mut state.space);
+    state.window_manager.move_to_workspace(&three, 1, &mut state.space);
+    assert_eq!(state.space.element_location(&one).map(|location| location.x), Some(0));
+    assert!(state.space.element_location(&two).is_none());
+    assert!(state.space.element_location(&three).is_none());
+
+    assert!(state.window_manager.activate(two.id(), &mut state.space));
+    assert_eq!(state.window_manager.get_active_window(), Some(&two));
+
+    // While the workspace slides in from the right, the app asked for is the one coming in.
+    for _ in 0..2 {
+        let location = state.space.element_location(&two).expect("the app slides in");
+        assert!(location.x > 0, "the app is still outside of the output");
+        assert!(state.space.element_location(&three).is_none(), "the old top app stays hidden");
+        state.window_manager.animate(&mut state.space);
+    }
+
+    thread::sleep(Duration::from_millis(300));
+    state.window_manager.animate(&mut state.space);
+    assert_eq!(state.space.element_location(&two).map(|location| location.x), Some(0));
+    assert!(state.space.element_location(&one).is_none());
+    assert!(state.space.element_location(&three).is_none());
+
+    client.stop();
+}